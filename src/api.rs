@@ -0,0 +1,901 @@
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, HttpResponseBuilder, ResponseError};
+use leptos::ServerFnError;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi, ToSchema};
+use uuid::Uuid;
+use crate::app::{Assignment, ShareBreakdown, TreatMode, UnassignedPolicy};
+use crate::bill::{Bill, BillError, BillItem, BillParticipant};
+use crate::ops::BillOp;
+use crate::realtime::BillRooms;
+use crate::split::{compute, SplitResult};
+use crate::storage::{db, Access, BillLinks};
+
+/// An error as the JSON API reports it:
+/// `{"error": {"code": "...", "message": "...", "field": "..."}}`.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct ApiError {
+    #[serde(skip)]
+    status: StatusCode,
+    #[schema(value_type = String, example = "invalid_bill")]
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: ApiError,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        ApiError { status, code, message: message.into(), field: None }
+    }
+
+    pub fn invalid(field: impl Into<String>, message: impl Into<String>) -> Self {
+        ApiError {
+            field: Some(field.into()),
+            ..ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "invalid_bill", message)
+        }
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).json(ErrorResponse { error: self.clone() })
+    }
+}
+
+impl From<ServerFnError> for ApiError {
+    fn from(e: ServerFnError) -> Self {
+        ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "server_error", e.to_string())
+    }
+}
+
+impl From<BillError> for ApiError {
+    fn from(e: BillError) -> Self {
+        ApiError::invalid(e.field, e.message)
+    }
+}
+
+/// Malformed JSON comes back in the same shape as every other error.
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default().error_handler(|err, _| {
+        ApiError::new(StatusCode::BAD_REQUEST, "invalid_json", err.to_string()).into()
+    })
+}
+
+fn default_weight() -> Decimal {
+    Decimal::ONE
+}
+
+#[derive(Clone, Debug, Deserialize, ToSchema)]
+pub struct SplitParticipantInput {
+    pub name: String,
+    #[serde(default)]
+    pub payer: bool,
+    #[serde(default)]
+    pub settled: bool,
+    #[serde(default = "default_weight")]
+    #[schema(default = "1")]
+    pub weight: Decimal,
+}
+
+/// An item is shared by everyone unless `shared_by` names who had it.
+/// `except` leaves people out of an item everyone else shared.
+#[derive(Clone, Debug, Deserialize, ToSchema)]
+pub struct SplitItemInput {
+    pub name: String,
+    pub price: Decimal,
+    #[serde(default)]
+    pub shared_by: Option<Vec<String>>,
+    #[serde(default)]
+    pub except: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, ToSchema)]
+pub struct DiscountInput {
+    #[serde(default)]
+    pub name: String,
+    pub amount: Decimal,
+}
+
+/// The body of `POST /api/v1/split`. Participants are referred to by name.
+#[derive(Clone, Debug, Deserialize, ToSchema)]
+pub struct SplitRequest {
+    #[serde(default)]
+    pub event_name: String,
+    pub participants: Vec<SplitParticipantInput>,
+    pub items: Vec<SplitItemInput>,
+    #[serde(default)]
+    pub tax: Decimal,
+    #[serde(default)]
+    pub tip: Decimal,
+    #[serde(default)]
+    pub discounts: Vec<DiscountInput>,
+    /// `Block` unless set. The page's `block`, `everyone` and `payer` are
+    /// read too.
+    #[serde(default)]
+    pub unassigned_policy: UnassignedPolicy,
+}
+
+impl SplitRequest {
+    pub fn to_bill(&self) -> Result<Bill, ApiError> {
+        let mut bill = Bill::empty(Uuid::new_v4());
+        bill.event_name = self.event_name.clone();
+        bill.total_tax = self.tax;
+        bill.tip = self.tip;
+        bill.unassigned_policy = self.unassigned_policy;
+        for (i, discount) in self.discounts.iter().enumerate() {
+            if discount.amount < Decimal::ZERO {
+                return Err(ApiError::invalid(format!("discounts[{}].amount", i), "can't be negative"));
+            }
+            bill.discount += discount.amount;
+        }
+
+        for (i, p) in self.participants.iter().enumerate() {
+            if bill.participants.iter().any(|e| e.name.trim().eq_ignore_ascii_case(p.name.trim())) {
+                return Err(ApiError::invalid(
+                    format!("participants[{}].name", i),
+                    format!("'{}' is used twice, names have to be unique", p.name),
+                ));
+            }
+            bill.participants.push(BillParticipant {
+                id: Uuid::new_v4(),
+                name: p.name.trim().to_string(),
+                payer: p.payer,
+                settled: p.settled,
+                weight: p.weight,
+            });
+        }
+
+        let find = |field: String, name: &str| {
+            bill.participants
+                .iter()
+                .find(|p| p.name.eq_ignore_ascii_case(name.trim()))
+                .map(|p| p.id)
+                .ok_or_else(|| ApiError::invalid(field, format!("'{}' isn't one of the participants", name)))
+        };
+        let mut items = Vec::new();
+        for (i, item) in self.items.iter().enumerate() {
+            let except = item
+                .except
+                .iter()
+                .map(|name| find(format!("items[{}].except", i), name))
+                .collect::<Result<Vec<_>, _>>()?;
+            let (assignment, participants) = match &item.shared_by {
+                Some(names) => (
+                    Assignment::Selected,
+                    names
+                        .iter()
+                        .map(|name| find(format!("items[{}].shared_by", i), name))
+                        .collect::<Result<Vec<_>, _>>()?,
+                ),
+                None if except.is_empty() => (Assignment::Everyone, Vec::new()),
+                None => (Assignment::EveryoneExcept(except), Vec::new()),
+            };
+            items.push(BillItem {
+                id: Uuid::new_v4(),
+                name: item.name.clone(),
+                price: item.price,
+                quantity: 1,
+                category: String::new(),
+                assignment,
+                participants,
+            });
+        }
+        bill.items = items;
+
+        bill.validate()?;
+        Ok(bill)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct ShareResponse {
+    pub name: String,
+    pub share: Decimal,
+    pub breakdown: ShareBreakdown,
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct TransferResponse {
+    pub from: String,
+    pub to: String,
+    pub amount: Decimal,
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct SplitResponse {
+    pub total: Decimal,
+    pub shares: Vec<ShareResponse>,
+    /// Who pays whom back. Payers are taken to have paid equal parts of
+    /// the total.
+    pub settlement: Vec<TransferResponse>,
+}
+
+impl SplitResponse {
+    pub fn new(bill: &Bill, result: SplitResult) -> Self {
+        let name = |id: Uuid| {
+            bill.participants
+                .iter()
+                .find(|p| p.id == id)
+                .map(|p| p.name.clone())
+                .unwrap_or_default()
+        };
+        SplitResponse {
+            total: result.total,
+            shares: result
+                .shares
+                .into_iter()
+                .map(|s| ShareResponse { name: s.name, share: s.share, breakdown: s.breakdown })
+                .collect(),
+            settlement: result
+                .settlement
+                .into_iter()
+                .map(|t| TransferResponse { from: name(t.from), to: name(t.to), amount: t.amount })
+                .collect(),
+        }
+    }
+}
+
+/// `POST /api/v1/split`: splits a bill without storing anything.
+#[utoipa::path(
+    post,
+    path = "/api/v1/split",
+    request_body = SplitRequest,
+    responses(
+        (status = 200, description = "The split and settlement plan", body = SplitResponse),
+        (status = 400, description = "Malformed JSON", body = ErrorResponse),
+        (status = 422, description = "The bill doesn't validate", body = ErrorResponse),
+    ),
+)]
+pub async fn split(body: web::Json<SplitRequest>) -> Result<HttpResponse, ApiError> {
+    let bill = body.to_bill()?;
+    let result = compute(&bill);
+    if result.blocked {
+        return Err(ApiError::invalid(
+            "items",
+            "some items aren't shared by anyone, set shared_by or pick another unassigned_policy",
+        ));
+    }
+    Ok(HttpResponse::Ok().json(SplitResponse::new(&bill, result)))
+}
+
+/// Who edits made through the API are credited to in the bill's history.
+fn author(req: &HttpRequest) -> String {
+    req.headers()
+        .get("X-Author")
+        .and_then(|v| v.to_str().ok())
+        .map(String::from)
+        .unwrap_or_else(|| String::from("API"))
+}
+
+/// Runs database work on the blocking thread pool, so a slow query doesn't
+/// hold up the other requests on the same worker.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T, ApiError> + Send + 'static) -> Result<T, ApiError> {
+    web::block(f).await.map_err(db::server_error)?
+}
+
+/// Checks the share token sent as `Authorization: Bearer <token>`.
+async fn authorize(req: &HttpRequest, id: Uuid, needed: Access) -> Result<Access, ApiError> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "missing_token",
+                "send one of the bill's share tokens as `Authorization: Bearer <token>`",
+            )
+        })?;
+    let token = token.trim().to_string();
+    blocking(move || {
+        db::require_access(id, &token, needed).map_err(|_| {
+            ApiError::new(StatusCode::FORBIDDEN, "forbidden", "this token is invalid, revoked or read-only")
+        })
+    })
+    .await
+}
+
+/// Changes to anything in the bill change the ETag, including finalizing it.
+pub fn etag(bill: &Bill) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(bill.canonical_json().as_bytes());
+    hasher.update(bill.finalized.as_deref().unwrap_or_default().as_bytes());
+    format!("\"{:x}\"", hasher.finalize())
+}
+
+fn with_etag(mut response: HttpResponseBuilder, bill: &Bill) -> HttpResponseBuilder {
+    if let Ok(value) = HeaderValue::from_str(&etag(bill)) {
+        response.insert_header((header::ETAG, value));
+    }
+    response
+}
+
+/// Every change has to say which version of the bill it was based on.
+fn if_match(req: &HttpRequest) -> Result<String, ApiError> {
+    req.headers()
+        .get(header::IF_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_string())
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::PRECONDITION_REQUIRED,
+                "etag_required",
+                "send the ETag you last saw as `If-Match`",
+            )
+        })
+}
+
+fn check_etag(expected: &str, bill: &Bill) -> Result<(), ApiError> {
+    if expected != "*" && expected != etag(bill) {
+        return Err(ApiError::new(
+            StatusCode::PRECONDITION_FAILED,
+            "etag_mismatch",
+            "the bill changed since you fetched it, fetch it again and retry",
+        ));
+    }
+    Ok(())
+}
+
+fn ensure_unlocked(bill: &Bill) -> Result<(), ApiError> {
+    if bill.finalized.is_some() {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "bill_finalized",
+            "the bill is finalized, unlock it before changing it",
+        ));
+    }
+    Ok(())
+}
+
+/// Runs `f` on the stored bill if it still has the ETag from `If-Match`,
+/// and stores the result if it passes the same checks as any other bill.
+/// Anyone with the bill open sees the change live.
+async fn edit<T: Send + 'static>(
+    req: &HttpRequest,
+    id: Uuid,
+    f: impl FnOnce(&mut Bill) -> Result<T, ApiError> + Send + 'static,
+) -> Result<(Bill, T), ApiError> {
+    authorize(req, id, Access::Editor).await?;
+    let expected = if_match(req)?;
+    let author = author(req);
+    let (bill, value, stamped) = blocking(move || {
+        db::edit_bill(id, &author, |bill| {
+            check_etag(&expected, bill)?;
+            let value = f(bill)?;
+            bill.validate()?;
+            Ok(value)
+        })
+    })
+    .await?;
+    if let Some(rooms) = req.app_data::<web::Data<BillRooms>>().cloned() {
+        actix_web::rt::spawn(async move { rooms.broadcast(id, None, &stamped).await });
+    }
+    Ok((bill, value))
+}
+
+async fn load(req: &HttpRequest, id: Uuid) -> Result<Bill, ApiError> {
+    authorize(req, id, Access::ReadOnly).await?;
+    blocking(move || Ok(db::get_doc(id)?))
+        .await?
+        .map(|doc| doc.to_bill())
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "not_found", format!("bill {} not found", id)))
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct CreatedBill {
+    pub bill: Bill,
+    pub links: BillLinks,
+}
+
+/// `POST /api/v1/bills`. The response holds the share tokens; the editor
+/// token is needed for every change after this.
+#[utoipa::path(
+    post,
+    path = "/api/v1/bills",
+    request_body = Bill,
+    responses(
+        (status = 201, description = "The stored bill and its share tokens", body = CreatedBill, headers(("ETag" = String, description = "The bill's current version"))),
+        (status = 400, description = "Malformed JSON", body = ErrorResponse),
+        (status = 422, description = "The bill doesn't validate", body = ErrorResponse),
+    ),
+)]
+pub async fn create_bill(req: HttpRequest, body: web::Json<Bill>) -> Result<HttpResponse, ApiError> {
+    let mut bill = body.into_inner();
+    bill.id = Uuid::new_v4();
+    bill.fill_missing_ids();
+    bill.finalized = None;
+    bill.validate()?;
+    let links = BillLinks {
+        id: bill.id,
+        view_token: Some(db::new_token()),
+        edit_token: db::new_token(),
+    };
+    let (stored, author) = (bill.clone(), author(&req));
+    let links = blocking(move || {
+        db::insert_bill(&stored, &links, &author)?;
+        Ok(links)
+    })
+    .await?;
+    Ok(with_etag(HttpResponse::Created(), &bill)
+        .insert_header((header::LOCATION, format!("/api/v1/bills/{}", bill.id)))
+        .json(CreatedBill { bill, links }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/bills/{id}",
+    params(("id" = Uuid, Path, description = "The bill's id")),
+    responses(
+        (status = 200, description = "The bill", body = Bill, headers(("ETag" = String, description = "The bill's current version"))),
+        (status = 401, description = "No share token", body = ErrorResponse),
+        (status = 403, description = "The token can't do this", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+    ),
+    security(("share_token" = [])),
+)]
+pub async fn get_bill(req: HttpRequest, path: web::Path<Uuid>) -> Result<HttpResponse, ApiError> {
+    let bill = load(&req, path.into_inner()).await?;
+    Ok(with_etag(HttpResponse::Ok(), &bill).json(bill))
+}
+
+/// `PUT /api/v1/bills/{id}` replaces the whole bill. A finalized bill only
+/// takes itself back with `finalized` cleared, and a finalized hash has to
+/// match the content.
+#[utoipa::path(
+    put,
+    path = "/api/v1/bills/{id}",
+    params(("id" = Uuid, Path, description = "The bill's id"), ("If-Match" = String, Header, description = "The ETag the change is based on, or `*`")),
+    request_body = Bill,
+    responses(
+        (status = 200, description = "The updated bill", body = Bill, headers(("ETag" = String, description = "The bill's current version"))),
+        (status = 401, description = "No share token", body = ErrorResponse),
+        (status = 403, description = "The token can't do this", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 409, description = "The bill is finalized", body = ErrorResponse),
+        (status = 412, description = "The bill changed since it was fetched", body = ErrorResponse),
+        (status = 422, description = "The bill doesn't validate", body = ErrorResponse),
+        (status = 428, description = "`If-Match` is missing", body = ErrorResponse),
+    ),
+    security(("share_token" = [])),
+)]
+pub async fn put_bill(req: HttpRequest, path: web::Path<Uuid>, body: web::Json<Bill>) -> Result<HttpResponse, ApiError> {
+    let id = path.into_inner();
+    let (bill, _) = edit(&req, id, move |bill| {
+        let mut new = body.into_inner();
+        new.id = id;
+        if !bill.allows_change_to(&new) {
+            ensure_unlocked(bill)?;
+        }
+        if new.finalized.as_ref().is_some_and(|hash| *hash != new.content_hash()) {
+            return Err(ApiError::invalid("finalized", "doesn't match the bill's content hash"));
+        }
+        new.fill_missing_ids();
+        *bill = new;
+        Ok(())
+    })
+    .await?;
+    Ok(with_etag(HttpResponse::Ok(), &bill).json(bill))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/bills/{id}",
+    params(("id" = Uuid, Path, description = "The bill's id"), ("If-Match" = String, Header, description = "The ETag the change is based on, or `*`")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 401, description = "No share token", body = ErrorResponse),
+        (status = 403, description = "The token can't do this", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 412, description = "The bill changed since it was fetched", body = ErrorResponse),
+        (status = 428, description = "`If-Match` is missing", body = ErrorResponse),
+    ),
+    security(("share_token" = [])),
+)]
+pub async fn delete_bill(req: HttpRequest, path: web::Path<Uuid>) -> Result<HttpResponse, ApiError> {
+    let id = path.into_inner();
+    authorize(&req, id, Access::Editor).await?;
+    let expected = if_match(&req)?;
+    blocking(move || db::delete_bill_if(id, |bill| check_etag(&expected, bill))).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+fn participant_not_found(id: Uuid) -> ApiError {
+    ApiError::new(StatusCode::NOT_FOUND, "not_found", format!("participant {} not found", id))
+}
+
+fn item_not_found(id: Uuid) -> ApiError {
+    ApiError::new(StatusCode::NOT_FOUND, "not_found", format!("item {} not found", id))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/bills/{id}/participants",
+    params(("id" = Uuid, Path, description = "The bill's id")),
+    responses(
+        (status = 200, description = "Everyone on the bill", body = Vec<BillParticipant>, headers(("ETag" = String, description = "The bill's current version"))),
+        (status = 401, description = "No share token", body = ErrorResponse),
+        (status = 403, description = "The token can't do this", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+    ),
+    security(("share_token" = [])),
+)]
+pub async fn list_participants(req: HttpRequest, path: web::Path<Uuid>) -> Result<HttpResponse, ApiError> {
+    let bill = load(&req, path.into_inner()).await?;
+    Ok(with_etag(HttpResponse::Ok(), &bill).json(&bill.participants))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/bills/{id}/participants/{participant_id}",
+    params(("id" = Uuid, Path, description = "The bill's id"), ("participant_id" = Uuid, Path, description = "The participant's id")),
+    responses(
+        (status = 200, description = "The participant", body = BillParticipant, headers(("ETag" = String, description = "The bill's current version"))),
+        (status = 401, description = "No share token", body = ErrorResponse),
+        (status = 403, description = "The token can't do this", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+    ),
+    security(("share_token" = [])),
+)]
+pub async fn get_participant(req: HttpRequest, path: web::Path<(Uuid, Uuid)>) -> Result<HttpResponse, ApiError> {
+    let (id, participant_id) = path.into_inner();
+    let bill = load(&req, id).await?;
+    let participant = bill.participants.iter().find(|p| p.id == participant_id).ok_or_else(|| participant_not_found(participant_id))?;
+    Ok(with_etag(HttpResponse::Ok(), &bill).json(participant))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/bills/{id}/participants",
+    params(("id" = Uuid, Path, description = "The bill's id"), ("If-Match" = String, Header, description = "The ETag the change is based on, or `*`")),
+    request_body = BillParticipant,
+    responses(
+        (status = 201, description = "The new participant", body = BillParticipant, headers(("ETag" = String, description = "The bill's current version"))),
+        (status = 401, description = "No share token", body = ErrorResponse),
+        (status = 403, description = "The token can't do this", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 409, description = "The bill is finalized", body = ErrorResponse),
+        (status = 412, description = "The bill changed since it was fetched", body = ErrorResponse),
+        (status = 422, description = "The bill doesn't validate", body = ErrorResponse),
+        (status = 428, description = "`If-Match` is missing", body = ErrorResponse),
+    ),
+    security(("share_token" = [])),
+)]
+pub async fn add_participant(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    body: web::Json<BillParticipant>,
+) -> Result<HttpResponse, ApiError> {
+    let (bill, participant) = edit(&req, path.into_inner(), move |bill| {
+        ensure_unlocked(bill)?;
+        let participant = body.into_inner();
+        if bill.participants.iter().any(|p| p.id == participant.id) {
+            return Err(ApiError::new(StatusCode::CONFLICT, "already_exists", "a participant with this id already exists"));
+        }
+        bill.participants.push(participant);
+        bill.fill_missing_ids();
+        Ok(bill.participants[bill.participants.len() - 1].clone())
+    })
+    .await?;
+    Ok(with_etag(HttpResponse::Created(), &bill).json(participant))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/bills/{id}/participants/{participant_id}",
+    params(("id" = Uuid, Path, description = "The bill's id"), ("participant_id" = Uuid, Path, description = "The participant's id"), ("If-Match" = String, Header, description = "The ETag the change is based on, or `*`")),
+    request_body = BillParticipant,
+    responses(
+        (status = 200, description = "The updated participant", body = BillParticipant, headers(("ETag" = String, description = "The bill's current version"))),
+        (status = 401, description = "No share token", body = ErrorResponse),
+        (status = 403, description = "The token can't do this", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 409, description = "The bill is finalized", body = ErrorResponse),
+        (status = 412, description = "The bill changed since it was fetched", body = ErrorResponse),
+        (status = 422, description = "The bill doesn't validate", body = ErrorResponse),
+        (status = 428, description = "`If-Match` is missing", body = ErrorResponse),
+    ),
+    security(("share_token" = [])),
+)]
+pub async fn put_participant(
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+    body: web::Json<BillParticipant>,
+) -> Result<HttpResponse, ApiError> {
+    let (id, participant_id) = path.into_inner();
+    let (bill, participant) = edit(&req, id, move |bill| {
+        ensure_unlocked(bill)?;
+        let existing = bill.participants.iter_mut().find(|p| p.id == participant_id).ok_or_else(|| participant_not_found(participant_id))?;
+        *existing = BillParticipant { id: participant_id, ..body.into_inner() };
+        Ok(existing.clone())
+    })
+    .await?;
+    Ok(with_etag(HttpResponse::Ok(), &bill).json(participant))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/bills/{id}/participants/{participant_id}",
+    params(("id" = Uuid, Path, description = "The bill's id"), ("participant_id" = Uuid, Path, description = "The participant's id"), ("If-Match" = String, Header, description = "The ETag the change is based on, or `*`")),
+    responses(
+        (status = 204, description = "Removed, along with every reference to them", headers(("ETag" = String, description = "The bill's current version"))),
+        (status = 401, description = "No share token", body = ErrorResponse),
+        (status = 403, description = "The token can't do this", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 409, description = "The bill is finalized", body = ErrorResponse),
+        (status = 412, description = "The bill changed since it was fetched", body = ErrorResponse),
+        (status = 428, description = "`If-Match` is missing", body = ErrorResponse),
+    ),
+    security(("share_token" = [])),
+)]
+pub async fn delete_participant(req: HttpRequest, path: web::Path<(Uuid, Uuid)>) -> Result<HttpResponse, ApiError> {
+    let (id, participant_id) = path.into_inner();
+    let (bill, _) = edit(&req, id, move |bill| {
+        ensure_unlocked(bill)?;
+        if !bill.participants.iter().any(|p| p.id == participant_id) {
+            return Err(participant_not_found(participant_id));
+        }
+        bill.apply(&BillOp::RemoveParticipant(participant_id));
+        Ok(())
+    })
+    .await?;
+    Ok(with_etag(HttpResponse::NoContent(), &bill).finish())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/bills/{id}/items",
+    params(("id" = Uuid, Path, description = "The bill's id")),
+    responses(
+        (status = 200, description = "Every item on the bill", body = Vec<BillItem>, headers(("ETag" = String, description = "The bill's current version"))),
+        (status = 401, description = "No share token", body = ErrorResponse),
+        (status = 403, description = "The token can't do this", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+    ),
+    security(("share_token" = [])),
+)]
+pub async fn list_items(req: HttpRequest, path: web::Path<Uuid>) -> Result<HttpResponse, ApiError> {
+    let bill = load(&req, path.into_inner()).await?;
+    Ok(with_etag(HttpResponse::Ok(), &bill).json(&bill.items))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/bills/{id}/items/{item_id}",
+    params(("id" = Uuid, Path, description = "The bill's id"), ("item_id" = Uuid, Path, description = "The item's id")),
+    responses(
+        (status = 200, description = "The item", body = BillItem, headers(("ETag" = String, description = "The bill's current version"))),
+        (status = 401, description = "No share token", body = ErrorResponse),
+        (status = 403, description = "The token can't do this", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+    ),
+    security(("share_token" = [])),
+)]
+pub async fn get_item(req: HttpRequest, path: web::Path<(Uuid, Uuid)>) -> Result<HttpResponse, ApiError> {
+    let (id, item_id) = path.into_inner();
+    let bill = load(&req, id).await?;
+    let item = bill.items.iter().find(|i| i.id == item_id).ok_or_else(|| item_not_found(item_id))?;
+    Ok(with_etag(HttpResponse::Ok(), &bill).json(item))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/bills/{id}/items",
+    params(("id" = Uuid, Path, description = "The bill's id"), ("If-Match" = String, Header, description = "The ETag the change is based on, or `*`")),
+    request_body = BillItem,
+    responses(
+        (status = 201, description = "The new item", body = BillItem, headers(("ETag" = String, description = "The bill's current version"))),
+        (status = 401, description = "No share token", body = ErrorResponse),
+        (status = 403, description = "The token can't do this", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 409, description = "The bill is finalized", body = ErrorResponse),
+        (status = 412, description = "The bill changed since it was fetched", body = ErrorResponse),
+        (status = 422, description = "The bill doesn't validate", body = ErrorResponse),
+        (status = 428, description = "`If-Match` is missing", body = ErrorResponse),
+    ),
+    security(("share_token" = [])),
+)]
+pub async fn add_item(req: HttpRequest, path: web::Path<Uuid>, body: web::Json<BillItem>) -> Result<HttpResponse, ApiError> {
+    let (bill, item) = edit(&req, path.into_inner(), move |bill| {
+        ensure_unlocked(bill)?;
+        let item = body.into_inner();
+        if bill.items.iter().any(|i| i.id == item.id) {
+            return Err(ApiError::new(StatusCode::CONFLICT, "already_exists", "an item with this id already exists"));
+        }
+        bill.items.push(item);
+        bill.fill_missing_ids();
+        Ok(bill.items[bill.items.len() - 1].clone())
+    })
+    .await?;
+    Ok(with_etag(HttpResponse::Created(), &bill).json(item))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/bills/{id}/items/{item_id}",
+    params(("id" = Uuid, Path, description = "The bill's id"), ("item_id" = Uuid, Path, description = "The item's id"), ("If-Match" = String, Header, description = "The ETag the change is based on, or `*`")),
+    request_body = BillItem,
+    responses(
+        (status = 200, description = "The updated item", body = BillItem, headers(("ETag" = String, description = "The bill's current version"))),
+        (status = 401, description = "No share token", body = ErrorResponse),
+        (status = 403, description = "The token can't do this", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 409, description = "The bill is finalized", body = ErrorResponse),
+        (status = 412, description = "The bill changed since it was fetched", body = ErrorResponse),
+        (status = 422, description = "The bill doesn't validate", body = ErrorResponse),
+        (status = 428, description = "`If-Match` is missing", body = ErrorResponse),
+    ),
+    security(("share_token" = [])),
+)]
+pub async fn put_item(
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+    body: web::Json<BillItem>,
+) -> Result<HttpResponse, ApiError> {
+    let (id, item_id) = path.into_inner();
+    let (bill, item) = edit(&req, id, move |bill| {
+        ensure_unlocked(bill)?;
+        let existing = bill.items.iter_mut().find(|i| i.id == item_id).ok_or_else(|| item_not_found(item_id))?;
+        *existing = BillItem { id: item_id, ..body.into_inner() };
+        Ok(existing.clone())
+    })
+    .await?;
+    Ok(with_etag(HttpResponse::Ok(), &bill).json(item))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/bills/{id}/items/{item_id}",
+    params(("id" = Uuid, Path, description = "The bill's id"), ("item_id" = Uuid, Path, description = "The item's id"), ("If-Match" = String, Header, description = "The ETag the change is based on, or `*`")),
+    responses(
+        (status = 204, description = "Removed", headers(("ETag" = String, description = "The bill's current version"))),
+        (status = 401, description = "No share token", body = ErrorResponse),
+        (status = 403, description = "The token can't do this", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 409, description = "The bill is finalized", body = ErrorResponse),
+        (status = 412, description = "The bill changed since it was fetched", body = ErrorResponse),
+        (status = 428, description = "`If-Match` is missing", body = ErrorResponse),
+    ),
+    security(("share_token" = [])),
+)]
+pub async fn delete_item(req: HttpRequest, path: web::Path<(Uuid, Uuid)>) -> Result<HttpResponse, ApiError> {
+    let (id, item_id) = path.into_inner();
+    let (bill, _) = edit(&req, id, move |bill| {
+        ensure_unlocked(bill)?;
+        if !bill.items.iter().any(|i| i.id == item_id) {
+            return Err(item_not_found(item_id));
+        }
+        bill.items.retain(|i| i.id != item_id);
+        Ok(())
+    })
+    .await?;
+    Ok(with_etag(HttpResponse::NoContent(), &bill).finish())
+}
+
+struct ShareTokenAuth;
+
+impl Modify for ShareTokenAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "share_token",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some("One of the bill's share tokens. Changes need the edit token."))
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// The OpenAPI document of everything under `/api/v1`, built from the same
+/// types the handlers use.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "whati8 bill API", version = "1"),
+    paths(
+        split,
+        create_bill,
+        get_bill,
+        put_bill,
+        delete_bill,
+        list_participants,
+        add_participant,
+        get_participant,
+        put_participant,
+        delete_participant,
+        list_items,
+        add_item,
+        get_item,
+        put_item,
+        delete_item,
+    ),
+    components(schemas(
+        ApiError,
+        ErrorResponse,
+        SplitRequest,
+        SplitParticipantInput,
+        SplitItemInput,
+        DiscountInput,
+        SplitResponse,
+        ShareResponse,
+        TransferResponse,
+        CreatedBill,
+        Bill,
+        BillParticipant,
+        BillItem,
+        BillLinks,
+        Assignment,
+        UnassignedPolicy,
+        TreatMode,
+        ShareBreakdown,
+    )),
+    modifiers(&ShareTokenAuth),
+)]
+pub struct ApiDoc;
+
+/// `GET /api/openapi.json`
+pub async fn openapi() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+/// The versioned JSON API and its OpenAPI document. Has to be registered
+/// before the catch-all `/api/{tail:.*}` route of the server functions.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route("/api/openapi.json", web::get().to(openapi));
+    cfg.service(
+        web::scope("/api/v1")
+            .app_data(json_config())
+            .route("/split", web::post().to(split))
+            .route("/bills", web::post().to(create_bill))
+            .service(
+                web::resource("/bills/{id}")
+                    .route(web::get().to(get_bill))
+                    .route(web::put().to(put_bill))
+                    .route(web::delete().to(delete_bill)),
+            )
+            .service(
+                web::resource("/bills/{id}/participants")
+                    .route(web::get().to(list_participants))
+                    .route(web::post().to(add_participant)),
+            )
+            .service(
+                web::resource("/bills/{id}/participants/{participant_id}")
+                    .route(web::get().to(get_participant))
+                    .route(web::put().to(put_participant))
+                    .route(web::delete().to(delete_participant)),
+            )
+            .service(
+                web::resource("/bills/{id}/items")
+                    .route(web::get().to(list_items))
+                    .route(web::post().to(add_item)),
+            )
+            .service(
+                web::resource("/bills/{id}/items/{item_id}")
+                    .route(web::get().to(get_item))
+                    .route(web::put().to(put_item))
+                    .route(web::delete().to(delete_item)),
+            ),
+    );
+}
@@ -6,13 +6,170 @@ use log::{info, debug, LevelFilter};
 use serde::{Deserialize, Serialize};
 use uuid::{timestamp::UUID_TICKS_BETWEEN_EPOCHS, Uuid};
 use rust_decimal::{prelude::{FromPrimitive, ToPrimitive}, Decimal};
-use wasm_bindgen::JsCast;
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
 use web_sys::{js_sys::{Date, Intl::DateTimeFormat}, Blob};
 use rusty_money::{Money, iso, Locale};
+use crate::bill::{Bill, BillItem, BillParticipant};
+use crate::crdt::{BillDoc, StampedOp};
+use crate::csv_file::{import_ops, items_csv, parse_items_csv, participants_csv, ImportRow};
+use crate::history::{bill_at, describe_changes, describe_op, describe_share_changes};
+use crate::ops::BillOp;
+use crate::quick_entry::{parse_quick_entry, quick_entry_ops, QuickLine};
+use crate::receipt::{parse_receipt, Receipt};
+use crate::share::{bill_fragment, bill_from_fragment};
+use crate::crypto::{decrypt_bill, encrypt_bill, encrypted_bill_path, generate_key, key_from_fragment, write_token};
+use crate::storage::{
+    bill_history, bill_links, bill_path, create_bill, create_encrypted_bill, delete_bill, list_bills, load_bill,
+    load_encrypted_bill, revoke_view_token, restore_revision, rotate_token, save_encrypted_bill, sync_bill, Access,
+    BillLinks,
+};
 
 const STORAGE_KEY_LITEM : &str = "litems-key";
 const STORAGE_KEY_PARTICIPANTS : &str = "participants-key";
 const STORAGE_KEY_SPLIT_ITEM: &str = "split-item-key";
+const STORAGE_KEY_MY_BILLS: &str = "my-bills-key";
+const STORAGE_KEY_ACTOR: &str = "actor-key";
+const STORAGE_KEY_BILL_DOC: &str = "bill-doc-key";
+const STORAGE_KEY_AUTHOR: &str = "author-key";
+
+
+/// A server bill this browser created, with the editor token to manage it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct MyBill {
+    id: Uuid,
+    edit_token: String,
+}
+
+fn my_bills() -> Vec<MyBill> {
+    window()
+        .local_storage()
+        .ok()
+        .flatten()
+        .and_then(|storage| storage.get_item(STORAGE_KEY_MY_BILLS).ok().flatten())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn store_my_bills(bills: &[MyBill]) {
+    if let Ok(Some(storage)) = window().local_storage() {
+        let json = serde_json::to_string(bills).expect("Couldn't serialize json");
+        if storage.set_item(STORAGE_KEY_MY_BILLS, &json).is_err() {
+            log::error!("Error while trying to set item in my bills");
+        }
+    }
+}
+
+fn remember_bill(links: &BillLinks) {
+    let mut bills = my_bills();
+    bills.retain(|b| b.id != links.id);
+    bills.push(MyBill { id: links.id, edit_token: links.edit_token.clone() });
+    store_my_bills(&bills);
+}
+
+fn forget_bill(id: Uuid) {
+    let mut bills = my_bills();
+    bills.retain(|b| b.id != id);
+    store_my_bills(&bills);
+    if let Ok(Some(storage)) = window().local_storage() {
+        let _ = storage.remove_item(&bill_doc_key(id));
+    }
+}
+
+/// Hands `contents` to the browser as a file download.
+fn download(filename: &str, mime: &str, contents: &str) -> Result<(), JsValue> {
+    let parts = web_sys::js_sys::Array::of1(&JsValue::from_str(contents));
+    let mut options = web_sys::BlobPropertyBag::new();
+    options.type_(mime);
+    let blob = Blob::new_with_str_sequence_and_options(&parts, &options)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob)?;
+    let link = document().create_element("a")?.unchecked_into::<web_sys::HtmlAnchorElement>();
+    link.set_href(&url);
+    link.set_download(filename);
+    link.click();
+    web_sys::Url::revoke_object_url(&url)
+}
+
+/// Reads the file picked in a file input, then clears the input so the same
+/// file can be picked again.
+fn read_picked_file(ev: &web_sys::Event, on_read: impl FnOnce(Result<String, String>) + 'static) {
+    let Some(input) = ev.target().and_then(|t| t.dyn_into::<web_sys::HtmlInputElement>().ok()) else {
+        return;
+    };
+    let Some(file) = input.files().and_then(|files| files.get(0)) else {
+        return;
+    };
+    input.set_value("");
+    spawn_local(async move {
+        let text = wasm_bindgen_futures::JsFuture::from(file.text()).await;
+        on_read(text.map(|t| t.as_string().unwrap_or_default()).map_err(|_| String::from("the file couldn't be read")));
+    });
+}
+
+/// A file name for downloads of `bill`, e.g. `Goa trip.whati8.json`.
+fn bill_file_name(bill: &Bill, extension: &str) -> String {
+    let name = bill.event_name.trim().replace(['/', '\\'], "-");
+    format!("{}.{}", if name.is_empty() { "bill" } else { &name }, extension)
+}
+
+/// The id this browser stamps its edits with.
+fn device_actor() -> Uuid {
+    let storage = window().local_storage().ok().flatten();
+    if let Some(actor) = storage
+        .as_ref()
+        .and_then(|storage| storage.get_item(STORAGE_KEY_ACTOR).ok().flatten())
+        .and_then(|actor| Uuid::from_str(&actor).ok())
+    {
+        return actor;
+    }
+    let actor = Uuid::new_v4();
+    if let Some(storage) = storage {
+        if storage.set_item(STORAGE_KEY_ACTOR, &actor.to_string()).is_err() {
+            log::error!("Error while trying to set item in actor");
+        }
+    }
+    actor
+}
+
+/// The name this browser's edits are credited to in a bill's history.
+fn author_name() -> String {
+    window()
+        .local_storage()
+        .ok()
+        .flatten()
+        .and_then(|storage| storage.get_item(STORAGE_KEY_AUTHOR).ok().flatten())
+        .unwrap_or_default()
+}
+
+fn store_author_name(name: &str) {
+    if let Ok(Some(storage)) = window().local_storage() {
+        if storage.set_item(STORAGE_KEY_AUTHOR, name).is_err() {
+            log::error!("Error while trying to set item in author");
+        }
+    }
+}
+
+fn bill_doc_key(id: Uuid) -> String {
+    format!("{}-{}", STORAGE_KEY_BILL_DOC, id)
+}
+
+/// Edits to a server bill kept in the browser, including ones made offline.
+fn stored_doc(id: Uuid) -> Option<BillDoc> {
+    window()
+        .local_storage()
+        .ok()
+        .flatten()
+        .and_then(|storage| storage.get_item(&bill_doc_key(id)).ok().flatten())
+        .and_then(|json| serde_json::from_str(&json).ok())
+}
+
+fn store_doc(doc: &BillDoc) {
+    if let Ok(Some(storage)) = window().local_storage() {
+        let json = serde_json::to_string(doc).expect("Couldn't serialize json");
+        if storage.set_item(&bill_doc_key(doc.id), &json).is_err() {
+            log::error!("Error while trying to set item in bill doc");
+        }
+    }
+}
 
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -31,12 +188,56 @@ impl LItems {
 }
 
 
+/// Who an item is assigned to. `Everyone` and `EveryoneExcept` are resolved
+/// against the bill's participants on every `calculate_split`, so people
+/// added later are picked up automatically.
+#[cfg_attr(feature = "ssr", derive(utoipa::ToSchema))]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Assignment {
+    #[default]
+    Selected,
+    Everyone,
+    EveryoneExcept(Vec<Uuid>),
+}
+
+impl Assignment {
+    pub fn from_mode(mode: &str, excluded: Vec<Uuid>) -> Self {
+        match mode {
+            "everyone" => Assignment::Everyone,
+            "except" => Assignment::EveryoneExcept(excluded),
+            _ => Assignment::Selected,
+        }
+    }
+
+    pub fn mode(&self) -> &'static str {
+        match self {
+            Assignment::Selected => "selected",
+            Assignment::Everyone => "everyone",
+            Assignment::EveryoneExcept(_) => "except",
+        }
+    }
+}
+
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct LItem {
     id: Uuid,
     item_name: RwSignal<String>,
     price: RwSignal<Decimal>,
+    #[serde(default = "default_quantity")]
+    quantity: RwSignal<u32>,
+    #[serde(default = "default_category")]
+    category: RwSignal<String>,
     participants: RwSignal<Vec<Participant>>,
+    assignment: RwSignal<Assignment>,
+}
+
+fn default_quantity() -> RwSignal<u32> {
+    create_rw_signal(1)
+}
+
+fn default_category() -> RwSignal<String> {
+    create_rw_signal(String::new())
 }
 
 
@@ -45,12 +246,32 @@ impl LItem {
         let item_name = create_rw_signal(item_name);
         let price = create_rw_signal(price);
         let participants = create_rw_signal(Vec::new());
+        let assignment = create_rw_signal(Assignment::default());
 
         LItem {
             id: Uuid::new_v4(),
             item_name,
             price,
-            participants
+            quantity: default_quantity(),
+            category: default_category(),
+            participants,
+            assignment
+        }
+    }
+
+    fn set_quantity(&self, quantity: u32) {
+        self.quantity.set(quantity.max(1));
+    }
+
+    fn set_category(&self, category: String) {
+        self.category.set(category);
+    }
+
+    /// The name as listed, e.g. "2 × Beer".
+    fn display_name(&self) -> String {
+        match self.quantity.get() {
+            1 => self.item_name.get(),
+            quantity => format!("{} × {}", quantity, self.item_name.get()),
         }
     }
 
@@ -67,8 +288,24 @@ impl LItem {
         self.price.update(|up| *up = updated_price.round_dp(2));
     }
 
-    fn get_split_by_participants(&self) -> Decimal {
-        self.price.get() / Decimal::from(self.participants.get().len())
+    fn set_assignment(&self, assignment: Assignment) {
+        self.assignment.update(|a| *a = assignment);
+    }
+
+    fn resolve_participants(&self, all: &[Participant]) -> Vec<Participant> {
+        match self.assignment.get() {
+            Assignment::Selected => self.participants.get(),
+            Assignment::Everyone => all.to_vec(),
+            Assignment::EveryoneExcept(excluded) => all
+                .iter()
+                .filter(|p| !excluded.contains(&p.id))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    fn is_unassigned(&self, all: &[Participant]) -> bool {
+        self.resolve_participants(all).is_empty()
     }
 
     fn add_participant(&mut self, participant: Participant)  {
@@ -76,7 +313,12 @@ impl LItem {
     }
 
     fn remove_participant(&mut self, participant_id: String) {
-        self.participants.update(|p| p.retain(|x| x.id.to_string() != participant_id))
+        self.participants.update(|p| p.retain(|x| x.id.to_string() != participant_id));
+        self.assignment.update(|a| {
+            if let Assignment::EveryoneExcept(excluded) = a {
+                excluded.retain(|id| id.to_string() != participant_id);
+            }
+        });
     }
 }
 
@@ -104,7 +346,10 @@ pub struct Participant {
     id: Uuid,
     name: RwSignal<String>,
     payer: RwSignal<bool>,
-    settle_status: RwSignal<bool>
+    settle_status: RwSignal<bool>,
+    /// How many shares this participant takes in anything split equally,
+    /// e.g. 0.5 for a child.
+    weight: RwSignal<Decimal>
 }
 
 impl Participant {
@@ -112,11 +357,13 @@ impl Participant {
         let name = create_rw_signal(name);
         let payer = create_rw_signal(false);
         let settle_status = create_rw_signal(false);
+        let weight = create_rw_signal(Decimal::ONE);
         Participant {
            id: Uuid::new_v4(),
            name,
            payer,
-           settle_status
+           settle_status,
+           weight
         }
     }
 
@@ -128,6 +375,10 @@ impl Participant {
         self.name.update(|name| *name = new_name.clone());
     }
 
+    fn update_weight(&self, weight: Decimal) {
+        self.weight.update(|w| *w = weight.max(Decimal::ZERO).round_dp(2));
+    }
+
     fn mark_as_payer(&mut self) {
         self.payer.update(|payed| *payed = !*payed);
     }
@@ -156,17 +407,140 @@ impl Participant {
 
 
 
+/// What happens to the cost of an item nobody has been assigned to.
+#[cfg_attr(feature = "ssr", derive(utoipa::ToSchema))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UnassignedPolicy {
+    /// Refuse to produce a split until every item is assigned.
+    #[default]
+    #[serde(alias = "block")]
+    Block,
+    #[serde(alias = "everyone")]
+    SplitAmongEveryone,
+    #[serde(alias = "payer")]
+    ChargePayer,
+}
+
+impl UnassignedPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnassignedPolicy::Block => "block",
+            UnassignedPolicy::SplitAmongEveryone => "everyone",
+            UnassignedPolicy::ChargePayer => "payer",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            UnassignedPolicy::Block => "Split is blocked until these items are assigned",
+            UnassignedPolicy::SplitAmongEveryone => "Split equally among everyone",
+            UnassignedPolicy::ChargePayer => "Charged to the payer",
+        }
+    }
+}
+
+impl FromStr for UnassignedPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "block" => Ok(UnassignedPolicy::Block),
+            "everyone" => Ok(UnassignedPolicy::SplitAmongEveryone),
+            "payer" => Ok(UnassignedPolicy::ChargePayer),
+            other => Err(format!("unknown unassigned item policy: {}", other)),
+        }
+    }
+}
+
+
+/// How a treated participant's share is spread over everyone else.
+#[cfg_attr(feature = "ssr", derive(utoipa::ToSchema))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TreatMode {
+    #[default]
+    Equal,
+    /// In proportion to what each of the others ordered.
+    Proportional,
+}
+
+impl TreatMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TreatMode::Equal => "equal",
+            TreatMode::Proportional => "proportional",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            TreatMode::Equal => "split equally",
+            TreatMode::Proportional => "split in proportion to what each person ordered",
+        }
+    }
+}
+
+impl FromStr for TreatMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "equal" => Ok(TreatMode::Equal),
+            "proportional" => Ok(TreatMode::Proportional),
+            other => Err(format!("unknown treat mode: {}", other)),
+        }
+    }
+}
+
+
+/// Where a participant's amount in `final_split` comes from.
+#[cfg_attr(feature = "ssr", derive(utoipa::ToSchema))]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShareBreakdown {
+    pub items: Decimal,
+    pub tax: Decimal,
+    #[serde(default)]
+    pub tip: Decimal,
+    /// Negative: the participant's part of the bill's discount.
+    #[serde(default)]
+    pub discount: Decimal,
+    /// Negative for a treated participant, positive for the people covering them.
+    pub treat: Decimal,
+    /// Negative for the amount above a participant's cap, positive for
+    /// whoever absorbs that overflow.
+    pub cap: Decimal,
+}
+
+impl ShareBreakdown {
+    pub fn total(&self) -> Decimal {
+        (self.items + self.tax + self.tip + self.discount + self.treat + self.cap).round_dp(2)
+    }
+}
+
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SplitItem {
     id: Uuid,
     event_name: RwSignal<String>,
     total_price: RwSignal<Decimal>,
     total_tax: RwSignal<Decimal>,
+    total_tip: RwSignal<Decimal>,
+    total_discount: RwSignal<Decimal>,
     participants: RwSignal<Vec<Participant>>,
     line_items: RwSignal<Vec<LItem>>,
     final_split: RwSignal<HashMap<Uuid, Decimal>>,
     settle_status: RwSignal<bool>,
-    summary_text: RwSignal<String>
+    summary_text: RwSignal<String>,
+    receipt_total: RwSignal<Option<Decimal>>,
+    unassigned_policy: RwSignal<UnassignedPolicy>,
+    treated: RwSignal<Vec<Uuid>>,
+    treat_mode: RwSignal<TreatMode>,
+    breakdown: RwSignal<HashMap<Uuid, ShareBreakdown>>,
+    caps: RwSignal<HashMap<Uuid, Decimal>>,
+    bill_cap: RwSignal<Option<Decimal>>,
+    sponsor: RwSignal<Option<Uuid>>,
+    finalized: RwSignal<Option<String>>,
+    /// Cap overflow nobody had room for, left with the capped participants.
+    cap_shortfall: RwSignal<Decimal>
 }
 
 
@@ -175,24 +549,70 @@ impl SplitItem {
         let event_name = create_rw_signal(String::new());
         let total_price = create_rw_signal(Decimal::new(0,2));
         let total_tax = create_rw_signal(Decimal::new(0,2));
+        let total_tip = create_rw_signal(Decimal::new(0,2));
+        let total_discount = create_rw_signal(Decimal::new(0,2));
         let participants = create_rw_signal(Vec::new());
         let line_items = create_rw_signal(Vec::new());
         let final_split = create_rw_signal(HashMap::new());
         let settle_status = create_rw_signal(false);
         let summary_text = create_rw_signal(String::new());
+        let receipt_total = create_rw_signal(None);
+        let unassigned_policy = create_rw_signal(UnassignedPolicy::default());
+        let treated = create_rw_signal(Vec::new());
+        let treat_mode = create_rw_signal(TreatMode::default());
+        let breakdown = create_rw_signal(HashMap::new());
+        let caps = create_rw_signal(HashMap::new());
+        let bill_cap = create_rw_signal(None);
+        let sponsor = create_rw_signal(None);
+        let finalized = create_rw_signal(None);
+        let cap_shortfall = create_rw_signal(Decimal::ZERO);
         SplitItem {
            id: Uuid::new_v4(),
            event_name,
            total_price,
            total_tax,
+           total_tip,
+           total_discount,
            participants,
            line_items,
            final_split,
            settle_status,
-           summary_text
+           summary_text,
+           receipt_total,
+           unassigned_policy,
+           treated,
+           treat_mode,
+           breakdown,
+           caps,
+           bill_cap,
+           sponsor,
+           finalized,
+           cap_shortfall
         }
     }
 
+    fn is_finalized(&self) -> bool {
+        self.finalized.get_untracked().is_some()
+    }
+
+    /// Locks the bill and records the hash of its content, so anyone can
+    /// check later that nothing changed.
+    fn finalize(&mut self) {
+        let hash = untrack(|| self.to_bill()).content_hash();
+        self.finalized.set(Some(hash));
+    }
+
+    fn unlock(&mut self) {
+        self.finalized.set(None);
+    }
+
+    /// Whether the bill no longer matches the hash taken when it was finalized.
+    fn is_tampered(&self) -> bool {
+        self.finalized
+            .get()
+            .is_some_and(|hash| hash != self.to_bill().content_hash())
+    }
+
     fn add_event_name(&mut self, event_name: String) {
         self.event_name.update(|e| *e = event_name);
     }
@@ -201,11 +621,94 @@ impl SplitItem {
         self.total_tax.update(|p| *p = tax.round_dp(2));
     }
 
+    fn add_total_tip(&mut self, tip: Decimal) {
+        self.total_tip.update(|p| *p = tip.round_dp(2));
+    }
+
+    fn add_total_discount(&mut self, discount: Decimal) {
+        self.total_discount.update(|p| *p = discount.round_dp(2));
+    }
+
 
     fn add_participant(&mut self, participant: Participant) {
         self.participants.update(|p| p.push(participant));
     }
 
+    fn set_unassigned_policy(&mut self, policy: UnassignedPolicy) {
+        self.unassigned_policy.update(|p| *p = policy);
+    }
+
+    fn toggle_treated(&mut self, id: Uuid) {
+        self.treated.update(|t| {
+            if t.contains(&id) {
+                t.retain(|x| *x != id);
+            } else {
+                t.push(id);
+            }
+        });
+    }
+
+    fn is_treated(&self, id: &Uuid) -> bool {
+        self.treated.get().contains(id)
+    }
+
+    fn set_treat_mode(&mut self, mode: TreatMode) {
+        self.treat_mode.update(|m| *m = mode);
+    }
+
+    fn set_participant_cap(&mut self, id: Uuid, cap: Option<Decimal>) {
+        self.caps.update(|c| match cap {
+            Some(cap) => {
+                c.insert(id, cap.round_dp(2));
+            }
+            None => {
+                c.remove(&id);
+            }
+        });
+    }
+
+    fn set_bill_cap(&mut self, cap: Option<Decimal>) {
+        self.bill_cap.update(|c| *c = cap.map(|cap| cap.round_dp(2)));
+    }
+
+    fn set_sponsor(&mut self, sponsor: Option<Uuid>) {
+        self.sponsor.update(|s| *s = sponsor);
+    }
+
+    fn sponsor_name(&self) -> Option<String> {
+        let sponsor = self.sponsor.get()?;
+        self.participants.get().iter().find(|p| p.id == sponsor).map(|p| p.name.get())
+    }
+
+    /// A participant's own cap wins over the bill-wide one. The sponsor is
+    /// never capped.
+    fn cap_for(&self, id: &Uuid) -> Option<Decimal> {
+        if self.sponsor.get().as_ref() == Some(id) {
+            return None;
+        }
+        self.caps.get().get(id).cloned().or(self.bill_cap.get())
+    }
+
+    fn set_receipt_total(&mut self, receipt_total: Option<Decimal>) {
+        self.receipt_total.update(|r| *r = receipt_total.map(|t| t.round_dp(2)));
+    }
+
+    /// Items, tax and tip less the discount, before anything is rounded into
+    /// shares. `total_price` adds up rounded shares, so it can be a paisa off.
+    fn computed_total(&self) -> Decimal {
+        let items: Decimal = self.line_items.get().iter().map(|li| li.price.get()).sum();
+        items + self.total_tax.get() + self.total_tip.get() - self.total_discount.get().min(items)
+    }
+
+    /// Difference between the grand total printed on the receipt and the
+    /// computed total. Positive means something is missing, negative means
+    /// a discount wasn't entered.
+    fn receipt_difference(&self) -> Option<Decimal> {
+        self.receipt_total
+            .get()
+            .map(|receipt| (receipt - self.computed_total()).round_dp(2))
+    }
+
 
     fn add_line_item(&mut self, li: LItem) {
         self.line_items.update(|p| p.push(li));
@@ -224,31 +727,127 @@ impl SplitItem {
 
     fn remove_participant(&mut self, id: String) {
         self.participants.update(|p| p.retain(|p| p.id.to_string() != id));
+        self.treated.update(|t| t.retain(|x| x.to_string() != id));
+        self.caps.update(|c| c.retain(|x, _| x.to_string() != id));
+        self.sponsor.update(|s| {
+            if s.map(|x| x.to_string()) == Some(id.clone()) {
+                *s = None;
+            }
+        });
+    }
+
+    fn unassigned_items(&self) -> Vec<LItem> {
+        let participants = self.participants.get();
+        self.line_items.get().into_iter().filter(|li| li.is_unassigned(&participants)).collect()
     }
 
-    fn total_involved_participant_count(&mut self) -> Decimal {
-        let mut count = Decimal::new(0, 0); // Change 2 to 0 for integer part
-        for part in &self.participants.get() {
-            if self.is_involved_in_any_orders(part) {
-                count += Decimal::new(1, 0); // Increment count by 1
+    fn is_blocked(&self) -> bool {
+        self.unassigned_policy.get() == UnassignedPolicy::Block && !self.unassigned_items().is_empty()
+    }
+
+    /// Whether unassigned items should go to the payer but nobody is marked
+    /// as one, so they are split among everyone instead.
+    fn has_no_payer_to_charge(&self) -> bool {
+        self.unassigned_policy.get() == UnassignedPolicy::ChargePayer
+            && !self.participants.get().iter().any(|p| p.is_payer())
+    }
+
+    /// Participants sharing a line item. Items nobody was assigned to fall
+    /// back to the unassigned policy so their cost never disappears.
+    fn item_sharers(&self, litem: &LItem) -> Vec<Uuid> {
+        let assigned = litem.resolve_participants(&self.participants.get());
+        if !assigned.is_empty() {
+            return assigned.iter().map(|p| p.id).collect();
+        }
+        match self.unassigned_policy.get() {
+            UnassignedPolicy::Block => Vec::new(),
+            UnassignedPolicy::ChargePayer if !self.has_no_payer_to_charge() => {
+                self.participants.get().iter().filter(|p| p.is_payer()).map(|p| p.id).collect()
+            }
+            UnassignedPolicy::SplitAmongEveryone | UnassignedPolicy::ChargePayer => {
+                self.participants.get().iter().map(|p| p.id).collect()
             }
         }
+    }
+
+    fn involved_participants(&self) -> Vec<Uuid> {
+        self.participants
+            .get()
+            .iter()
+            .filter(|part| self.is_involved_in_any_orders(part))
+            .map(|part| part.id)
+            .collect()
+    }
+
+    fn weight_of(&self, id: &Uuid) -> Decimal {
+        self.participants
+            .get()
+            .iter()
+            .find(|p| p.id == *id)
+            .map(|p| p.weight.get())
+            .unwrap_or(Decimal::ONE)
+    }
 
-        count
+    /// `id`'s part of `amount` when it is divided over `among` by participant
+    /// weight. Falls back to a head count if everyone has a weight of zero.
+    fn weighted_share(&self, amount: Decimal, id: &Uuid, among: &[Uuid]) -> Decimal {
+        if !among.contains(id) {
+            return Decimal::from(0);
+        }
+        let total_weight: Decimal = among.iter().map(|a| self.weight_of(a)).sum();
+        if total_weight.is_zero() {
+            return amount / Decimal::from(among.len());
+        }
+        amount * self.weight_of(id) / total_weight
     }
 
 
-    fn tax_split(&mut self) -> Decimal {
-        if self.total_tax.get().ceil() > Decimal::from(0) && Decimal::from(self.final_split.get().len()) > Decimal::from(0) && self.total_involved_participant_count() > Decimal::from(0) {
-            self.total_tax.get().round_dp(2) / self.total_involved_participant_count()
+    /// What each participant pays towards each item, before tax, tip and
+    /// everything after: `(item id, participant id, amount)`, in item order.
+    pub fn item_shares(&self) -> Vec<(Uuid, Uuid, Decimal)> {
+        let mut shares = Vec::new();
+        for item in self.line_items.get().iter() {
+            let sharers = self.item_sharers(item);
+            for id in sharers.iter() {
+                shares.push((item.id, *id, self.weighted_share(item.price.get(), id, &sharers).round_dp(2)));
+            }
+        }
+        shares
+    }
+
+    fn tax_split(&self, part: &Participant) -> Decimal {
+        if self.total_tax.get().ceil() > Decimal::from(0) {
+            self.weighted_share(self.total_tax.get().round_dp(2), &part.id, &self.involved_participants())
         } else {
             Decimal::from(0)
         }
     }
 
-    fn is_involved_in_any_orders(&mut self, part: &Participant) -> bool {
+    /// The tip is shared like the tax.
+    fn tip_split(&self, part: &Participant) -> Decimal {
+        if self.total_tip.get() > Decimal::ZERO {
+            self.weighted_share(self.total_tip.get(), &part.id, &self.involved_participants())
+        } else {
+            Decimal::ZERO
+        }
+    }
+
+    /// Gives out the discount in proportion to what everyone ordered, as a
+    /// negative amount. Never more than the items themselves cost.
+    fn apply_discount(&self, breakdown: &mut HashMap<Uuid, ShareBreakdown>) {
+        let items_total: Decimal = breakdown.values().map(|b| b.items).sum();
+        let discount = self.total_discount.get().min(items_total);
+        if discount <= Decimal::ZERO {
+            return;
+        }
+        for b in breakdown.values_mut() {
+            b.discount = -(discount * b.items / items_total).round_dp(2);
+        }
+    }
+
+    fn is_involved_in_any_orders(&self, part: &Participant) -> bool {
        for litem in self.line_items.get().iter() {
-        if litem.participants.get().contains(part) {
+        if self.item_sharers(litem).contains(&part.id) {
             return true;
         }
        } 
@@ -262,16 +861,81 @@ impl SplitItem {
         let participants = self.participants.get();
         let line_items = self.line_items.get();
         let final_split = self.final_split.get();
+        let breakdown = self.breakdown.get();
+        let treated = self.treated.get();
+        let sponsor_name = self.sponsor_name();
 
         let mut summary_text = String::new();
         summary_text.push_str(&format!("-----BILL SPLIT SUMMARY (COPY THIS)---- \n"));
         summary_text.push_str(&format!("Event Name: {}\n", event_name));
         summary_text.push_str(&format!("Total Price: ₹{}\n", total_price));
-        summary_text.push_str(&format!("Total Tax: ₹{}\n\n", total_tax));
+        summary_text.push_str(&format!("Total Tax: ₹{}\n", total_tax));
+        if !self.total_tip.get().is_zero() {
+            summary_text.push_str(&format!("Total Tip: ₹{}\n", self.total_tip.get()));
+        }
+        if !self.total_discount.get().is_zero() {
+            summary_text.push_str(&format!("Total Discount: ₹{}\n", self.total_discount.get()));
+        }
+        if let Some(receipt_total) = self.receipt_total.get() {
+            summary_text.push_str(&format!("Receipt Total: ₹{}\n", receipt_total));
+            if let Some(difference) = self.receipt_difference().filter(|d| !d.is_zero()) {
+                summary_text.push_str(&format!("Unreconciled Difference: ₹{}\n", difference));
+            }
+        }
+        if let Some(hash) = self.finalized.get() {
+            summary_text.push_str(&format!("Finalized, content hash (SHA-256): {}\n", hash));
+            if self.is_tampered() {
+                summary_text.push_str("WARNING: the bill changed after it was finalized, the hash no longer matches\n");
+            }
+        }
+        summary_text.push_str("\n");
+
+        let unassigned = self.unassigned_items();
+        if !unassigned.is_empty() {
+            summary_text.push_str(&format!("Unassigned Items ({}):\n", self.unassigned_policy.get().description()));
+            for item in unassigned.iter() {
+                summary_text.push_str(&format!("  - {}: ₹{}\n", &item.display_name(), &item.price.get()));
+            }
+            if self.has_no_payer_to_charge() {
+                summary_text.push_str("  Nobody is marked as the payer, so these are split among everyone.\n");
+            }
+            summary_text.push_str("\n");
+            if self.is_blocked() {
+                summary_text.push_str("SPLIT BLOCKED: assign the items above to see who owes what.\n");
+                self.summary_text.set(summary_text);
+                return;
+            }
+        }
+
+        if !treated.is_empty() {
+            let names: Vec<String> = participants
+                .iter()
+                .filter(|p| treated.contains(&p.id))
+                .map(|p| p.name.get())
+                .collect();
+            summary_text.push_str(&format!(
+                "Treated: {} (their share is {} among everyone else)\n\n",
+                names.join(", "),
+                self.treat_mode.get().description()
+            ));
+        }
+
+        // See `split::paid_amounts`
+        let payers = participants.iter().filter(|p| p.payer.get()).count();
+        if payers > 1 {
+            summary_text.push_str(&format!(
+                "The {} payers are taken to have paid equal parts of the bill, ₹{} each\n\n",
+                payers,
+                (total_price / Decimal::from(payers)).round_dp(2)
+            ));
+        }
 
         summary_text.push_str("Participants:\n");
         for participant in participants {
             summary_text.push_str(&format!("- Name: {} ", &participant.name.get()));
+            if participant.weight.get() != Decimal::ONE {
+                summary_text.push_str(&format!("(x{} share) ", participant.weight.get()));
+            }
             if participant.payer.get() {
                 summary_text.push_str(" [PAYER] \n");
             }else {
@@ -279,93 +943,775 @@ impl SplitItem {
             }
             summary_text.push_str("  Items:\n");
             for item in line_items.iter() {
-                if item.participants.get().clone().iter().any(|p| p.id == participant.id) {
-                    summary_text.push_str(&format!("    - {}: ₹{}\n", &item.item_name.get(), &item.price.get()));
+                if self.item_sharers(item).contains(&participant.id) {
+                    summary_text.push_str(&format!("    - {}: ₹{}\n", &item.display_name(), &item.price.get()));
                 }
             }
+            let treat = breakdown.get(&participant.id).map(|b| b.treat).unwrap_or_default();
+            if treated.contains(&participant.id) {
+                summary_text.push_str(&format!("  Treated: ₹{} covered by the group\n", -treat));
+            } else if !treat.is_zero() {
+                summary_text.push_str(&format!("  Covering treats: ₹{}\n", treat));
+            }
+            let cap = breakdown.get(&participant.id).map(|b| b.cap).unwrap_or_default();
+            if cap < Decimal::ZERO {
+                summary_text.push_str(&format!(
+                    "  Capped at ₹{}: ₹{} covered by {}\n",
+                    self.cap_for(&participant.id).unwrap_or_default(),
+                    -cap,
+                    sponsor_name.clone().unwrap_or(String::from("the group"))
+                ));
+            } else if cap > Decimal::ZERO {
+                summary_text.push_str(&format!("  Covering capped shares: ₹{}\n", cap));
+            }
             summary_text.push_str(&format!("  Total Amount Owed: ₹{}\n\n", final_split.get(&participant.id).unwrap_or(&Decimal::ZERO)));
         }
 
+        let shortfall = self.cap_shortfall.get();
+        if shortfall > Decimal::ZERO {
+            summary_text.push_str(&format!(
+                "CAPS NOT MET: nobody has room for ₹{} more, so it stays with the capped participants.\n",
+                shortfall
+            ));
+        }
+
         self.summary_text.set(summary_text);
     }
 
 
 
     fn calculate_split(&mut self) {
+        if self.is_blocked() {
+            self.final_split.update(|s| s.clear());
+            self.total_price.update(|p| *p = Decimal::new(0, 2));
+            self.update_summary_text();
+            return;
+        }
+
+        let mut breakdown: HashMap<Uuid, ShareBreakdown> = HashMap::new();
+        let item_shares = self.item_shares();
         for part in self.participants.get().iter() {
-        let current_part_split = self.line_items.get().iter().fold(
-            Decimal::new(0, 2),
-            |mut acc, x| {
-                if x.participants.get().iter().any(|p| p.id == part.id) {
-                    acc += x.get_split_by_participants().round_dp(2);
-                }
-                acc.round_dp(2)
-            },
-        );
-        let tax = self.tax_split();
+        let current_part_split = item_shares
+            .iter()
+            .filter(|(_, id, _)| *id == part.id)
+            .map(|(_, _, amount)| *amount)
+            .sum::<Decimal>()
+            .round_dp(2);
+        let entry = breakdown.entry(part.id).or_default();
         if self.is_involved_in_any_orders(part) {
-            self.final_split.update(|s| {
-                s.insert(part.id, Decimal::add(current_part_split, tax.round_dp(2)));
-            });
-        } else {
-            self.final_split.update(|s| {
-                s.insert(part.id, Decimal::from(0).round_dp(2));
-            });
+            entry.items = current_part_split;
+            entry.tax = self.tax_split(part).round_dp(2);
+            entry.tip = self.tip_split(part).round_dp(2);
         }
+        }
+        self.apply_discount(&mut breakdown);
+        self.redistribute_treats(&mut breakdown);
+        self.apply_caps(&mut breakdown);
+
+        self.final_split.set(breakdown.iter().map(|(id, b)| (*id, b.total())).collect());
+        self.breakdown.set(breakdown);
 
         let mut total = Decimal::new(0,0);
         for (_id, &amt) in self.final_split.get().iter() {
             total = Decimal::add(total, amt.round_dp(2));
         }
-        self.total_price.update(|p| *p = total.round_dp(2));
-        self.update_summary_text()
-        } 
-    }
+        self.total_price.update(|p| *p = total.round_dp(2));
+        self.update_summary_text()
+    }
+
+    /// Trims every share down to its cap. The overflow goes to the sponsor,
+    /// or is spread over the participants who still have room under theirs.
+    /// Treated participants never take any of it.
+    fn apply_caps(&self, breakdown: &mut HashMap<Uuid, ShareBreakdown>) {
+        self.cap_shortfall.set(Decimal::ZERO);
+        let participants = self.participants.get();
+        let treated = self.treated.get();
+        let mut overflow = Decimal::new(0, 2);
+        let mut trimmed: Vec<(Uuid, Decimal)> = Vec::new();
+        for part in participants.iter() {
+            if let (Some(cap), Some(b)) = (self.cap_for(&part.id), breakdown.get_mut(&part.id)) {
+                let excess = b.total() - cap;
+                if excess > Decimal::ZERO {
+                    b.cap -= excess;
+                    overflow += excess;
+                    trimmed.push((part.id, excess));
+                }
+            }
+        }
+        if overflow.is_zero() {
+            return;
+        }
+
+        let sponsor = self.sponsor.get().filter(|s| participants.iter().any(|p| p.id == *s));
+        if let Some(sponsor) = sponsor {
+            breakdown.entry(sponsor).or_default().cap += overflow;
+            return;
+        }
+
+        for _ in 0..participants.len() {
+            let room: Vec<(Uuid, Option<Decimal>)> = participants
+                .iter()
+                .filter(|p| !treated.contains(&p.id))
+                .filter_map(|p| {
+                    let total = breakdown.get(&p.id).map(|b| b.total()).unwrap_or_default();
+                    match self.cap_for(&p.id) {
+                        Some(cap) if total >= cap => None,
+                        Some(cap) => Some((p.id, Some(cap - total))),
+                        None => Some((p.id, None)),
+                    }
+                })
+                .collect();
+            if room.is_empty() {
+                break;
+            }
+            let pending = overflow;
+            let ids: Vec<Uuid> = room.iter().map(|(id, _)| *id).collect();
+            let last = ids.len() - 1;
+            for (i, (id, space)) in room.into_iter().enumerate() {
+                // The last one takes whatever rounding left over, so a cent
+                // can't be stranded with nobody's share big enough to take it
+                let share = if i == last { overflow } else { self.weighted_share(pending, &id, &ids).round_dp(2) };
+                let given = space.map_or(share, |space| share.min(space)).min(overflow);
+                breakdown.entry(id).or_default().cap += given;
+                overflow -= given;
+            }
+            if overflow <= Decimal::ZERO {
+                return;
+            }
+        }
+
+        // Everyone is at their cap, so the caps can't all be met. The rest
+        // goes back to the capped participants, in proportion to how far over
+        // they were, and the summary says so.
+        if overflow > Decimal::ZERO {
+            let total_trimmed: Decimal = trimmed.iter().map(|(_, excess)| *excess).sum();
+            for (id, excess) in trimmed.iter() {
+                breakdown.entry(*id).or_default().cap += (overflow * excess / total_trimmed).round_dp(2);
+            }
+            self.cap_shortfall.set(overflow);
+        }
+    }
+
+    /// Moves each treated participant's items and tax onto everyone who
+    /// isn't treated, either equally or in proportion to their own share.
+    fn redistribute_treats(&self, breakdown: &mut HashMap<Uuid, ShareBreakdown>) {
+        let treated = self.treated.get();
+        let others: Vec<Uuid> = self
+            .participants
+            .get()
+            .iter()
+            .map(|p| p.id)
+            .filter(|id| !treated.contains(id))
+            .collect();
+        if others.is_empty() {
+            return;
+        }
+
+        for id in treated.iter() {
+            let amount = breakdown.get(id).map(|b| b.total()).unwrap_or_default();
+            if amount.is_zero() {
+                continue;
+            }
+            let mut weights: Vec<(Uuid, Decimal)> = match self.treat_mode.get() {
+                TreatMode::Equal => others.iter().map(|o| (*o, self.weight_of(o))).collect(),
+                TreatMode::Proportional => others
+                    .iter()
+                    .map(|o| (*o, breakdown.get(o).map(|b| b.items + b.tax + b.tip + b.discount).unwrap_or_default()))
+                    .collect(),
+            };
+            if weights.iter().all(|(_, w)| w.is_zero()) {
+                weights = others.iter().map(|o| (*o, Decimal::ONE)).collect();
+            }
+            let total_weight: Decimal = weights.iter().map(|(_, w)| *w).sum();
+            for (other, weight) in weights {
+                breakdown.entry(other).or_default().treat += (amount * weight / total_weight).round_dp(2);
+            }
+            breakdown.entry(*id).or_default().treat -= amount;
+        }
+    }
+
+    fn get_split_amount_by_id(&self, id: Uuid) -> Option<Decimal> {
+       self.final_split.get().get(&id).cloned()
+    }
+
+    /// What everyone owes after the last `calculate_split`, by participant id.
+    pub fn final_shares(&self) -> HashMap<Uuid, Decimal> {
+        self.final_split.get_untracked()
+    }
+
+    /// Where each share in `final_shares` comes from.
+    pub fn breakdowns(&self) -> HashMap<Uuid, ShareBreakdown> {
+        self.breakdown.get_untracked()
+    }
+
+    pub fn summary(&self) -> String {
+        self.summary_text.get_untracked()
+    }
+
+    pub fn blocked(&self) -> bool {
+        untrack(|| self.is_blocked())
+    }
+
+    pub fn to_bill(&self) -> Bill {
+        Bill {
+            id: self.id,
+            event_name: self.event_name.get(),
+            total_tax: self.total_tax.get(),
+            tip: self.total_tip.get(),
+            discount: self.total_discount.get(),
+            receipt_total: self.receipt_total.get(),
+            unassigned_policy: self.unassigned_policy.get(),
+            treated: self.treated.get(),
+            treat_mode: self.treat_mode.get(),
+            caps: self.caps.get(),
+            bill_cap: self.bill_cap.get(),
+            sponsor: self.sponsor.get(),
+            settle_status: self.settle_status.get(),
+            finalized: self.finalized.get(),
+            participants: self
+                .participants
+                .get()
+                .iter()
+                .map(|p| BillParticipant {
+                    id: p.id,
+                    name: p.name.get(),
+                    payer: p.payer.get(),
+                    settled: p.settle_status.get(),
+                    weight: p.weight.get(),
+                })
+                .collect(),
+            items: self
+                .line_items
+                .get()
+                .iter()
+                .map(|li| BillItem {
+                    id: li.id,
+                    name: li.item_name.get(),
+                    price: li.price.get(),
+                    quantity: li.quantity.get(),
+                    category: li.category.get(),
+                    assignment: li.assignment.get(),
+                    participants: li.participants.get().iter().map(|p| p.id).collect(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Rebuilds the reactive bill from a snapshot and runs the split. Items
+    /// share the same `Participant` signals as the bill itself.
+    pub fn from_bill(bill: &Bill) -> Self {
+        let mut split = SplitItem::new();
+        split.id = bill.id;
+        for bp in bill.participants.iter() {
+            split.apply_op(&BillOp::AddParticipant(bp.clone()));
+        }
+        for bi in bill.items.iter() {
+            split.apply_op(&BillOp::AddItem(bi.clone()));
+        }
+        split.apply_op(&BillOp::UpdateSettings(bill.settings().into()));
+        split.calculate_split();
+        split
+    }
+
+    /// Applies a change in place, keeping the existing signals so rows that
+    /// are already on screen stay live. Call `calculate_split` afterwards.
+    /// Whether a finalized bill takes the op is up to `Bill::apply` and
+    /// `BillDoc::apply_local`; anything they accepted is shown.
+    pub fn apply_op(&mut self, op: &BillOp) {
+        match op {
+            BillOp::AddParticipant(bp) => {
+                if !self.participants.get_untracked().iter().any(|p| p.id == bp.id) {
+                    let mut participant = Participant::new(bp.name.clone());
+                    participant.id = bp.id;
+                    participant.payer.set(bp.payer);
+                    participant.settle_status.set(bp.settled);
+                    participant.update_weight(bp.weight);
+                    self.add_participant(participant);
+                }
+            }
+            BillOp::UpdateParticipant(patch) => {
+                if let Some(mut participant) = self.participants.get_untracked().into_iter().find(|p| p.id == patch.id) {
+                    if let Some(name) = &patch.name {
+                        participant.update_name(name.clone());
+                    }
+                    if let Some(payer) = patch.payer {
+                        participant.payer.set(payer);
+                    }
+                    if let Some(settled) = patch.settled {
+                        participant.settle_status.set(settled);
+                    }
+                    if let Some(weight) = patch.weight {
+                        participant.update_weight(weight);
+                    }
+                }
+            }
+            BillOp::RemoveParticipant(id) => {
+                for mut litem in self.line_items.get_untracked() {
+                    litem.remove_participant(id.to_string());
+                }
+                self.remove_participant(id.to_string());
+            }
+            BillOp::AddItem(bi) => {
+                if !self.line_items.get_untracked().iter().any(|li| li.id == bi.id) {
+                    let mut litem = LItem::new(bi.name.clone(), bi.price);
+                    litem.id = bi.id;
+                    litem.set_quantity(bi.quantity);
+                    litem.set_category(bi.category.clone());
+                    litem.set_assignment(bi.assignment.clone());
+                    self.set_item_participants(&mut litem, &bi.participants);
+                    self.add_line_item(litem);
+                }
+            }
+            BillOp::UpdateItem(patch) => {
+                if let Some(mut litem) = self.line_items.get_untracked().into_iter().find(|li| li.id == patch.id) {
+                    if let Some(name) = &patch.name {
+                        litem.update_name(name.clone());
+                    }
+                    if let Some(price) = patch.price {
+                        litem.update_price(price);
+                    }
+                    if let Some(quantity) = patch.quantity {
+                        litem.set_quantity(quantity);
+                    }
+                    if let Some(category) = &patch.category {
+                        litem.set_category(category.clone());
+                    }
+                    if let Some(assignment) = &patch.assignment {
+                        litem.set_assignment(assignment.clone());
+                    }
+                    if let Some(participants) = &patch.participants {
+                        self.set_item_participants(&mut litem, participants);
+                    }
+                }
+            }
+            BillOp::RemoveItem(id) => self.remove_line_item(id.to_string()),
+            BillOp::UpdateSettings(patch) => {
+                if let Some(event_name) = &patch.event_name {
+                    if self.event_name.get_untracked() != *event_name {
+                        self.add_event_name(event_name.clone());
+                    }
+                }
+                if let Some(total_tax) = patch.total_tax {
+                    self.add_total_tax(total_tax);
+                }
+                if let Some(tip) = patch.tip {
+                    self.add_total_tip(tip);
+                }
+                if let Some(discount) = patch.discount {
+                    self.add_total_discount(discount);
+                }
+                if let Some(receipt_total) = patch.receipt_total {
+                    self.set_receipt_total(receipt_total);
+                }
+                if let Some(policy) = patch.unassigned_policy {
+                    self.set_unassigned_policy(policy);
+                }
+                if let Some(treated) = &patch.treated {
+                    self.treated.set(treated.clone());
+                }
+                if let Some(treat_mode) = patch.treat_mode {
+                    self.set_treat_mode(treat_mode);
+                }
+                if let Some(caps) = &patch.caps {
+                    self.caps.set(caps.clone());
+                }
+                if let Some(bill_cap) = patch.bill_cap {
+                    self.set_bill_cap(bill_cap);
+                }
+                if let Some(sponsor) = patch.sponsor {
+                    self.set_sponsor(sponsor);
+                }
+                if let Some(settle_status) = patch.settle_status {
+                    self.settle_status.set(settle_status);
+                }
+                if let Some(finalized) = &patch.finalized {
+                    if self.finalized.get_untracked() != *finalized {
+                        self.finalized.set(finalized.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    fn set_item_participants(&self, litem: &mut LItem, ids: &[Uuid]) {
+        litem.clear_participants();
+        for participant in self.participants.get_untracked().into_iter().filter(|p| ids.contains(&p.id)) {
+            litem.add_participant(participant);
+        }
+    }
+}
+
+
+
+
+#[component]
+pub fn App() -> impl IntoView {
+    // Provides context that manages stylesheets, titles, meta tags, etc.
+    provide_meta_context();
+    view! {
+        // injects a stylesheet into the document <head>
+        // id=leptos means cargo-leptos will hot-reload this stylesheet
+        <Link rel="shortcut icon" type_="image/ico" href="./assets/favicon.ico"/>
+        <link rel="icon" type="image/png" sizes="192x192" href="./assets/android-chrome-192x192.png"/>
+        <link rel="icon" type="image/png" sizes="512x512" href="./assets/android-chrome-512x512.png"/>
+        <link rel="apple-touch-icon" href="./assets/apple-touch-icon.png"/>
+        <link rel="icon" type="image/png" sizes="16x16" href="./assets/favicon-16x16.png"/>
+        <link rel="icon" type="image/png" sizes="32x32" href="./assets/favicon-32x32.png"/>
+
+        <Stylesheet id="leptos" href="/pkg/leptos_start.css"/>
+        // sets the document title
+        <Title text="Whati8"/>
+
+        // content for this welcome page
+        <Router>
+            <main>
+                <Routes>
+                    <Route path="/" view=Homepage/>
+                    <Route path="/bill/:id" view=BillPage/>
+                    <Route path="/e/:id" view=EncryptedBillPage/>
+                    <Route path="/*any" view=NotFound/>
+                </Routes>
+            </main>
+        </Router>
+    }
+}
+
+
+
+/// A bill stored on the server, loaded by id and share token.
+#[component]
+fn BillPage() -> impl IntoView {
+    let params = use_params_map();
+    let query = use_query_map();
+    let token = move || query.with(|q| q.get("token").cloned().unwrap_or_default());
+    let bill = create_resource(
+        move || (params.with(|p| p.get("id").cloned().unwrap_or_default()), token()),
+        |(id, token)| async move {
+            let id = Uuid::from_str(id.as_str()).map_err(|e| ServerFnError::Args(e.to_string()))?;
+            load_bill(id, token).await
+        },
+    );
+
+    view! {
+        <Suspense fallback=move || view! { <p>"Loading bill..."</p> }>
+            {move || bill.get().map(|shared| match shared {
+                Ok(shared) => view! {
+                    <Homepage bill=shared.bill doc=shared.doc token=token() access=shared.access/>
+                }.into_view(),
+                Err(e) => view! { <h3>{format!("Couldn't load this bill: {}", e)}</h3> }.into_view(),
+            })}
+        </Suspense>
+    }
+}
+
+
+/// Where an end-to-end encrypted bill lives and the key that opens it. The
+/// key only ever exists in the browser.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EncryptedSession {
+    id: Uuid,
+    key: String,
+    version: i64,
+}
+
+
+/// An end-to-end encrypted bill. The key is read from the URL fragment and
+/// the bill is decrypted in the browser, so this only resolves client-side.
+#[component]
+fn EncryptedBillPage() -> impl IntoView {
+    let params = use_params_map();
+    let bill = create_local_resource(
+        move || params.with(|p| p.get("id").cloned().unwrap_or_default()),
+        |id| async move {
+            let id = Uuid::from_str(id.as_str()).map_err(|e| e.to_string())?;
+            let key = key_from_fragment(&window().location().hash().unwrap_or_default())
+                .ok_or_else(|| String::from("the link is missing its key"))?;
+            let stored = load_encrypted_bill(id).await.map_err(|e| e.to_string())?;
+            let bill = decrypt_bill(&stored.ciphertext, &key)?;
+            Ok::<_, String>((bill, EncryptedSession { id, key, version: stored.version }))
+        },
+    );
+
+    view! {
+        <Suspense fallback=move || view! { <p>"Decrypting bill..."</p> }>
+            {move || bill.get().map(|loaded| match loaded {
+                Ok((bill, session)) => view! { <Homepage bill=bill encrypted=session/> }.into_view(),
+                Err(e) => view! { <h3>{format!("Couldn't open this bill: {}", e)}</h3> }.into_view(),
+            })}
+        </Suspense>
+    }
+}
+
+
+#[component]
+fn SavedBills() -> impl IntoView {
+    let delete = create_action(|mine: &MyBill| {
+        let mine = mine.clone();
+        async move {
+            let result = delete_bill(mine.id, mine.edit_token).await;
+            if result.is_ok() {
+                forget_bill(mine.id);
+            }
+            result
+        }
+    });
+    let bills = create_local_resource(
+        move || delete.version().get(),
+        |_| list_bills(my_bills().into_iter().map(|b| b.edit_token).collect()),
+    );
 
-    fn get_split_amount_by_id(&self, id: Uuid) -> Option<Decimal> {
-       self.final_split.get().get(&id).cloned()
+    view! {
+        <div id="saved-bills-section" class="mt-4">
+            <label>Your saved bills</label>
+            <Transition fallback=move || view! { <p>"Loading..."</p> }>
+                {move || bills.get().map(|bills| match bills {
+                    Ok(bills) if bills.is_empty() => view! { <h3>No saved bills yet!</h3> }.into_view(),
+                    Ok(bills) => {
+                        let mine = my_bills();
+                        bills
+                            .into_iter()
+                            .filter_map(|bill| mine.iter().find(|m| m.id == bill.id).cloned().map(|m| (bill, m)))
+                            .map(|(bill, mine)| view! {
+                                <div class="flex items-center mt-2">
+                                    <A href=bill_path(mine.id, &mine.edit_token) class="flex-grow text-blue-500">
+                                        {if bill.event_name.is_empty() { String::from("Untitled bill") } else { bill.event_name.clone() }}
+                                    </A>
+                                    <button
+                                        on:click=move |_| delete.dispatch(mine.clone())
+                                        class="bg-red-500 text-white p-2 rounded-md ml-2">
+                                        Delete
+                                    </button>
+                                </div>
+                            })
+                            .collect_view()
+                    }
+                    Err(e) => view! { <h3>{format!("Couldn't list bills: {}", e)}</h3> }.into_view(),
+                })}
+            </Transition>
+        </div>
     }
 }
 
 
+/// The read-only and editor links of a bill, with controls to rotate or
+/// revoke them. Only rendered for editors.
+#[component]
+fn ShareLinks(id: Uuid, token: String) -> impl IntoView {
+    let token = store_value(token);
+    let links = create_local_resource(|| (), move |_| bill_links(id, token.get_value()));
+    // `Some(kind)` rotates that token, `None` revokes the read-only one
+    let change_links = create_action(move |change: &Option<Access>| {
+        let change = *change;
+        async move {
+            match change {
+                Some(kind) => rotate_token(id, token.get_value(), kind).await,
+                None => revoke_view_token(id, token.get_value()).await,
+            }
+        }
+    });
+
+    let navigate = use_navigate();
+    create_effect(move |_| {
+        if let Some(Ok(new_links)) = change_links.value().get() {
+            if new_links.edit_token != token.get_value() {
+                remember_bill(&new_links);
+                navigate(&new_links.edit_path(), Default::default());
+            }
+        }
+    });
 
+    let current_links = move || {
+        change_links
+            .value()
+            .get()
+            .and_then(|r| r.ok())
+            .or_else(|| links.get().and_then(|r| r.ok()))
+    };
+    let full_url = |path: String| format!("{}{}", window().location().origin().unwrap_or_default(), path);
 
-#[component]
-pub fn App() -> impl IntoView {
-    // Provides context that manages stylesheets, titles, meta tags, etc.
-    provide_meta_context();
     view! {
-        // injects a stylesheet into the document <head>
-        // id=leptos means cargo-leptos will hot-reload this stylesheet
-        <Link rel="shortcut icon" type_="image/ico" href="./assets/favicon.ico"/>
-        <link rel="icon" type="image/png" sizes="192x192" href="./assets/android-chrome-192x192.png"/>
-        <link rel="icon" type="image/png" sizes="512x512" href="./assets/android-chrome-512x512.png"/>
-        <link rel="apple-touch-icon" href="./assets/apple-touch-icon.png"/>
-        <link rel="icon" type="image/png" sizes="16x16" href="./assets/favicon-16x16.png"/>
-        <link rel="icon" type="image/png" sizes="32x32" href="./assets/favicon-32x32.png"/>
+        <div id="share-links-section" class="border-dashed border-2 border-blue-500 mt-4 p-4 rounded-md">
+            <label>Share this bill</label>
+            {move || current_links().map(|links| view! {
+                <div>
+                    <label for="view-link">Read-only link</label>
+                    <input type="text" id="view-link" readonly class="mt-2 p-2 border rounded-md w-full"
+                    value=links.view_path().map(full_url).unwrap_or(String::from("Revoked"))/>
+                    <label for="edit-link">Editor link (keep this one to yourself)</label>
+                    <input type="text" id="edit-link" readonly class="mt-2 p-2 border rounded-md w-full"
+                    value=full_url(links.edit_path())/>
+                </div>
+            })}
+            <div class="flex mt-2">
+                <button on:click=move |_| change_links.dispatch(Some(Access::ReadOnly))
+                class="p-2 border rounded-md bg-blue-500 text-white">New read-only link</button>
+                <button on:click=move |_| change_links.dispatch(None)
+                class="p-2 border rounded-md bg-red-500 text-white ml-2">Revoke read-only link</button>
+                <button on:click=move |_| change_links.dispatch(Some(Access::Editor))
+                class="p-2 border rounded-md bg-blue-500 text-white ml-2">New editor link</button>
+            </div>
+            {move || change_links.value().get().and_then(|r| r.err()).map(|e| format!("Couldn't update links: {}", e))}
+        </div>
+    }
+}
 
-        <Stylesheet id="leptos" href="/pkg/leptos_start.css"/>
-        // sets the document title
-        <Title text="Whati8"/>
 
-        // content for this welcome page
-        <Router>
-            <main>
-                <Routes>
-                    <Route path="/" view=Homepage/>
-                    <Route path="/*any" view=NotFound/>
-                </Routes>
-            </main>
-        </Router>
+/// Every saved change to a bill, a comparison between any two revisions and
+/// a way back to an older one. Only rendered for editors.
+#[component]
+fn BillHistory(id: Uuid, token: String, #[prop(into)] on_restore: Callback<()>) -> impl IntoView {
+    let token = store_value(token);
+    let (refreshes, set_refreshes) = create_signal(0);
+    let restore = create_action(move |revision: &i64| {
+        let revision = *revision;
+        async move { restore_revision(id, token.get_value(), revision, author_name()).await }
+    });
+    create_effect(move |_| {
+        if let Some(Ok(())) = restore.value().get() {
+            on_restore.call(());
+        }
+    });
+    let events = create_local_resource(
+        move || (refreshes.get(), restore.version().get()),
+        move |_| bill_history(id, token.get_value()),
+    );
+    let (compare_from, set_compare_from) = create_signal(None::<i64>);
+    let (compare_to, set_compare_to) = create_signal(None::<i64>);
+    let (author, set_author) = create_signal(author_name());
+    let when = |created_at: i64| {
+        String::from(Date::new(&JsValue::from_f64(created_at as f64 * 1000.0)).to_locale_string("default", &JsValue::UNDEFINED))
+    };
+
+    view! {
+        <div id="history-section" class="border-dashed border-2 border-blue-500 mt-4 p-4 rounded-md">
+            <label>History</label>
+            <div class="flex items-center mt-2">
+                <label for="author-name" class="mr-2">Your name in the history</label>
+                <input type="text" id="author-name" class="p-2 border rounded-md flex-grow"
+                value=author
+                on:change=move |ev| {
+                    let name = event_target_value(&ev);
+                    store_author_name(&name);
+                    set_author.set(name);
+                }/>
+                <button on:click=move |_| set_refreshes.update(|n| *n += 1)
+                class="p-2 border rounded-md bg-blue-500 text-white ml-2">Refresh</button>
+            </div>
+            <Transition fallback=move || view! { <p>"Loading..."</p> }>
+                {move || events.get().map(|events| match events {
+                    Ok(events) if events.is_empty() => view! { <h3>No changes saved yet!</h3> }.into_view(),
+                    Ok(events) => {
+                        let latest = events.last().map(|e| e.revision).unwrap_or_default();
+                        let from = compare_from.get().unwrap_or((latest - 1).max(0));
+                        let to = compare_to.get().unwrap_or(latest);
+                        let before = bill_at(id, &events, from);
+                        let after = bill_at(id, &events, to);
+                        let options = |selected: i64| {
+                            events
+                                .iter()
+                                .map(|e| view! {
+                                    <option value=e.revision.to_string() selected=e.revision == selected>
+                                        {format!("Revision {}", e.revision)}
+                                    </option>
+                                })
+                                .collect_view()
+                        };
+
+                        let mut bill = Bill::empty(id);
+                        let mut revisions = Vec::new();
+                        for event in events.iter() {
+                            let changes: Vec<String> = event.ops.iter().flat_map(|op| describe_op(&bill, op)).collect();
+                            event.ops.iter().for_each(|op| bill.apply(op));
+                            revisions.push((event.clone(), changes));
+                        }
+                        revisions.reverse();
+
+                        view! {
+                            <div class="flex items-center mt-2">
+                                <label class="mr-2">Compare</label>
+                                <select class="p-2 border rounded-md"
+                                on:change=move |ev| set_compare_from.set(event_target_value(&ev).parse().ok())>
+                                    <option value="0" selected=from == 0>"Empty bill"</option>
+                                    {options(from)}
+                                </select>
+                                <label class="mx-2">with</label>
+                                <select class="p-2 border rounded-md"
+                                on:change=move |ev| set_compare_to.set(event_target_value(&ev).parse().ok())>
+                                    {options(to)}
+                                </select>
+                            </div>
+                            <ul class="mt-2 font-bold">
+                                {describe_share_changes(&before, &after).into_iter().map(|line| view! { <li>{line}</li> }).collect_view()}
+                            </ul>
+                            <ul class="mt-2">
+                                {describe_changes(&before, &after).into_iter().map(|line| view! { <li>{line}</li> }).collect_view()}
+                            </ul>
+                            {revisions
+                                .into_iter()
+                                .map(|(event, changes)| {
+                                    let revision = event.revision;
+                                    view! {
+                                        <div class="mt-2 p-2 border rounded-md">
+                                            <div class="flex items-center">
+                                                <span class="flex-grow">
+                                                    {format!(
+                                                        "Revision {} by {} on {}",
+                                                        revision,
+                                                        if event.author.is_empty() { "someone" } else { event.author.as_str() },
+                                                        when(event.created_at)
+                                                    )}
+                                                    {event.restored_from.map(|r| format!(" (restored revision {})", r))}
+                                                </span>
+                                                {(revision != latest).then(|| view! {
+                                                    <button on:click=move |_| restore.dispatch(revision)
+                                                    disabled=move || restore.pending().get()
+                                                    class="p-2 border rounded-md bg-blue-500 text-white ml-2">Restore</button>
+                                                })}
+                                            </div>
+                                            <ul class="text-sm">
+                                                {changes.into_iter().map(|line| view! { <li>{line}</li> }).collect_view()}
+                                            </ul>
+                                        </div>
+                                    }
+                                })
+                                .collect_view()}
+                        }.into_view()
+                    }
+                    Err(e) => view! { <h3>{format!("Couldn't load the history: {}", e)}</h3> }.into_view(),
+                })}
+            </Transition>
+            {move || restore.value().get().and_then(|r| r.err()).map(|e| format!("Couldn't restore: {}", e))}
+        </div>
     }
 }
 
 
-
 #[component]
-fn Homepage() -> impl IntoView {
-    let (participants, set_participants) = create_signal(Participants::new());
-    let (split_item, set_split_item) = create_signal(SplitItem::new());
+fn Homepage(
+    /// A bill loaded from the server. Without one the page starts empty.
+    #[prop(optional)]
+    bill: Option<Bill>,
+    /// The server's record of edits to `bill`, merged with edits made here.
+    #[prop(optional)]
+    doc: Option<BillDoc>,
+    /// The share token the bill was opened with.
+    #[prop(optional)]
+    token: Option<String>,
+    #[prop(optional)]
+    access: Option<Access>,
+    /// Set when the bill is end-to-end encrypted; saving re-encrypts it.
+    #[prop(optional)]
+    encrypted: Option<EncryptedSession>,
+) -> impl IntoView {
+    let is_encrypted = encrypted.is_some();
+    let encrypted = store_value(encrypted);
+    let saved_id = bill.as_ref().map(|b| b.id);
+    let read_only = access == Some(Access::ReadOnly);
+    let token = store_value(token.unwrap_or_default());
+    let initial = bill.as_ref().map(SplitItem::from_bill).unwrap_or_else(SplitItem::new);
+    let (participants, set_participants) = create_signal(Participants(initial.participants.get_untracked()));
+    let (litems, set_litems) = create_signal(LItems(initial.line_items.get_untracked()));
+    let (split_item, set_split_item) = create_signal(initial);
     let mark_only_one_payer = move |id: String| {
         participants
         .get()
@@ -406,7 +1752,6 @@ fn Homepage() -> impl IntoView {
     
 
 
-    let (litems, set_litems) = create_signal(LItems::new());
     let remove_participant = move |id: String| {
     if !id.is_empty() {
             set_participants.update(|participants| {
@@ -449,11 +1794,24 @@ fn Homepage() -> impl IntoView {
     let litem_name_ref = create_node_ref::<Input>();
     let litem_price_ref = create_node_ref::<Input>();
     let litem_parts_ref = create_node_ref::<Select>();
+    let litem_assignment_ref = create_node_ref::<Select>();
     let split_item_ref_name = create_node_ref::<Input>();
     let split_item_total_price = create_node_ref::<Input>();
     let split_item_total_tax = create_node_ref::<Input>();
 
 
+    let finalized = move || split_item.get().finalized.get().is_some();
+    let toggle_finalized = move || {
+        let mut split = split_item.get();
+        if finalized() {
+            split.unlock();
+        } else {
+            split.calculate_split();
+            split.finalize();
+        }
+        split.update_summary_text();
+    };
+
     let update_split_event_name = move |name: String| {
         let mut split_i = split_item.get();
         split_i.add_event_name(name);
@@ -467,12 +1825,81 @@ fn Homepage() -> impl IntoView {
         split_item.get().calculate_split();
     };
 
+    let update_split_total_tip = move |value: Decimal| {
+        let mut split_i = split_item.get();
+        split_i.add_total_tip(value.max(Decimal::ZERO));
+        split_item.get().calculate_split();
+    };
+
+    let update_split_total_discount = move |value: Decimal| {
+        let mut split_i = split_item.get();
+        split_i.add_total_discount(value.max(Decimal::ZERO));
+        split_item.get().calculate_split();
+    };
+
+
+    let update_unassigned_policy = move |value: String| {
+        let mut split_i = split_item.get();
+        split_i.set_unassigned_policy(UnassignedPolicy::from_str(value.as_str()).unwrap_or_default());
+        split_item.get().calculate_split();
+    };
+
+
+    let update_treat_mode = move |value: String| {
+        let mut split_i = split_item.get();
+        split_i.set_treat_mode(TreatMode::from_str(value.as_str()).unwrap_or_default());
+        split_item.get().calculate_split();
+    };
+
+
+    let update_bill_cap = move |value: String| {
+        let mut split_i = split_item.get();
+        split_i.set_bill_cap(Decimal::from_str_exact(value.trim()).ok());
+        split_item.get().calculate_split();
+    };
+
+
+    let update_sponsor = move |value: String| {
+        let mut split_i = split_item.get();
+        split_i.set_sponsor(Uuid::from_str(value.as_str()).ok());
+        split_item.get().calculate_split();
+    };
+
+
+    let update_split_receipt_total = move |value: String| {
+        let mut split_i = split_item.get();
+        split_i.set_receipt_total(Decimal::from_str_exact(value.trim()).ok());
+        split_item.get().calculate_split();
+    };
+
+
+    // Closes the gap to the receipt: a receipt above the computed total gets
+    // an "Unitemised" line shared by everyone, one below it a bigger
+    // discount, since items can't have a negative price.
+    let reconcile_receipt = move || {
+        let mut split_i = split_item.get();
+        match split_i.receipt_difference() {
+            Some(difference) if difference > Decimal::ZERO => {
+                let new = LItem::new(String::from("Unitemised"), difference);
+                new.set_assignment(Assignment::Everyone);
+                set_litems.update(|l| l.add(new.clone()));
+                split_i.add_line_item(new);
+            }
+            Some(difference) if difference < Decimal::ZERO => {
+                split_i.add_total_discount(split_i.total_discount.get_untracked() - difference);
+            }
+            _ => return,
+        }
+        split_item.get().calculate_split();
+    };
+
 
 
     let mut add_line_item = move || {
         let litem_name_input = litem_name_ref.get().unwrap();
         let litem_price_input = litem_price_ref.get().unwrap();
         let litem_parts_input = litem_parts_ref.get().unwrap();
+        let litem_assignment_input = litem_assignment_ref.get().unwrap();
         let name = String::from(litem_name_input.value().trim());
         let price = Decimal::from_str_exact(litem_price_input.value().as_str()).unwrap_or(Decimal::from(0));
         let parts = litem_parts_input.selected_options();
@@ -496,10 +1923,18 @@ fn Homepage() -> impl IntoView {
             .map(|p| participants.get().0.iter().find(|participant| participant.id.to_string() == *p).cloned().unwrap())
             .collect();
 
-            for participant in selected_participants {
-                new.add_participant(participant.clone());
-                parts_selected.clear();
+            match Assignment::from_mode(litem_assignment_input.value().as_str(), Vec::new()) {
+                Assignment::Selected => {
+                    for participant in selected_participants {
+                        new.add_participant(participant.clone());
+                    }
+                }
+                Assignment::Everyone => new.set_assignment(Assignment::Everyone),
+                Assignment::EveryoneExcept(_) => new.set_assignment(Assignment::EveryoneExcept(
+                    selected_participants.iter().map(|p| p.id).collect(),
+                )),
             }
+            parts_selected.clear();
             split_item.get().add_line_item(new);
             split_item.get().calculate_split();
             litem_name_input.set_value("");
@@ -551,6 +1986,296 @@ fn Homepage() -> impl IntoView {
         }
     });
 
+    let load_into_page = move |bill: &Bill| {
+        untrack(|| {
+            let split = SplitItem::from_bill(bill);
+            set_participants.set(Participants(split.participants.get_untracked()));
+            set_litems.set(LItems(split.line_items.get_untracked()));
+            set_split_item.set(split);
+        });
+    };
+
+    // Adds edits made in bulk, such as imported items, to what's on the page.
+    // They go out to everyone like any other change.
+    let add_to_bill = move |ops: Vec<BillOp>| {
+        let mut split = split_item.get_untracked();
+        if split.is_finalized() {
+            return;
+        }
+        for op in ops.iter() {
+            split.apply_op(op);
+        }
+        split.calculate_split();
+        set_participants.set(Participants(split.participants.get_untracked()));
+        set_litems.set(LItems(split.line_items.get_untracked()));
+    };
+
+    let (items_csv_rows, set_items_csv_rows) = create_signal(None::<Result<Vec<ImportRow>, String>>);
+    let (create_missing, set_create_missing) = create_signal(false);
+    let on_items_csv = move |ev: web_sys::Event| {
+        read_picked_file(&ev, move |text| set_items_csv_rows.set(Some(text.and_then(|t| parse_items_csv(&t)))));
+    };
+    let commit_items_csv = move || {
+        if let Some(Ok(rows)) = items_csv_rows.get_untracked() {
+            add_to_bill(import_ops(&split_item.get_untracked().to_bill(), &rows, create_missing.get_untracked()));
+        }
+        set_items_csv_rows.set(None);
+    };
+
+    // A receipt pasted as text is read into a `Receipt` and shown for review
+    // before anything is added.
+    let (receipt_text, set_receipt_text) = create_signal(String::new());
+    let (receipt, set_receipt) = create_signal(None::<Receipt>);
+    let commit_receipt = move || {
+        if let Some(receipt) = receipt.get_untracked() {
+            add_to_bill(receipt.to_ops(&split_item.get_untracked().to_bill()));
+        }
+        set_receipt.set(None);
+        set_receipt_text.set(String::new());
+    };
+
+    // Live collaboration on server bills. Local edits are diffed against the
+    // last shown snapshot and stamped into a `BillDoc`, which is kept in local
+    // storage and sent to everyone connected. The doc is merged with the
+    // server's on load, on save and whenever the browser comes back online,
+    // so edits made offline on several devices end up the same everywhere.
+    // Encrypted bills stay off the socket since ops are plaintext.
+    let live_doc = store_value(saved_id.filter(|_| !is_encrypted).map(|id| {
+        let mut live = doc.unwrap_or_else(|| BillDoc::from_bill(&untrack(|| split_item.get().to_bill())));
+        if let Some(offline) = stored_doc(id) {
+            live.merge(&offline);
+        }
+        live
+    }));
+    let synced = store_value(untrack(|| split_item.get().to_bill()));
+    // Shows the bill as the doc has it, touching only what changed.
+    let show_doc = move || {
+        let Some(bill) = live_doc.with_value(|live| live.as_ref().map(BillDoc::to_bill)) else {
+            return;
+        };
+        let ops = synced.get_value().diff(&bill);
+        if ops.is_empty() {
+            return;
+        }
+        synced.set_value(bill);
+        let mut split = split_item.get_untracked();
+        for op in ops.iter() {
+            split.apply_op(op);
+        }
+        split.calculate_split();
+        set_participants.set(Participants(split.participants.get_untracked()));
+        set_litems.set(LItems(split.line_items.get_untracked()));
+    };
+    let sync_with_server = move || async move {
+        let (Some(id), Some(live)) = (saved_id, live_doc.get_value()) else {
+            return Ok(());
+        };
+        let merged = sync_bill(id, token.get_value(), live, author_name()).await?;
+        live_doc.update_value(|live| {
+            if let Some(live) = live {
+                live.merge(&merged);
+                store_doc(live);
+            }
+        });
+        show_doc();
+        Ok::<_, ServerFnError>(())
+    };
+    let sync = create_action(move |_: &()| sync_with_server());
+    if let (Some(id), false) = (saved_id, is_encrypted) {
+        let actor = device_actor();
+        // The message handler lives as long as its socket and is dropped on
+        // reconnect, rather than leaked.
+        type OnMessage = Closure<dyn FnMut(web_sys::MessageEvent)>;
+        let socket = store_value(None::<(web_sys::WebSocket, OnMessage)>);
+        let connect = move || {
+            socket.with_value(|old| {
+                if let Some((old, _)) = old {
+                    old.set_onmessage(None);
+                    let _ = old.close();
+                }
+            });
+            let location = window().location();
+            let scheme = if location.protocol().unwrap_or_default() == "https:" { "wss" } else { "ws" };
+            let url = format!(
+                "{}://{}/ws/bill/{}?token={}&author={}",
+                scheme,
+                location.host().unwrap_or_default(),
+                id,
+                token.get_value(),
+                String::from(web_sys::js_sys::encode_uri_component(&author_name()))
+            );
+            let Ok(ws) = web_sys::WebSocket::new(&url) else {
+                log::error!("Couldn't connect to live updates");
+                return;
+            };
+            let on_message = Closure::<dyn FnMut(web_sys::MessageEvent)>::new(move |ev: web_sys::MessageEvent| {
+                let Some(ops) = ev
+                    .data()
+                    .as_string()
+                    .and_then(|text| serde_json::from_str::<Vec<StampedOp>>(&text).ok())
+                else {
+                    return;
+                };
+                live_doc.update_value(|live| {
+                    if let Some(live) = live {
+                        for op in ops.iter() {
+                            live.apply(op);
+                        }
+                        store_doc(live);
+                    }
+                });
+                show_doc();
+            });
+            ws.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+            socket.set_value(Some((ws, on_message)));
+        };
+        create_effect(move |_| {
+            connect();
+            if !read_only {
+                sync.dispatch(());
+            }
+        });
+        window_event_listener(ev::online, move |_| {
+            connect();
+            if !read_only {
+                sync.dispatch(());
+            }
+        });
+        create_effect(move |_| {
+            let current = split_item.get().to_bill();
+            let ops = synced.get_value().diff(&current);
+            if ops.is_empty() {
+                return;
+            }
+            synced.set_value(current);
+            let mut stamped = Vec::new();
+            live_doc.update_value(|live| {
+                if let Some(live) = live {
+                    stamped = live.apply_local(actor, &ops);
+                    store_doc(live);
+                }
+            });
+            let ws = socket.with_value(|s| s.as_ref().map(|(ws, _)| ws.clone()));
+            if let (Some(ws), Ok(json)) = (ws, serde_json::to_string(&stamped)) {
+                if ws.send_with_str(&json).is_err() {
+                    log::error!("Couldn't send live update");
+                }
+            }
+        });
+    }
+
+    // A bill shared through the URL fragment never reaches the server, so it
+    // can only be picked up once the page is running in the browser.
+    let (share_error, set_share_error) = create_signal(None::<String>);
+    if saved_id.is_none() {
+        create_effect(move |_| {
+            let hash = window().location().hash().unwrap_or_default();
+            match bill_from_fragment(&hash) {
+                Some(Ok(bill)) => load_into_page(&bill),
+                Some(Err(e)) => set_share_error.set(Some(e)),
+                None => {}
+            }
+        });
+    }
+
+    let export_bill = move || {
+        let bill = split_item.get().to_bill();
+        if download(&bill_file_name(&bill, "whati8.json"), "application/json", &bill.to_export_json()).is_err() {
+            log::error!("Couldn't export the bill");
+        }
+    };
+    let (csv_error, set_csv_error) = create_signal(None::<String>);
+    let export_csv = move |name: &str, to_csv: fn(&SplitItem) -> Result<String, String>| {
+        let split = split_item.get();
+        match to_csv(&split) {
+            Ok(csv) => {
+                set_csv_error.set(None);
+                if download(&bill_file_name(&split.to_bill(), name), "text/csv", &csv).is_err() {
+                    log::error!("Couldn't export the CSV");
+                }
+            }
+            Err(e) => set_csv_error.set(Some(e)),
+        }
+    };
+    // An imported bill replaces what's on the page but keeps a saved bill's
+    // id, so it goes out as edits like any other change.
+    let (import_error, set_import_error) = create_signal(None::<String>);
+    let import_bill = move |json: String| {
+        if finalized() {
+            set_import_error.set(Some(String::from("unlock the bill before importing over it")));
+            return;
+        }
+        match Bill::from_export_json(&json) {
+            Ok(mut bill) => {
+                if let Some(id) = saved_id {
+                    bill.id = id;
+                }
+                load_into_page(&bill);
+                set_import_error.set(None);
+            }
+            Err(e) => set_import_error.set(Some(e.to_string())),
+        }
+    };
+    let on_import = move |ev: web_sys::Event| {
+        read_picked_file(&ev, move |text| match text {
+            Ok(text) => import_bill(text),
+            Err(e) => set_import_error.set(Some(e)),
+        });
+    };
+
+    let (fragment_link, set_fragment_link) = create_signal(None::<Result<String, String>>);
+    let share_in_link = move || {
+        let link = bill_fragment(&split_item.get().to_bill())
+            .map(|fragment| format!("{}/{}", window().location().origin().unwrap_or_default(), fragment));
+        set_fragment_link.set(Some(link));
+    };
+
+    let navigate = use_navigate();
+    let save_to_server = create_action(move |bill: &Bill| {
+        let bill = bill.clone();
+        async move {
+            if let Some(session) = encrypted.get_value() {
+                let ciphertext = encrypt_bill(&bill, &session.key).map_err(ServerFnError::ServerError)?;
+                let token = write_token(session.id, &session.key).map_err(ServerFnError::ServerError)?;
+                let version = save_encrypted_bill(session.id, ciphertext, session.version, token).await?;
+                encrypted.update_value(|s| {
+                    if let Some(s) = s {
+                        s.version = version;
+                    }
+                });
+                return Ok(None);
+            }
+            match saved_id {
+                Some(_) => sync_with_server().await.map(|_| None),
+                None => create_bill(bill, author_name()).await.map(Some),
+            }
+        }
+    });
+    let navigate_after_save = navigate.clone();
+    create_effect(move |_| {
+        if let Some(Ok(Some(links))) = save_to_server.value().get() {
+            remember_bill(&links);
+            navigate_after_save(&links.edit_path(), Default::default());
+        }
+    });
+
+    let save_encrypted = create_action(|bill: &Bill| {
+        let bill = bill.clone();
+        async move {
+            let key = generate_key().map_err(ServerFnError::ServerError)?;
+            let ciphertext = encrypt_bill(&bill, &key).map_err(ServerFnError::ServerError)?;
+            let id = Uuid::new_v4();
+            let token = write_token(id, &key).map_err(ServerFnError::ServerError)?;
+            create_encrypted_bill(id, ciphertext, token).await?;
+            Ok::<_, ServerFnError>(encrypted_bill_path(id, &key))
+        }
+    });
+    create_effect(move |_| {
+        if let Some(Ok(path)) = save_encrypted.value().get() {
+            navigate(&path, Default::default());
+        }
+    });
+
     let on_submit = move |ev: SubmitEvent| {
         ev.prevent_default();
         add_participant()
@@ -561,6 +2286,23 @@ fn Homepage() -> impl IntoView {
         add_line_item()
     };
 
+    // Quick entry adds every line it can read and leaves the rest in the
+    // box, next to what's wrong with them.
+    let (quick_text, set_quick_text) = create_signal(String::new());
+    let (quick_errors, set_quick_errors) = create_signal(Vec::<(String, String)>::new());
+    let add_quick_entry = move || {
+        let bill = split_item.get_untracked().to_bill();
+        let lines = parse_quick_entry(&quick_text.get_untracked(), &bill);
+        add_to_bill(quick_entry_ops(&bill, &lines));
+        let failed: Vec<QuickLine> = lines.into_iter().filter(|line| line.entry.is_err()).collect();
+        set_quick_text.set(failed.iter().map(|line| line.text.as_str()).collect::<Vec<_>>().join("\n"));
+        set_quick_errors.set(failed.into_iter().filter_map(|line| line.entry.err().map(|e| (line.text, e))).collect());
+    };
+    let on_submit_quick = move |ev: SubmitEvent| {
+        ev.prevent_default();
+        add_quick_entry()
+    };
+
 
     let edit_participant_name = move |value: String, id: String| {
         let mut part = participants.get().0.iter().find(|p| p.id.to_string()== id).unwrap().clone();
@@ -583,6 +2325,23 @@ fn Homepage() -> impl IntoView {
     };
 
 
+    let edit_item_quantity = move |value: String, id: String| {
+        let Ok(quantity) = value.trim().parse::<u32>() else {
+            return;
+        };
+        let litem = litems.get().0.iter().find(|l| l.id.to_string() == id).unwrap().clone();
+        litem.set_quantity(quantity);
+        split_item.get().calculate_split();
+    };
+
+
+    let edit_item_category = move |value: String, id: String| {
+        let litem = litems.get().0.iter().find(|l| l.id.to_string() == id).unwrap().clone();
+        litem.set_category(value.trim().to_string());
+        split_item.get().calculate_split();
+    };
+
+
     let edit_participant_payer = move |id: String| {
         let mut part = participants.get().0.iter().find(|p| p.id.to_string()== id).unwrap().clone();
         part.mark_as_payer();
@@ -592,6 +2351,41 @@ fn Homepage() -> impl IntoView {
 
 
 
+    let cap_note = move |id: Uuid| {
+        let split_i = split_item.get();
+        let cap = split_i.breakdown.get().get(&id).map(|b| b.cap).unwrap_or_default();
+        if cap < Decimal::ZERO {
+            Some(format!("₹{} over cap, covered by {}", -cap, split_i.sponsor_name().unwrap_or(String::from("the group"))))
+        } else if cap > Decimal::ZERO {
+            Some(format!("incl. ₹{} covering capped shares", cap))
+        } else {
+            None
+        }
+    };
+
+
+    let edit_participant_weight = move |value: String, id: String| {
+        if let Ok(weight) = Decimal::from_str_exact(value.trim()) {
+            let part = participants.get().0.iter().find(|p| p.id.to_string() == id).unwrap().clone();
+            part.update_weight(weight);
+            split_item.get().calculate_split();
+        }
+    };
+
+
+    let edit_participant_cap = move |value: String, id: String| {
+        split_item.get().set_participant_cap(Uuid::from_str(id.as_str()).unwrap(), Decimal::from_str_exact(value.trim()).ok());
+        split_item.get().calculate_split();
+    };
+
+
+    let edit_participant_treated = move |id: String| {
+        split_item.get().toggle_treated(Uuid::from_str(id.as_str()).unwrap());
+        split_item.get().calculate_split();
+    };
+
+
+
     let edit_selected_parts = move |id: String, event: web_sys::Event| {
     let mut litem = litems.get().0.iter().find(|l| l.id.to_string() == id).unwrap().clone();
     if let Some(target) = event.target() {
@@ -608,11 +2402,22 @@ fn Homepage() -> impl IntoView {
                 }
             }
 
-            litem.clear_participants();
-
-            for participant in parts_selected {
-                if let Some(part) = participants.get().0.iter().find(|p| p.id.to_string() == participant) {
-                    litem.add_participant(part.clone());
+            if let Assignment::EveryoneExcept(_) = litem.assignment.get() {
+                let excluded = participants
+                    .get()
+                    .0
+                    .iter()
+                    .filter(|p| parts_selected.contains(&p.id.to_string()))
+                    .map(|p| p.id)
+                    .collect();
+                litem.set_assignment(Assignment::EveryoneExcept(excluded));
+            } else {
+                litem.clear_participants();
+
+                for participant in parts_selected {
+                    if let Some(part) = participants.get().0.iter().find(|p| p.id.to_string() == participant) {
+                        litem.add_participant(part.clone());
+                    }
                 }
             }
             split_item.get().calculate_split();
@@ -621,9 +2426,19 @@ fn Homepage() -> impl IntoView {
     };
 
 
+    let edit_item_assignment = move |mode: String, id: String| {
+        let litem = litems.get().0.iter().find(|l| l.id.to_string() == id).unwrap().clone();
+        litem.set_assignment(Assignment::from_mode(mode.as_str(), Vec::new()));
+        split_item.get().calculate_split();
+    };
+
+
     let check_if_litem_participant = move |part_id: String, litem_id: String| {
         if let Some(litem) = litems.get().0.iter().find(|l| l.id.to_string() == litem_id) {
-            litem.participants.get().iter().any(|p| p.id.to_string() == part_id)
+            match litem.assignment.get() {
+                Assignment::EveryoneExcept(excluded) => excluded.iter().any(|id| id.to_string() == part_id),
+                _ => litem.participants.get().iter().any(|p| p.id.to_string() == part_id),
+            }
         } else {
             false
         }
@@ -646,6 +2461,8 @@ fn Homepage() -> impl IntoView {
                         </div>
                         <label for="e-name">Event name</label>
                         <input type="text" id="e-name"
+                        value=move || split_item.get().event_name.get()
+                        disabled=move || read_only || finalized()
                         on:input=move |ev| update_split_event_name(event_target_value(&ev))
                         placeholder="Enter the event name.." class="mt-2 p-2 border rounded-md w-full" />
                     </div>
@@ -655,6 +2472,20 @@ fn Homepage() -> impl IntoView {
                                         {
                                         view! {
                                           <div>
+                                            {move || {
+                                                let unassigned = split_item.get().unassigned_items();
+                                                (!unassigned.is_empty()).then(|| view! {
+                                                    <div class="border-2 border-red-500 mt-4 p-4 rounded-md">
+                                                        <label>{format!("{} item(s) not assigned to anyone", unassigned.len())}</label>
+                                                        <ul class="list-disc ml-4">
+                                                            {unassigned.into_iter().map(|li| view! {
+                                                                <li>{li.display_name()} ": ₹" {li.price.get().to_string()}</li>
+                                                            }).collect_view()}
+                                                        </ul>
+                                                        <span class="text-sm">{split_item.get().unassigned_policy.get().description()}</span>
+                                                    </div>
+                                                })
+                                            }}
                                             <form on:submit=on_submit>
                                             <div class="border-dashed border-2 border-pink-500 mt-4 p-4 rounded-md ">
                                                  <label for="total-amount">Total Amount</label>
@@ -675,6 +2506,7 @@ fn Homepage() -> impl IntoView {
                                                                         value=split_item.get().final_split.get().get(&part.id).unwrap_or(&Decimal::from(0)).to_string()
 
                                                                         id=format!("participant-amount_{}", part.id) placeholder="Enter amount" class="w-full mt-2 p-2 border rounded" readonly/>
+                                                                        <span class="text-sm ml-2">{move || cap_note(part.id)}</span>
                                                                         </div>
                                                                     </div>
                                                                 }
@@ -685,6 +2517,7 @@ fn Homepage() -> impl IntoView {
                                                                      <input type="text"
                                                                      value=split_item.get().final_split.get().get(&part.id).unwrap_or(&Decimal::from(0)).to_string()
                                                                      id=format!("participant-amount_{}", part.id) placeholder="Enter amount" class="w-full mt-2 p-2 border rounded" readonly/>
+                                                                        <span class="text-sm ml-2">{move || cap_note(part.id)}</span>
                                                                  </div>
                                                               }
                                                          }
@@ -692,9 +2525,42 @@ fn Homepage() -> impl IntoView {
                                                          </For>
                                                      </div>
                                             </form> 
+                                              {move || split_item.get().receipt_difference().map(|difference| {
+                                                  if difference.is_zero() {
+                                                      view! {
+                                                          <div class="border-2 border-green-500 mt-4 p-4 rounded-md">
+                                                              "Matches the receipt total"
+                                                          </div>
+                                                      }
+                                                  } else {
+                                                      view! {
+                                                          <div class="border-2 border-yellow-500 mt-4 p-4 rounded-md">
+                                                              <label>"Receipt differs by ₹" {difference.to_string()}</label>
+                                                              <button type="button"
+                                                              on:click=move |_| reconcile_receipt()
+                                                              disabled=move || read_only || finalized() || !participants_exists()
+                                                              class="mt-2 p-2 border rounded-md w-full bg-blue-500 text-white">
+                                                                  {if difference > Decimal::ZERO {
+                                                                      "Add difference as Unitemised line"
+                                                                  } else {
+                                                                      "Add difference as a discount"
+                                                                  }}
+                                                              </button>
+                                                              <span class="text-sm">"or fix the items by hand"</span>
+                                                          </div>
+                                                      }
+                                                  }
+                                              })}
                                               <div class="space-y-2">
                                                <textarea id="summary" class="w-full h-full mt-4 p-2 border rounded" rows="20" readonly>{split_item.get().summary_text.get().clone()}</textarea>
                                                 </div>
+                                              {(!read_only).then(|| view! {
+                                                  <button type="button"
+                                                  on:click=move |_| toggle_finalized()
+                                                  class="mt-2 p-2 border rounded-md w-full bg-gray-700 text-white">
+                                                      {move || if finalized() { "Unlock to make changes" } else { "Finalize and lock the bill" }}
+                                                  </button>
+                                              })}
                                           </div>
                                         }
                                     }else {
@@ -708,6 +2574,7 @@ fn Homepage() -> impl IntoView {
                                     }
 
                     </div>
+                    <fieldset disabled=move || read_only || finalized()>
                     <div id="add-participant-section" class=" mt-4">
                                 <div class="w-full pr-2">
                                     <label for="participant-dropdown">Participants</label>
@@ -739,6 +2606,14 @@ fn Homepage() -> impl IntoView {
                                                                      checked=part.is_payer()
                                                                      class="mb-2 p-2 ml-2"
                                                                  />
+                                                                 <label class="mb-2 ml-2">Treated</label>
+                                                                 <input
+                                                                     type="checkbox"
+                                                                     on:click=move |_| edit_participant_treated(part.id.to_string())
+                                                                     name="is-treated"
+                                                                     checked=move || split_item.get().is_treated(&part.id)
+                                                                     class="mb-2 p-2 ml-2"
+                                                                 />
                                                                  <button
                                                                      on:click=move |_| remove_participant(part.id.to_string())
                                                                      class="bg-red-500 text-white p-2 rounded-md ml-2 sm:ml-2"
@@ -747,6 +2622,18 @@ fn Homepage() -> impl IntoView {
                                                                  </button>
                                                              </div>
                                                     </div>
+                                                    <div class="flex items-center">
+                                                        <label class="mr-2">Cap</label>
+                                                        <input type="number" step="0.01"
+                                                        on:input=move |ev| edit_participant_cap(event_target_value(&ev), part.id.to_string())
+                                                        value=move || split_item.get().caps.get().get(&part.id).map(|c| c.to_string()).unwrap_or_default()
+                                                        placeholder="No cap" class="border rounded-md p-2 w-full sm:w-auto" />
+                                                        <label class="ml-2 mr-2">Weight</label>
+                                                        <input type="number" step="0.1" min="0"
+                                                        on:input=move |ev| edit_participant_weight(event_target_value(&ev), part.id.to_string())
+                                                        value=part.weight.get().to_string()
+                                                        class="border rounded-md p-2 w-20" />
+                                                    </div>
                                                  </div>
                                                  </For>
                                           </div>
@@ -819,12 +2706,41 @@ fn Homepage() -> impl IntoView {
                                                     placeholder="Enter item price" class="mr-2 border rounded-md p-2" />
 
                                                 </div>
+                                                <div class="mb-2">
+                                                    <label for="item-quantity">Quantity</label>
+                                                    <input
+                                                         on:input=move |ev| {
+                                                             edit_item_quantity(event_target_value(&ev), litem.id.to_string())
+                                                         }
+                                                    type="number" min="1" step="1" id="item-quantity" value=litem.quantity.get_untracked()
+                                                    class="mr-2 border rounded-md p-2" />
+                                                </div>
+                                                <div class="mb-2">
+                                                    <label for="item-category">Category</label>
+                                                    <input
+                                                         on:input=move |ev| {
+                                                             edit_item_category(event_target_value(&ev), litem.id.to_string())
+                                                         }
+                                                    type="text" id="item-category" value=litem.category.get_untracked()
+                                                    placeholder="e.g. Drinks" class="mr-2 border rounded-md p-2" />
+                                                </div>
                                                 </div>
                                                 {move ||
                                                     if participants_exists() {
                                                 view! {
                                                     <div>
-                                                        <label for="participant-dropdown">Participants</label>
+                                                        <label for="assignment-dropdown">Assigned to</label>
+                                                     <select id="assignment-dropdown"
+                                                     on:change=move |ev| edit_item_assignment(event_target_value(&ev), litem.id.to_string())
+                                                     class="mt-2 p-2 border rounded-md w-full">
+                                                         <option value="selected" selected=move || litem.assignment.get() == Assignment::Selected>"Selected participants"</option>
+                                                         <option value="everyone" selected=move || litem.assignment.get() == Assignment::Everyone>"Everyone"</option>
+                                                         <option value="except" selected=move || matches!(litem.assignment.get(), Assignment::EveryoneExcept(_))>"Everyone except"</option>
+                                                     </select>
+                                                     <div class:hidden=move || litem.assignment.get() == Assignment::Everyone>
+                                                        <label for="participant-dropdown">
+                                                            {move || if matches!(litem.assignment.get(), Assignment::EveryoneExcept(_)) { "Excluded" } else { "Participants" }}
+                                                        </label>
                                                      <select id="participants-dropdown"
                                                      on:change=move |event: web_sys::Event| edit_selected_parts(litem.id.to_string(), event)
                                                      multiple class="mt-2 p-2 border rounded-md w-full">
@@ -841,6 +2757,7 @@ fn Homepage() -> impl IntoView {
                                                         }
                                                           </For>
                                                      </select>
+                                                     </div>
                                                     </div>
                                                       }
                                                     } else {
@@ -885,6 +2802,105 @@ fn Homepage() -> impl IntoView {
                                      }
                                     id="Total Tax" class="mt-2 p-2 border rounded-md w-full" step="0.1"/>
                                 </div>
+                                <div class="flex mt-2">
+                                    <div class="w-1/2 pr-2">
+                                        <label for="total-tip">Tip</label>
+                                        <input type="number" id="total-tip" min="0" step="0.1"
+                                        value={move || split_item.get().total_tip.get().to_string()}
+                                        on:input=move |ev| {
+                                            update_split_total_tip(Decimal::from_str_exact(event_target_value(&ev).as_str()).unwrap_or_else(|_| Decimal::from(0)));
+                                        }
+                                        class="mt-2 p-2 border rounded-md w-full"/>
+                                    </div>
+                                    <div class="w-1/2">
+                                        <label for="total-discount">Discount</label>
+                                        <input type="number" id="total-discount" min="0" step="0.1"
+                                        value={move || split_item.get().total_discount.get().to_string()}
+                                        on:input=move |ev| {
+                                            update_split_total_discount(Decimal::from_str_exact(event_target_value(&ev).as_str()).unwrap_or_else(|_| Decimal::from(0)));
+                                        }
+                                        class="mt-2 p-2 border rounded-md w-full"/>
+                                    </div>
+                                </div>
+                                <div class="mt-2">
+                                    <label for="unassigned-policy">Unassigned items</label>
+                                    <select id="unassigned-policy"
+                                    on:change=move |ev| update_unassigned_policy(event_target_value(&ev))
+                                    class="mt-2 p-2 border rounded-md w-full">
+                                        {[UnassignedPolicy::Block, UnassignedPolicy::SplitAmongEveryone, UnassignedPolicy::ChargePayer]
+                                            .into_iter()
+                                            .map(|policy| view! {
+                                                <option value=policy.as_str()
+                                                selected=move || split_item.get().unassigned_policy.get() == policy>
+                                                    {policy.description()}
+                                                </option>
+                                            })
+                                            .collect_view()}
+                                    </select>
+                                </div>
+                                <div class="mt-2">
+                                    <label for="treat-mode">Treated participants' share</label>
+                                    <select id="treat-mode"
+                                    on:change=move |ev| update_treat_mode(event_target_value(&ev))
+                                    class="mt-2 p-2 border rounded-md w-full">
+                                        {[TreatMode::Equal, TreatMode::Proportional]
+                                            .into_iter()
+                                            .map(|mode| view! {
+                                                <option value=mode.as_str()
+                                                selected=move || split_item.get().treat_mode.get() == mode>
+                                                    {mode.description()}
+                                                </option>
+                                            })
+                                            .collect_view()}
+                                    </select>
+                                </div>
+                                <div class="mt-2">
+                                    <label for="bill-cap">Cap per person (optional)</label>
+                                    <input type="number" id="bill-cap"
+                                    value={move || split_item.get().bill_cap.get().map(|c| c.to_string()).unwrap_or_default()}
+                                    on:input=move |ev| update_bill_cap(event_target_value(&ev))
+                                    placeholder="No cap" class="mt-2 p-2 border rounded-md w-full" step="0.01"/>
+                                    <label for="sponsor">Amounts over a cap go to</label>
+                                    <select id="sponsor"
+                                    on:change=move |ev| update_sponsor(event_target_value(&ev))
+                                    class="mt-2 p-2 border rounded-md w-full">
+                                        <option value="" selected=move || split_item.get().sponsor.get().is_none()>"Spread across the group"</option>
+                                        <For each=all_participants key=|part| part.id let:part>
+                                            <option value=part.id.to_string()
+                                            selected=move || split_item.get().sponsor.get() == Some(part.id)>
+                                                {part.name}
+                                            </option>
+                                        </For>
+                                    </select>
+                                </div>
+                                <div class="mt-2">
+                                    <label for="receipt-total">Receipt grand total (optional)</label>
+                                    <input type="number" id="receipt-total"
+                                    value={move || split_item.get().receipt_total.get().map(|t| t.to_string()).unwrap_or_default()}
+                                    on:input=move |ev| update_split_receipt_total(event_target_value(&ev))
+                                    placeholder="Total printed on the receipt" class="mt-2 p-2 border rounded-md w-full" step="0.01"/>
+                                </div>
+                                <form on:submit=on_submit_quick class="mt-2">
+                                 <label for="quick-entry">"Quick entry"</label>
+                                 <textarea id="quick-entry" rows="2"
+                                 prop:value=quick_text
+                                 on:input=move |ev| set_quick_text.set(event_target_value(&ev))
+                                 on:keydown=move |ev| if ev.key() == "Enter" && !ev.shift_key() {
+                                     ev.prevent_default();
+                                     add_quick_entry();
+                                 }
+                                 placeholder="Pizza 450 @alice @bob"
+                                 class="mt-2 p-2 border rounded-md w-full"></textarea>
+                                 <span class="text-sm">"One per line: 2x Beer 300 @all -@carol, or Tax 18% to add to the tax. Shift+Enter starts a new line."</span>
+                                 <ul class="text-sm text-red-600">
+                                     {move || quick_errors.get().into_iter().map(|(line, error)| view! {
+                                         <li>{format!("{}: {}", line, error)}</li>
+                                     }).collect_view()}
+                                 </ul>
+                                 <div class="w-1/2 pl-2 mt-2">
+                                     <button type="submit" id="quick-entry-btn" class="p-2 border rounded-md w-full bg-blue-500 text-white">"Add"</button>
+                                 </div>
+                                </form>
                                 <form on:submit=on_submit_item>
                                  <label for="item-name" class="mt-2">Item Name</label>
                                  <input type="text" node_ref=litem_name_ref id="item-name" placeholder="Enter item name" class="mt-2 p-2 border rounded-md w-full"/>
@@ -892,6 +2908,13 @@ fn Homepage() -> impl IntoView {
                                  <label for="item-price" class="mt-2">Item Price</label>
                                  <input type="number" node_ref=litem_price_ref id="item-price" placeholder="Enter item price" class="mt-2 p-2 border rounded-md w-full" step="0.01"/>
 
+                                 <label for="assignment-mode" class="mt-2">Assign to</label>
+                                 <select id="assignment-mode" node_ref=litem_assignment_ref class="mt-2 p-2 border rounded-md w-full">
+                                     <option value="selected" selected>"Selected participants"</option>
+                                     <option value="everyone">"Everyone"</option>
+                                     <option value="except">"Everyone except selected"</option>
+                                 </select>
+
                                  <label for="participants-dropdown" class="mt-2">Select Participants</label>
 
                                  <select id="participants-dropdown" node_ref=litem_parts_ref multiple class="mt-2 p-2 border rounded-md w-full">
@@ -903,10 +2926,221 @@ fn Homepage() -> impl IntoView {
                                      <button type="submit" id="add-item-btn" class="p-2 border rounded-md w-full bg-blue-500 text-white">Add Item</button>
                                  </div>
                                  </form>
+                                 <div id="items-csv-section" class="mt-4">
+                                     <label for="items-csv">"Import items from CSV"</label>
+                                     <input type="file" id="items-csv" accept=".csv,text/csv"
+                                     on:change=on_items_csv
+                                     class="mt-2 p-2 border rounded-md w-full"/>
+                                     <span class="text-sm">"Columns: name, quantity, unit price, category and participants (separated by ;)"</span>
+                                     {move || items_csv_rows.get().map(|parsed| match parsed {
+                                         Err(e) => view! { <h3>{format!("Couldn't read the CSV: {}", e)}</h3> }.into_view(),
+                                         Ok(rows) => {
+                                             let bill = split_item.get().to_bill();
+                                             let ready = rows.iter().filter(|row| row.status(&bill, create_missing.get()).is_ok()).count();
+                                             view! {
+                                                 <label class="mt-2 block">
+                                                     <input type="checkbox" prop:checked=create_missing
+                                                     on:change=move |ev| set_create_missing.set(event_target_checked(&ev))/>
+                                                     " Add people who aren't on the bill yet"
+                                                 </label>
+                                                 <table class="mt-2 w-full text-sm">
+                                                     <tr>
+                                                         <th>"Line"</th><th>"Item"</th><th>"Qty"</th><th>"Unit price"</th>
+                                                         <th>"Price"</th><th>"Category"</th><th>"Participants"</th><th></th>
+                                                     </tr>
+                                                     {rows.iter().map(|row| {
+                                                         let status = row.status(&bill, create_missing.get());
+                                                         view! {
+                                                             <tr class:text-red-600=status.is_err()>
+                                                                 <td>{row.line}</td>
+                                                                 {match &row.item {
+                                                                     Ok(item) => view! {
+                                                                         <td>{item.name.clone()}</td>
+                                                                         <td>{item.quantity}</td>
+                                                                         <td>{item.unit_price.to_string()}</td>
+                                                                         <td>{item.price().to_string()}</td>
+                                                                         <td>{item.category.clone()}</td>
+                                                                         <td>{item.participants.join(", ")}</td>
+                                                                     }.into_view(),
+                                                                     Err(_) => view! { <td colspan="6"></td> }.into_view(),
+                                                                 }}
+                                                                 <td>{match status {
+                                                                     Ok(None) => String::from("OK"),
+                                                                     Ok(Some(note)) => note,
+                                                                     Err(e) => e,
+                                                                 }}</td>
+                                                             </tr>
+                                                         }
+                                                     }).collect_view()}
+                                                 </table>
+                                                 <button
+                                                     on:click=move |_| commit_items_csv()
+                                                     disabled=ready == 0
+                                                     class="mt-2 p-2 border rounded-md w-full bg-blue-500 text-white">
+                                                     {format!("Add {} items", ready)}
+                                                 </button>
+                                                 <button
+                                                     on:click=move |_| set_items_csv_rows.set(None)
+                                                     class="mt-2 p-2 border rounded-md w-full">
+                                                     "Cancel"
+                                                 </button>
+                                             }.into_view()
+                                         }
+                                     })}
+                                 </div>
+                                 <div id="receipt-section" class="mt-4">
+                                     <label for="receipt-text">"Paste a receipt"</label>
+                                     <textarea id="receipt-text" rows="8"
+                                     prop:value=receipt_text
+                                     on:input=move |ev| set_receipt_text.set(event_target_value(&ev))
+                                     placeholder="Copy the text of a bill or an order summary here"
+                                     class="mt-2 p-2 border rounded-md w-full"></textarea>
+                                     <button
+                                         on:click=move |_| set_receipt.set(Some(parse_receipt(&receipt_text.get_untracked())))
+                                         disabled=move || receipt_text.get().trim().is_empty()
+                                         class="mt-2 p-2 border rounded-md w-full bg-blue-500 text-white">
+                                         "Read receipt"
+                                     </button>
+                                     {move || receipt.get().map(|read| view! {
+                                         <table class="mt-2 w-full text-sm">
+                                             <tr><th>"Item"</th><th>"Qty"</th><th>"Price"</th><th></th></tr>
+                                             {read.items.iter().enumerate().map(|(index, item)| view! {
+                                                 <tr>
+                                                     <td>{item.name.clone()}</td>
+                                                     <td>{item.quantity}</td>
+                                                     <td>{item.price.to_string()}</td>
+                                                     <td>
+                                                         <button
+                                                             on:click=move |_| set_receipt.update(|r| if let Some(r) = r { r.items.remove(index); })
+                                                             class="px-2 border rounded-md">
+                                                             "Leave out"
+                                                         </button>
+                                                     </td>
+                                                 </tr>
+                                             }).collect_view()}
+                                             {read.charges.iter().map(|charge| view! {
+                                                 <tr><td colspan="2">{format!("{} (tax)", charge.label)}</td><td>{charge.amount.to_string()}</td><td></td></tr>
+                                             }).collect_view()}
+                                             {(!read.tip.is_zero()).then(|| view! {
+                                                 <tr><td colspan="2">"Tip"</td><td>{read.tip.to_string()}</td><td></td></tr>
+                                             })}
+                                             {read.discounts.iter().map(|discount| view! {
+                                                 <tr><td colspan="2">{format!("{} (discount)", discount.label)}</td><td>{format!("-{}", discount.amount)}</td><td></td></tr>
+                                             }).collect_view()}
+                                             <tr>
+                                                 <th colspan="2">"Grand total"</th>
+                                                 <th>{read.grand_total.map(|t| t.to_string()).unwrap_or_else(|| String::from("not found"))}</th>
+                                                 <th></th>
+                                             </tr>
+                                         </table>
+                                         {read.difference().filter(|d| !d.is_zero()).map(|d| view! {
+                                             <h3 class="text-red-600">{format!("The lines read add up to {}, {} off the grand total. Check for missed lines.", read.computed_total(), d)}</h3>
+                                         })}
+                                         {(!read.unread.is_empty()).then(|| view! {
+                                             <h3>"Left out, check whether any of these were charged:"</h3>
+                                             <ul class="text-sm">{read.unread.iter().map(|line| view! { <li>{line.clone()}</li> }).collect_view()}</ul>
+                                         })}
+                                         <p class="text-sm">"Taxes, the tip and discounts are added to what the bill already has. The grand total replaces the receipt total."</p>
+                                         <button
+                                             on:click=move |_| commit_receipt()
+                                             disabled=read.items.is_empty() && read.charges.is_empty()
+                                             class="mt-2 p-2 border rounded-md w-full bg-blue-500 text-white">
+                                             {format!("Add {} items and the taxes", read.items.len())}
+                                         </button>
+                                         <button
+                                             on:click=move |_| set_receipt.set(None)
+                                             class="mt-2 p-2 border rounded-md w-full">
+                                             "Cancel"
+                                         </button>
+                                     })}
+                                 </div>
                     </div>
 
 
 
+                    </fieldset>
+                    <div id="share-link-section" class="mt-4">
+                        {move || share_error.get().map(|e| view! { <h3>{format!("Couldn't open the shared bill: {}", e)}</h3> })}
+                        <button
+                            on:click=move |_| share_in_link()
+                            class="mt-2 p-2 border rounded-md w-full bg-blue-500 text-white">
+                            "Share as link (nothing leaves your device)"
+                        </button>
+                        {move || fragment_link.get().map(|link| match link {
+                            Ok(link) => view! {
+                                <input type="text" id="fragment-link" readonly value=link
+                                class="mt-2 p-2 border rounded-md w-full"/>
+                            }.into_view(),
+                            Err(e) => view! { <h3>{format!("Couldn't create the link: {}", e)}</h3> }.into_view(),
+                        })}
+                    </div>
+                    <div id="bill-file-section" class="mt-4">
+                        <button
+                            on:click=move |_| export_bill()
+                            class="mt-2 p-2 border rounded-md w-full bg-blue-500 text-white">
+                            "Export bill"
+                        </button>
+                        <button
+                            on:click=move |_| export_csv("shares.csv", participants_csv)
+                            class="mt-2 p-2 border rounded-md w-full bg-blue-500 text-white">
+                            "Export shares (CSV)"
+                        </button>
+                        <button
+                            on:click=move |_| export_csv("items.csv", items_csv)
+                            class="mt-2 p-2 border rounded-md w-full bg-blue-500 text-white">
+                            "Export items per person (CSV)"
+                        </button>
+                        {move || csv_error.get().map(|e| view! { <h3>{format!("Couldn't export the CSV: {}", e)}</h3> })}
+                        {(!read_only).then(|| view! {
+                            <label for="import-bill" class="mt-2 block">"Import bill"</label>
+                            <input type="file" id="import-bill" accept=".json,application/json"
+                            on:change=on_import
+                            class="mt-2 p-2 border rounded-md w-full"/>
+                        })}
+                        {move || import_error.get().map(|e| view! { <h3>{format!("Couldn't import the bill: {}", e)}</h3> })}
+                    </div>
+                    {if read_only {
+                        view! {
+                            <div id="save-bill-section" class="mt-4">
+                                <h3>"You're viewing a read-only copy of this bill."</h3>
+                            </div>
+                        }
+                    } else {
+                        view! {
+                            <div id="save-bill-section" class="mt-4">
+                                <button
+                                    on:click=move |_| save_to_server.dispatch(split_item.get().to_bill())
+                                    disabled=move || save_to_server.pending().get()
+                                    class="mt-2 p-2 border rounded-md w-full bg-green-500 text-white">
+                                    {if saved_id.is_some() { "Save changes" } else { "Save to server" }}
+                                </button>
+                                {move || save_to_server.value().get().map(|result| match result {
+                                    Ok(_) => String::from("Saved"),
+                                    Err(e) => format!("Couldn't save: {}", e),
+                                })}
+                                {(saved_id.is_none() && !is_encrypted).then(|| view! {
+                                    <button
+                                        on:click=move |_| save_encrypted.dispatch(split_item.get().to_bill())
+                                        disabled=move || save_encrypted.pending().get()
+                                        class="mt-2 p-2 border rounded-md w-full bg-green-700 text-white">
+                                        "Save end-to-end encrypted"
+                                    </button>
+                                })}
+                                {move || save_encrypted.value().get().and_then(|r| r.err()).map(|e| format!("Couldn't save: {}", e))}
+                                {is_encrypted.then(|| view! {
+                                    <span class="text-sm">"End-to-end encrypted: share this page's full link, the key is after the #"</span>
+                                })}
+                            </div>
+                        }
+                    }}
+                    {match (saved_id, access) {
+                        (Some(id), Some(Access::Editor)) => Some(view! {
+                            <ShareLinks id=id token=token.get_value()/>
+                            <BillHistory id=id token=token.get_value() on_restore=move |_| sync.dispatch(())/>
+                        }),
+                        _ => None,
+                    }}
+                    <SavedBills/>
                 </section>
             </main>
         </body>
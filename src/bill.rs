@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use crate::app::{Assignment, TreatMode, UnassignedPolicy};
+
+/// A signal-free snapshot of a `SplitItem`, used wherever a bill leaves the
+/// page: the server, share links and files.
+#[cfg_attr(feature = "ssr", derive(utoipa::ToSchema))]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bill {
+    /// Left out by callers creating a bill; the server picks one.
+    #[serde(default)]
+    pub id: Uuid,
+    #[serde(default)]
+    pub event_name: String,
+    #[serde(default)]
+    pub total_tax: Decimal,
+    #[serde(default)]
+    pub tip: Decimal,
+    /// Taken off the items, in proportion to what everyone ordered.
+    #[serde(default)]
+    pub discount: Decimal,
+    #[serde(default)]
+    pub receipt_total: Option<Decimal>,
+    #[serde(default)]
+    pub unassigned_policy: UnassignedPolicy,
+    #[serde(default)]
+    pub treated: Vec<Uuid>,
+    #[serde(default)]
+    pub treat_mode: TreatMode,
+    #[serde(default)]
+    pub caps: HashMap<Uuid, Decimal>,
+    #[serde(default)]
+    pub bill_cap: Option<Decimal>,
+    #[serde(default)]
+    pub sponsor: Option<Uuid>,
+    #[serde(default)]
+    pub settle_status: bool,
+    /// The content hash taken when the bill was finalized. While set the bill
+    /// is locked.
+    #[serde(default)]
+    pub finalized: Option<String>,
+    #[serde(default)]
+    pub participants: Vec<BillParticipant>,
+    #[serde(default)]
+    pub items: Vec<BillItem>,
+}
+
+impl Bill {
+    /// A bill with nothing on it yet.
+    pub fn empty(id: Uuid) -> Self {
+        Bill {
+            id,
+            event_name: String::new(),
+            total_tax: Decimal::ZERO,
+            tip: Decimal::ZERO,
+            discount: Decimal::ZERO,
+            receipt_total: None,
+            unassigned_policy: UnassignedPolicy::default(),
+            treated: Vec::new(),
+            treat_mode: TreatMode::default(),
+            caps: HashMap::new(),
+            bill_cap: None,
+            sponsor: None,
+            settle_status: false,
+            finalized: None,
+            participants: Vec::new(),
+            items: Vec::new(),
+        }
+    }
+
+    /// The bill as JSON with object keys sorted and everyone and everything
+    /// in id order, so the same bill always gives the same bytes. The
+    /// finalized hash itself is left out.
+    pub fn canonical_json(&self) -> String {
+        let mut bill = self.clone();
+        bill.finalized = None;
+        bill.treated.sort();
+        bill.participants.sort_by_key(|p| p.id);
+        bill.items.sort_by_key(|i| i.id);
+        for item in bill.items.iter_mut() {
+            item.participants.sort();
+            if let Assignment::EveryoneExcept(excluded) = &mut item.assignment {
+                excluded.sort();
+            }
+        }
+        // `serde_json::Value` keeps object keys sorted, which also fixes the
+        // order of the caps map
+        serde_json::to_value(&bill).map(|value| value.to_string()).unwrap_or_default()
+    }
+
+    /// SHA-256 of `canonical_json`, in hex.
+    pub fn content_hash(&self) -> String {
+        format!("{:x}", Sha256::digest(self.canonical_json().as_bytes()))
+    }
+}
+
+/// A problem with a bill, and the field it is about, e.g. `items[2].price`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BillError {
+    pub field: String,
+    pub message: String,
+}
+
+impl BillError {
+    fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        BillError { field: field.into(), message: message.into() }
+    }
+}
+
+impl std::fmt::Display for BillError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.field.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.field, self.message)
+        }
+    }
+}
+
+/// Marks an exported bill file, so it can be told apart from any other JSON.
+pub const EXPORT_FORMAT: &str = "whati8-bill";
+/// Bumped whenever an older whati8 couldn't read the files a newer one writes.
+pub const EXPORT_VERSION: u32 = 1;
+
+/// A bill as it is written to a file by "Export bill".
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BillExport {
+    pub format: String,
+    pub version: u32,
+    pub bill: Bill,
+}
+
+impl Bill {
+    pub fn to_export_json(&self) -> String {
+        let export = BillExport {
+            format: String::from(EXPORT_FORMAT),
+            version: EXPORT_VERSION,
+            bill: self.clone(),
+        };
+        serde_json::to_string_pretty(&export).unwrap_or_default()
+    }
+
+    /// Reads an exported bill file. Errors name the field at fault, e.g.
+    /// `items[2].price`, or the line and column when the JSON is broken.
+    pub fn from_export_json(json: &str) -> Result<Bill, BillError> {
+        let mut value: serde_json::Value = serde_json::from_str(json).map_err(|e| {
+            BillError::new("", format!("not valid JSON (line {}, column {})", e.line(), e.column()))
+        })?;
+        match value.get("format").and_then(|f| f.as_str()) {
+            Some(EXPORT_FORMAT) => {}
+            Some(other) => return Err(BillError::new("format", format!("'{}' isn't a whati8 bill file", other))),
+            None => return Err(BillError::new("format", "missing, this isn't a whati8 bill file")),
+        }
+        match value.get("version").and_then(|v| v.as_u64()) {
+            Some(version) if version > EXPORT_VERSION as u64 => {
+                return Err(BillError::new(
+                    "version",
+                    format!("{} is newer than this version of whati8 can read", version),
+                ))
+            }
+            Some(_) => {}
+            None => return Err(BillError::new("version", "missing or not a number")),
+        }
+        let bill = value
+            .get_mut("bill")
+            .map(serde_json::Value::take)
+            .ok_or_else(|| BillError::new("bill", "missing"))?;
+        let bill: Bill = serde_path_to_error::deserialize(bill).map_err(|e| {
+            let path = e.path().to_string();
+            BillError::new(if path == "." { String::from("bill") } else { path }, e.into_inner().to_string())
+        })?;
+        bill.validate()?;
+        Ok(bill)
+    }
+}
+
+impl Bill {
+    /// Gives a fresh id to every participant and item that came in without
+    /// one, so hand-written bills don't have to make up UUIDs.
+    pub fn fill_missing_ids(&mut self) {
+        for p in self.participants.iter_mut().filter(|p| p.id.is_nil()) {
+            p.id = Uuid::new_v4();
+        }
+        for i in self.items.iter_mut().filter(|i| i.id.is_nil()) {
+            i.id = Uuid::new_v4();
+        }
+    }
+
+    /// Checks the things the page never lets happen, for bills that come in
+    /// from elsewhere: the API, files and scripts.
+    pub fn validate(&self) -> Result<(), BillError> {
+        let non_negative = |field: &str, amount: Decimal| {
+            if amount < Decimal::ZERO {
+                Err(BillError::new(field, "can't be negative"))
+            } else {
+                Ok(())
+            }
+        };
+        non_negative("total_tax", self.total_tax)?;
+        non_negative("tip", self.tip)?;
+        non_negative("discount", self.discount)?;
+        if let Some(receipt_total) = self.receipt_total {
+            non_negative("receipt_total", receipt_total)?;
+        }
+        if let Some(bill_cap) = self.bill_cap {
+            non_negative("bill_cap", bill_cap)?;
+        }
+
+        for (i, p) in self.participants.iter().enumerate() {
+            if p.name.trim().is_empty() {
+                return Err(BillError::new(format!("participants[{}].name", i), "is empty"));
+            }
+            non_negative(&format!("participants[{}].weight", i), p.weight)?;
+            let earlier = &self.participants[..i];
+            if earlier.iter().any(|e| e.id == p.id) {
+                return Err(BillError::new(format!("participants[{}].id", i), "is used twice"));
+            }
+        }
+
+        let known = |id: &Uuid| self.participants.iter().any(|p| p.id == *id);
+        for (i, item) in self.items.iter().enumerate() {
+            if item.name.trim().is_empty() {
+                return Err(BillError::new(format!("items[{}].name", i), "is empty"));
+            }
+            non_negative(&format!("items[{}].price", i), item.price)?;
+            if item.quantity == 0 {
+                return Err(BillError::new(format!("items[{}].quantity", i), "has to be at least 1"));
+            }
+            if self.items[..i].iter().any(|e| e.id == item.id) {
+                return Err(BillError::new(format!("items[{}].id", i), "is used twice"));
+            }
+            if !item.participants.iter().all(known) {
+                return Err(BillError::new(format!("items[{}].participants", i), "has someone who isn't on the bill"));
+            }
+            if let Assignment::EveryoneExcept(excluded) = &item.assignment {
+                if !excluded.iter().all(known) {
+                    return Err(BillError::new(format!("items[{}].assignment", i), "excludes someone who isn't on the bill"));
+                }
+            }
+        }
+
+        if !self.treated.iter().all(known) {
+            return Err(BillError::new("treated", "has someone who isn't on the bill"));
+        }
+        for (id, cap) in self.caps.iter() {
+            if !known(id) {
+                return Err(BillError::new("caps", "has someone who isn't on the bill"));
+            }
+            non_negative("caps", *cap)?;
+        }
+        if self.sponsor.is_some_and(|id| !known(&id)) {
+            return Err(BillError::new("sponsor", "isn't on the bill"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "ssr", derive(utoipa::ToSchema))]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BillParticipant {
+    #[serde(default)]
+    pub id: Uuid,
+    pub name: String,
+    #[serde(default)]
+    pub payer: bool,
+    #[serde(default)]
+    pub settled: bool,
+    #[serde(default = "default_weight")]
+    pub weight: Decimal,
+}
+
+#[cfg_attr(feature = "ssr", derive(utoipa::ToSchema))]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BillItem {
+    #[serde(default)]
+    pub id: Uuid,
+    pub name: String,
+    /// What all of them cost together, not each.
+    pub price: Decimal,
+    /// How many were ordered.
+    #[serde(default = "default_quantity", skip_serializing_if = "is_one")]
+    pub quantity: u32,
+    /// Free text such as "Drinks", kept from imported receipts and files.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub category: String,
+    #[serde(default)]
+    pub assignment: Assignment,
+    /// Ids of the selected participants, used when `assignment` is `Selected`.
+    #[serde(default)]
+    pub participants: Vec<Uuid>,
+}
+
+fn default_weight() -> Decimal {
+    Decimal::ONE
+}
+
+pub(crate) fn default_quantity() -> u32 {
+    1
+}
+
+// Left out of the JSON when it is 1, so bills from before quantities keep
+// their content hash.
+fn is_one(quantity: &u32) -> bool {
+    *quantity == 1
+}
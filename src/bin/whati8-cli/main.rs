@@ -0,0 +1,200 @@
+//! Splits a bill from the terminal with the same engine as the web app.
+//!
+//! ```text
+//! whati8-cli [--format json|toml|yaml] [FILE]
+//! whati8-cli --tui [FILE]
+//! ```
+//!
+//! Reads the bill from FILE, or from stdin when FILE is missing or `-`. The
+//! bill is in the same format the JSON API returns, or a file exported from
+//! the web app. `--tui` edits the bill
+//! interactively instead, and saves it back to FILE.
+
+mod tui;
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use rust_decimal::Decimal;
+use uuid::Uuid;
+use whati8::bill::Bill;
+use whati8::split::{compute, SplitResult};
+
+const USAGE: &str = "usage: whati8-cli [--format json|toml|yaml] [FILE]
+       whati8-cli --tui [FILE]
+
+Prints the split, summary and settlement plan of the bill in FILE, or read
+from stdin when FILE is missing or `-`. The format is taken from the file's
+extension, and defaults to JSON.
+
+--tui opens the bill for editing in the terminal, starting a new one if FILE
+doesn't exist yet. It is saved as JSON, so FILE has to be a JSON file.";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    fn parse(s: &str) -> Result<Self, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(Format::Json),
+            "toml" => Ok(Format::Toml),
+            "yaml" | "yml" => Ok(Format::Yaml),
+            other => Err(format!("unknown format: {}", other)),
+        }
+    }
+
+    fn from_path(path: &str) -> Option<Self> {
+        let extension = std::path::Path::new(path).extension()?.to_str()?;
+        Format::parse(extension).ok()
+    }
+}
+
+struct Args {
+    format: Option<Format>,
+    path: Option<String>,
+    tui: bool,
+    help: bool,
+}
+
+impl Args {
+    /// `--format`, or else the file's extension, or else JSON.
+    fn format(&self) -> Format {
+        self.format
+            .or_else(|| self.path.as_deref().and_then(Format::from_path))
+            .unwrap_or(Format::Json)
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args { format: None, path: None, tui: false, help: false };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => parsed.help = true,
+            "-f" | "--format" => {
+                let format = args.next().ok_or("--format needs a value")?;
+                parsed.format = Some(Format::parse(&format)?);
+            }
+            "--tui" => parsed.tui = true,
+            "-" => parsed.path = None,
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {}\n\n{}", arg, USAGE)),
+            _ if parsed.path.is_some() => return Err(format!("only one file can be split at a time\n\n{}", USAGE)),
+            _ => parsed.path = Some(arg),
+        }
+    }
+    Ok(parsed)
+}
+
+/// Whether `text` is a file from "Export bill" rather than a bare bill.
+fn is_export(text: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(text).is_ok_and(|value| value.get("format").is_some())
+}
+
+fn read_bill(args: &Args) -> Result<Bill, String> {
+    let (text, source) = match &args.path {
+        Some(path) => (
+            std::fs::read_to_string(path).map_err(|e| format!("couldn't read {}: {}", path, e))?,
+            path.as_str(),
+        ),
+        None => {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|e| format!("couldn't read stdin: {}", e))?;
+            (text, "stdin")
+        }
+    };
+    let mut bill: Bill = match args.format() {
+        // Files from "Export bill" in the web app
+        Format::Json if is_export(&text) => Bill::from_export_json(&text).map_err(|e| e.to_string()),
+        Format::Json => serde_json::from_str(&text).map_err(|e| e.to_string()),
+        Format::Toml => toml::from_str(&text).map_err(|e| e.to_string()),
+        Format::Yaml => serde_yaml::from_str(&text).map_err(|e| e.to_string()),
+    }
+    .map_err(|e| format!("{} isn't a bill: {}", source, e))?;
+    bill.fill_missing_ids();
+    bill.validate().map_err(|e| format!("{} isn't a valid bill: {}", source, e))?;
+    Ok(bill)
+}
+
+/// Saves a bill the way the web app exports it.
+pub fn write_bill(bill: &Bill, path: &Path) -> Result<(), String> {
+    std::fs::write(path, bill.to_export_json() + "\n").map_err(|e| format!("couldn't write {}: {}", path.display(), e))
+}
+
+pub fn money(amount: Decimal) -> String {
+    format!("₹{:.2}", amount.round_dp(2))
+}
+
+fn print_split(bill: &Bill, result: &SplitResult) {
+    let name = |id: Uuid| {
+        bill.participants
+            .iter()
+            .find(|p| p.id == id)
+            .map(|p| p.name.clone())
+            .unwrap_or_default()
+    };
+    let width = result.shares.iter().map(|s| s.name.chars().count()).max().unwrap_or(0).max(4);
+
+    println!("{:<width$}  {:>12}", "Name", "Share", width = width);
+    for share in result.shares.iter() {
+        println!("{:<width$}  {:>12}", share.name, money(share.share), width = width);
+    }
+    println!("{:<width$}  {:>12}", "Total", money(result.total), width = width);
+
+    println!();
+    println!("{}", result.summary.trim_end());
+
+    println!();
+    if result.settlement.is_empty() {
+        println!("Nobody owes anybody anything.");
+    } else {
+        println!("Settlement plan:");
+        for transfer in result.settlement.iter() {
+            println!("  {} pays {} {}", name(transfer.from), name(transfer.to), money(transfer.amount));
+        }
+    }
+}
+
+fn run() -> Result<(), String> {
+    let args = parse_args(std::env::args().skip(1))?;
+    if args.help {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    if args.tui {
+        // Saving writes an exported bill, which is JSON. Read back as TOML or
+        // YAML it would come out as an empty bill.
+        if args.format() != Format::Json {
+            return Err(format!("--tui saves the bill as JSON, so FILE has to be a .json file\n\n{}", USAGE));
+        }
+        let bill = match &args.path {
+            Some(path) if Path::new(path).exists() => read_bill(&args)?,
+            _ => Bill::empty(Uuid::new_v4()),
+        };
+        return tui::run(bill, args.path.map(PathBuf::from));
+    }
+    let bill = read_bill(&args)?;
+    let result = compute(&bill);
+
+    if result.blocked {
+        return Err(String::from(
+            "some items aren't shared by anyone; assign them or set another unassigned_policy",
+        ));
+    }
+    print_split(&bill, &result);
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
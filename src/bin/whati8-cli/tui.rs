@@ -0,0 +1,478 @@
+//! The interactive mode: the same flow as the web page, in a terminal. Every
+//! change reruns the split, and `s` saves the bill as JSON.
+
+use std::io::stdout;
+use std::path::PathBuf;
+use std::str::FromStr;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Cell, Clear, List, ListItem, ListState, Paragraph, Row, Table, Wrap};
+use ratatui::{Frame, Terminal};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+use whati8::app::Assignment;
+use whati8::bill::{Bill, BillItem, BillParticipant};
+use whati8::ops::BillOp;
+use whati8::split::{compute, SplitResult};
+use crate::{money, write_bill};
+
+const HELP: &str = "tab switch list · p add person · i add item · a assign · y payer · t tax · d remove · s save · q quit";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Focus {
+    Participants,
+    Items,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Prompt {
+    ParticipantName,
+    ItemName,
+    ItemPrice(String),
+    Tax,
+    SavePath,
+}
+
+impl Prompt {
+    fn title(&self) -> String {
+        match self {
+            Prompt::ParticipantName => String::from("Name"),
+            Prompt::ItemName => String::from("Item"),
+            Prompt::ItemPrice(name) => format!("Price of {}", name),
+            Prompt::Tax => String::from("Total tax"),
+            Prompt::SavePath => String::from("Save as"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Mode {
+    Normal,
+    Input { prompt: Prompt, text: String },
+    /// The checklist of who shares an item. Row 0 is "Everyone".
+    Assign { item: usize, row: usize },
+}
+
+struct App {
+    bill: Bill,
+    result: SplitResult,
+    path: Option<PathBuf>,
+    focus: Focus,
+    participant_row: usize,
+    item_row: usize,
+    mode: Mode,
+    status: String,
+    unsaved: bool,
+    quit: bool,
+}
+
+impl App {
+    fn new(bill: Bill, path: Option<PathBuf>) -> Self {
+        App {
+            result: compute(&bill),
+            bill,
+            path,
+            focus: Focus::Participants,
+            participant_row: 0,
+            item_row: 0,
+            mode: Mode::Normal,
+            status: String::from(HELP),
+            unsaved: false,
+            quit: false,
+        }
+    }
+
+    /// Makes a change if the bill still validates afterwards, and reruns the
+    /// split.
+    fn change(&mut self, f: impl FnOnce(&mut Bill)) {
+        let mut bill = self.bill.clone();
+        f(&mut bill);
+        if let Err(e) = bill.validate() {
+            self.status = e.to_string();
+            return;
+        }
+        self.bill = bill;
+        self.result = compute(&self.bill);
+        self.unsaved = true;
+        self.participant_row = self.participant_row.min(self.bill.participants.len().saturating_sub(1));
+        self.item_row = self.item_row.min(self.bill.items.len().saturating_sub(1));
+        self.status = String::from(HELP);
+    }
+
+    fn on_key(&mut self, key: KeyEvent) {
+        match self.mode.clone() {
+            Mode::Normal => self.on_normal_key(key),
+            Mode::Input { prompt, text } => self.on_input_key(key, prompt, text),
+            Mode::Assign { item, row } => self.on_assign_key(key, item, row),
+        }
+    }
+
+    fn on_normal_key(&mut self, key: KeyEvent) {
+        let (row, len) = match self.focus {
+            Focus::Participants => (&mut self.participant_row, self.bill.participants.len()),
+            Focus::Items => (&mut self.item_row, self.bill.items.len()),
+        };
+        match key.code {
+            KeyCode::Up => *row = row.saturating_sub(1),
+            KeyCode::Down => *row = (*row + 1).min(len.saturating_sub(1)),
+            KeyCode::Tab | KeyCode::BackTab => {
+                self.focus = match self.focus {
+                    Focus::Participants => Focus::Items,
+                    Focus::Items => Focus::Participants,
+                }
+            }
+            KeyCode::Char('p') => self.ask(Prompt::ParticipantName, String::new()),
+            KeyCode::Char('i') => self.ask(Prompt::ItemName, String::new()),
+            KeyCode::Char('t') => self.ask(Prompt::Tax, self.bill.total_tax.to_string()),
+            KeyCode::Char('a') if !self.bill.items.is_empty() => {
+                self.focus = Focus::Items;
+                self.mode = Mode::Assign { item: self.item_row, row: 0 };
+            }
+            KeyCode::Char('y') if self.focus == Focus::Participants => {
+                let row = self.participant_row;
+                self.change(|bill| {
+                    if let Some(p) = bill.participants.get_mut(row) {
+                        p.payer = !p.payer;
+                    }
+                });
+            }
+            KeyCode::Char('d') | KeyCode::Delete => self.remove_selected(),
+            KeyCode::Char('s') => match self.path.clone() {
+                Some(path) => self.save(path),
+                None => self.ask(Prompt::SavePath, String::from("bill.json")),
+            },
+            KeyCode::Char('q') | KeyCode::Esc => {
+                if self.unsaved && !self.status.starts_with("Unsaved") {
+                    self.status = String::from("Unsaved changes: press s to save, or q again to quit anyway");
+                } else {
+                    self.quit = true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ask(&mut self, prompt: Prompt, text: String) {
+        self.mode = Mode::Input { prompt, text };
+    }
+
+    fn on_input_key(&mut self, key: KeyEvent, prompt: Prompt, mut text: String) {
+        match key.code {
+            KeyCode::Esc => {
+                self.mode = Mode::Normal;
+                return;
+            }
+            KeyCode::Enter => {
+                self.mode = Mode::Normal;
+                self.submit(prompt, text.trim().to_string());
+                return;
+            }
+            KeyCode::Backspace => {
+                text.pop();
+            }
+            KeyCode::Char(c) => text.push(c),
+            _ => {}
+        }
+        self.mode = Mode::Input { prompt, text };
+    }
+
+    fn submit(&mut self, prompt: Prompt, text: String) {
+        match prompt {
+            Prompt::ParticipantName => {
+                self.change(|bill| {
+                    bill.participants.push(BillParticipant {
+                        id: Uuid::new_v4(),
+                        name: text,
+                        payer: bill.participants.is_empty(),
+                        settled: false,
+                        weight: Decimal::ONE,
+                    })
+                });
+                self.focus = Focus::Participants;
+                self.participant_row = self.bill.participants.len().saturating_sub(1);
+            }
+            Prompt::ItemName if text.is_empty() => {}
+            Prompt::ItemName => self.ask(Prompt::ItemPrice(text), String::new()),
+            Prompt::ItemPrice(name) => match Decimal::from_str(&text) {
+                Ok(price) => {
+                    let before = self.bill.items.len();
+                    self.change(|bill| {
+                        bill.items.push(BillItem {
+                            id: Uuid::new_v4(),
+                            name,
+                            price,
+                            quantity: 1,
+                            category: String::new(),
+                            assignment: Assignment::Selected,
+                            participants: Vec::new(),
+                        })
+                    });
+                    if self.bill.items.len() > before {
+                        self.focus = Focus::Items;
+                        self.item_row = before;
+                        self.mode = Mode::Assign { item: before, row: 0 };
+                    }
+                }
+                Err(_) => {
+                    self.status = format!("'{}' isn't a price", text);
+                    self.ask(Prompt::ItemPrice(name), text);
+                }
+            },
+            Prompt::Tax => match Decimal::from_str(if text.is_empty() { "0" } else { &text }) {
+                Ok(tax) => self.change(|bill| bill.total_tax = tax),
+                Err(_) => self.status = format!("'{}' isn't an amount", text),
+            },
+            Prompt::SavePath if text.is_empty() => {}
+            Prompt::SavePath => self.save(PathBuf::from(text)),
+        }
+    }
+
+    fn is_checked(item: &BillItem, id: Uuid) -> bool {
+        match &item.assignment {
+            Assignment::Selected => item.participants.contains(&id),
+            Assignment::Everyone => true,
+            Assignment::EveryoneExcept(excluded) => !excluded.contains(&id),
+        }
+    }
+
+    fn on_assign_key(&mut self, key: KeyEvent, item: usize, row: usize) {
+        let rows = self.bill.participants.len() + 1;
+        match key.code {
+            KeyCode::Up => self.mode = Mode::Assign { item, row: row.saturating_sub(1) },
+            KeyCode::Down => self.mode = Mode::Assign { item, row: (row + 1).min(rows - 1) },
+            KeyCode::Char(' ') => self.change(|bill| {
+                let ids: Vec<Uuid> = bill.participants.iter().map(|p| p.id).collect();
+                let Some(bi) = bill.items.get_mut(item) else { return };
+                if row == 0 {
+                    bi.assignment = if bi.assignment == Assignment::Everyone {
+                        Assignment::Selected
+                    } else {
+                        Assignment::Everyone
+                    };
+                    bi.participants.clear();
+                    return;
+                }
+                let toggled = ids[row - 1];
+                let checked: Vec<Uuid> = ids
+                    .iter()
+                    .copied()
+                    .filter(|id| App::is_checked(bi, *id) != (*id == toggled))
+                    .collect();
+                if checked.len() == ids.len() {
+                    bi.assignment = Assignment::Everyone;
+                    bi.participants.clear();
+                } else {
+                    bi.assignment = Assignment::Selected;
+                    bi.participants = checked;
+                }
+            }),
+            KeyCode::Enter | KeyCode::Esc => self.mode = Mode::Normal,
+            _ => {}
+        }
+    }
+
+    fn remove_selected(&mut self) {
+        match self.focus {
+            Focus::Participants => {
+                if let Some(id) = self.bill.participants.get(self.participant_row).map(|p| p.id) {
+                    self.change(|bill| bill.apply(&BillOp::RemoveParticipant(id)));
+                }
+            }
+            Focus::Items => {
+                if let Some(id) = self.bill.items.get(self.item_row).map(|i| i.id) {
+                    self.change(|bill| bill.apply(&BillOp::RemoveItem(id)));
+                }
+            }
+        }
+    }
+
+    fn save(&mut self, path: PathBuf) {
+        match write_bill(&self.bill, &path) {
+            Ok(()) => {
+                self.status = format!("Saved to {}", path.display());
+                self.path = Some(path);
+                self.unsaved = false;
+            }
+            Err(e) => self.status = e,
+        }
+    }
+}
+
+fn assigned_to(bill: &Bill, item: &BillItem) -> String {
+    let names = |ids: &[Uuid]| {
+        bill.participants
+            .iter()
+            .filter(|p| ids.contains(&p.id))
+            .map(|p| p.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    match &item.assignment {
+        Assignment::Everyone => String::from("everyone"),
+        Assignment::EveryoneExcept(excluded) => format!("everyone but {}", names(excluded)),
+        Assignment::Selected if item.participants.is_empty() => String::from("nobody yet"),
+        Assignment::Selected => names(&item.participants),
+    }
+}
+
+fn list_block(title: &str, focused: bool) -> Block<'_> {
+    let style = if focused { Style::default().fg(Color::Yellow) } else { Style::default() };
+    Block::default().borders(Borders::ALL).border_style(style).title(title)
+}
+
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+fn draw(f: &mut Frame, app: &App) {
+    let outer = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(f.size());
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(55), Constraint::Percentage(45)])
+        .split(outer[0]);
+    let lists = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+        .split(columns[0]);
+    let highlight = Style::default().add_modifier(Modifier::REVERSED);
+
+    let participants: Vec<ListItem> = app
+        .bill
+        .participants
+        .iter()
+        .map(|p| ListItem::new(format!("{}{}", p.name, if p.payer { "  (paying)" } else { "" })))
+        .collect();
+    let mut state = ListState::default().with_selected(Some(app.participant_row));
+    f.render_stateful_widget(
+        List::new(participants)
+            .block(list_block("Participants", app.focus == Focus::Participants))
+            .highlight_style(highlight),
+        lists[0],
+        &mut state,
+    );
+
+    let items: Vec<ListItem> = app
+        .bill
+        .items
+        .iter()
+        .map(|i| ListItem::new(format!("{}  {}  ({})", i.name, money(i.price), assigned_to(&app.bill, i))))
+        .collect();
+    let mut state = ListState::default().with_selected(Some(app.item_row));
+    f.render_stateful_widget(
+        List::new(items)
+            .block(list_block("Items", app.focus == Focus::Items))
+            .highlight_style(highlight),
+        lists[1],
+        &mut state,
+    );
+
+    let title = if app.bill.event_name.is_empty() { String::from("Split") } else { app.bill.event_name.clone() };
+    let block = Block::default().borders(Borders::ALL).title(title);
+    if app.result.blocked {
+        f.render_widget(
+            Paragraph::new("Split is blocked until every item is assigned. Press a on an item to assign it.")
+                .wrap(Wrap { trim: true })
+                .block(block),
+            columns[1],
+        );
+    } else {
+        let mut rows: Vec<Row> = app
+            .result
+            .shares
+            .iter()
+            .map(|s| Row::new(vec![Cell::from(s.name.clone()), Cell::from(money(s.share))]))
+            .collect();
+        rows.push(Row::new(vec![Cell::from("Tax"), Cell::from(money(app.bill.total_tax))]));
+        rows.push(
+            Row::new(vec![Cell::from("Total"), Cell::from(money(app.result.total))])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        );
+        f.render_widget(
+            Table::new(rows, [Constraint::Percentage(60), Constraint::Percentage(40)]).block(block),
+            columns[1],
+        );
+    }
+
+    let saved = match (&app.path, app.unsaved) {
+        (Some(path), false) => format!("Saved to {}", path.display()),
+        (Some(path), true) => format!("{} (unsaved)", path.display()),
+        (None, _) => String::from("Not saved yet"),
+    };
+    f.render_widget(
+        Paragraph::new(vec![Line::from(app.status.clone()), Line::from(saved)])
+            .block(Block::default().borders(Borders::TOP)),
+        outer[1],
+    );
+
+    match &app.mode {
+        Mode::Normal => {}
+        Mode::Input { prompt, text } => {
+            let area = centered(f.size(), 50, 3);
+            f.render_widget(Clear, area);
+            f.render_widget(
+                Paragraph::new(text.as_str()).block(Block::default().borders(Borders::ALL).title(prompt.title())),
+                area,
+            );
+            f.set_cursor(area.x + 1 + text.chars().count() as u16, area.y + 1);
+        }
+        Mode::Assign { item, row } => {
+            let Some(bi) = app.bill.items.get(*item) else { return };
+            let mut rows = vec![ListItem::new(format!(
+                "[{}] Everyone",
+                if bi.assignment == Assignment::Everyone { "x" } else { " " }
+            ))];
+            rows.extend(app.bill.participants.iter().map(|p| {
+                ListItem::new(format!("[{}] {}", if App::is_checked(bi, p.id) { "x" } else { " " }, p.name))
+            }));
+            let area = centered(f.size(), 40, rows.len() as u16 + 2);
+            let mut state = ListState::default().with_selected(Some(*row));
+            f.render_widget(Clear, area);
+            f.render_stateful_widget(
+                List::new(rows)
+                    .block(Block::default().borders(Borders::ALL).title(format!("Who had {}? (space, enter)", bi.name)))
+                    .highlight_style(highlight),
+                area,
+                &mut state,
+            );
+        }
+    }
+}
+
+/// Runs the terminal UI until the user quits. `path` is where `s` saves to.
+pub fn run(bill: Bill, path: Option<PathBuf>) -> Result<(), String> {
+    enable_raw_mode().map_err(|e| e.to_string())?;
+    execute!(stdout(), EnterAlternateScreen).map_err(|e| e.to_string())?;
+    let result = event_loop(App::new(bill, path));
+    // Give the terminal back even if the loop failed
+    let _ = disable_raw_mode();
+    let _ = execute!(stdout(), LeaveAlternateScreen);
+    result
+}
+
+fn event_loop(mut app: App) -> Result<(), String> {
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout())).map_err(|e| e.to_string())?;
+    while !app.quit {
+        terminal.draw(|f| draw(f, &app)).map_err(|e| e.to_string())?;
+        if let Event::Key(key) = event::read().map_err(|e| e.to_string())? {
+            if key.kind == KeyEventKind::Press {
+                app.on_key(key);
+            }
+        }
+    }
+    terminal.show_cursor().map_err(|e| e.to_string())
+}
@@ -0,0 +1,518 @@
+use std::collections::{BTreeMap, HashMap};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer, Serialize};
+use uuid::Uuid;
+use crate::app::{Assignment, TreatMode, UnassignedPolicy};
+use crate::bill::{Bill, BillItem, BillParticipant};
+use crate::ops::{BillOp, BillSettings, SettingsPatch};
+
+/// A Lamport clock reading. Ties are broken by the device that made the
+/// edit, so every device puts concurrent edits in the same order.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Stamp {
+    pub clock: u64,
+    pub actor: Uuid,
+}
+
+impl Stamp {
+    /// Later than any edit, for entries nobody has added yet.
+    const NEVER: Stamp = Stamp { clock: u64::MAX, actor: Uuid::from_u128(u128::MAX) };
+}
+
+/// A value where the edit with the latest stamp wins.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lww<T> {
+    pub value: T,
+    pub stamp: Stamp,
+}
+
+impl<T: Clone> Lww<T> {
+    fn new(value: T, stamp: Stamp) -> Self {
+        Lww { value, stamp }
+    }
+
+    /// Returns whether the edit won.
+    fn set(&mut self, value: T, stamp: Stamp) -> bool {
+        if stamp > self.stamp {
+            self.value = value;
+            self.stamp = stamp;
+            return true;
+        }
+        false
+    }
+
+    fn merge(&mut self, other: &Lww<T>) -> bool {
+        self.set(other.value.clone(), other.stamp)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct ParticipantEntry {
+    added: Stamp,
+    /// Removing only touches this flag, so it wins over concurrent edits to
+    /// the other fields. Adding the same id again, e.g. when restoring an old
+    /// revision, brings the participant back.
+    present: Lww<bool>,
+    name: Lww<String>,
+    payer: Lww<bool>,
+    settled: Lww<bool>,
+    weight: Lww<Decimal>,
+}
+
+impl ParticipantEntry {
+    fn new(bp: &BillParticipant, stamp: Stamp) -> Self {
+        ParticipantEntry {
+            added: stamp,
+            present: Lww::new(true, stamp),
+            name: Lww::new(bp.name.clone(), stamp),
+            payer: Lww::new(bp.payer, stamp),
+            settled: Lww::new(bp.settled, stamp),
+            weight: Lww::new(bp.weight, stamp),
+        }
+    }
+
+    /// Holds edits to a participant whose add hasn't arrived yet. It isn't
+    /// shown, and the add fills in every field the edits didn't touch.
+    fn unseen() -> Self {
+        ParticipantEntry {
+            added: Stamp::NEVER,
+            present: Lww::default(),
+            name: Lww::default(),
+            payer: Lww::default(),
+            settled: Lww::default(),
+            weight: Lww::default(),
+        }
+    }
+
+    fn merge(&mut self, other: &ParticipantEntry) -> bool {
+        let added = self.added.min(other.added);
+        let mut changed = added != self.added;
+        self.added = added;
+        changed |= self.present.merge(&other.present);
+        changed |= self.name.merge(&other.name);
+        changed |= self.payer.merge(&other.payer);
+        changed |= self.settled.merge(&other.settled);
+        changed |= self.weight.merge(&other.weight);
+        changed
+    }
+
+    fn to_participant(&self, id: Uuid) -> BillParticipant {
+        BillParticipant {
+            id,
+            name: self.name.value.clone(),
+            payer: self.payer.value,
+            settled: self.settled.value,
+            weight: self.weight.value,
+        }
+    }
+}
+
+fn one() -> Lww<u32> {
+    Lww::new(1, Stamp::default())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct ItemEntry {
+    added: Stamp,
+    present: Lww<bool>,
+    name: Lww<String>,
+    price: Lww<Decimal>,
+    // Documents stored before quantities existed have neither of these
+    #[serde(default = "one")]
+    quantity: Lww<u32>,
+    #[serde(default)]
+    category: Lww<String>,
+    assignment: Lww<Assignment>,
+    participants: Lww<Vec<Uuid>>,
+}
+
+impl ItemEntry {
+    fn new(bi: &BillItem, stamp: Stamp) -> Self {
+        ItemEntry {
+            added: stamp,
+            present: Lww::new(true, stamp),
+            name: Lww::new(bi.name.clone(), stamp),
+            price: Lww::new(bi.price, stamp),
+            quantity: Lww::new(bi.quantity, stamp),
+            category: Lww::new(bi.category.clone(), stamp),
+            assignment: Lww::new(bi.assignment.clone(), stamp),
+            participants: Lww::new(bi.participants.clone(), stamp),
+        }
+    }
+
+    /// See `ParticipantEntry::unseen`.
+    fn unseen() -> Self {
+        ItemEntry {
+            added: Stamp::NEVER,
+            present: Lww::default(),
+            name: Lww::default(),
+            price: Lww::default(),
+            quantity: Lww::default(),
+            category: Lww::default(),
+            assignment: Lww::default(),
+            participants: Lww::default(),
+        }
+    }
+
+    fn merge(&mut self, other: &ItemEntry) -> bool {
+        let added = self.added.min(other.added);
+        let mut changed = added != self.added;
+        self.added = added;
+        changed |= self.present.merge(&other.present);
+        changed |= self.name.merge(&other.name);
+        changed |= self.price.merge(&other.price);
+        changed |= self.quantity.merge(&other.quantity);
+        changed |= self.category.merge(&other.category);
+        changed |= self.assignment.merge(&other.assignment);
+        changed |= self.participants.merge(&other.participants);
+        changed
+    }
+
+    fn to_item(&self, id: Uuid) -> BillItem {
+        BillItem {
+            id,
+            name: self.name.value.clone(),
+            price: self.price.value,
+            quantity: self.quantity.value,
+            category: self.category.value.clone(),
+            assignment: self.assignment.value.clone(),
+            participants: self.participants.value.clone(),
+        }
+    }
+}
+
+/// Each setting is a register of its own, so changing the tip on one device
+/// and the tax on another keeps both.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct SettingsEntry {
+    event_name: Lww<String>,
+    total_tax: Lww<Decimal>,
+    tip: Lww<Decimal>,
+    discount: Lww<Decimal>,
+    receipt_total: Lww<Option<Decimal>>,
+    unassigned_policy: Lww<UnassignedPolicy>,
+    treated: Lww<Vec<Uuid>>,
+    treat_mode: Lww<TreatMode>,
+    caps: Lww<HashMap<Uuid, Decimal>>,
+    bill_cap: Lww<Option<Decimal>>,
+    sponsor: Lww<Option<Uuid>>,
+    settle_status: Lww<bool>,
+    finalized: Lww<Option<String>>,
+}
+
+impl SettingsEntry {
+    fn new(settings: &BillSettings, stamp: Stamp) -> Self {
+        SettingsEntry {
+            event_name: Lww::new(settings.event_name.clone(), stamp),
+            total_tax: Lww::new(settings.total_tax, stamp),
+            tip: Lww::new(settings.tip, stamp),
+            discount: Lww::new(settings.discount, stamp),
+            receipt_total: Lww::new(settings.receipt_total, stamp),
+            unassigned_policy: Lww::new(settings.unassigned_policy, stamp),
+            treated: Lww::new(settings.treated.clone(), stamp),
+            treat_mode: Lww::new(settings.treat_mode, stamp),
+            caps: Lww::new(settings.caps.clone(), stamp),
+            bill_cap: Lww::new(settings.bill_cap, stamp),
+            sponsor: Lww::new(settings.sponsor, stamp),
+            settle_status: Lww::new(settings.settle_status, stamp),
+            finalized: Lww::new(settings.finalized.clone(), stamp),
+        }
+    }
+
+    fn set(&mut self, patch: &SettingsPatch, stamp: Stamp) -> bool {
+        let mut changed = false;
+        if let Some(event_name) = &patch.event_name {
+            changed |= self.event_name.set(event_name.clone(), stamp);
+        }
+        if let Some(total_tax) = patch.total_tax {
+            changed |= self.total_tax.set(total_tax, stamp);
+        }
+        if let Some(tip) = patch.tip {
+            changed |= self.tip.set(tip, stamp);
+        }
+        if let Some(discount) = patch.discount {
+            changed |= self.discount.set(discount, stamp);
+        }
+        if let Some(receipt_total) = patch.receipt_total {
+            changed |= self.receipt_total.set(receipt_total, stamp);
+        }
+        if let Some(unassigned_policy) = patch.unassigned_policy {
+            changed |= self.unassigned_policy.set(unassigned_policy, stamp);
+        }
+        if let Some(treated) = &patch.treated {
+            changed |= self.treated.set(treated.clone(), stamp);
+        }
+        if let Some(treat_mode) = patch.treat_mode {
+            changed |= self.treat_mode.set(treat_mode, stamp);
+        }
+        if let Some(caps) = &patch.caps {
+            changed |= self.caps.set(caps.clone(), stamp);
+        }
+        if let Some(bill_cap) = patch.bill_cap {
+            changed |= self.bill_cap.set(bill_cap, stamp);
+        }
+        if let Some(sponsor) = patch.sponsor {
+            changed |= self.sponsor.set(sponsor, stamp);
+        }
+        if let Some(settle_status) = patch.settle_status {
+            changed |= self.settle_status.set(settle_status, stamp);
+        }
+        if let Some(finalized) = &patch.finalized {
+            changed |= self.finalized.set(finalized.clone(), stamp);
+        }
+        changed
+    }
+
+    fn merge(&mut self, other: &SettingsEntry) {
+        self.event_name.merge(&other.event_name);
+        self.total_tax.merge(&other.total_tax);
+        self.tip.merge(&other.tip);
+        self.discount.merge(&other.discount);
+        self.receipt_total.merge(&other.receipt_total);
+        self.unassigned_policy.merge(&other.unassigned_policy);
+        self.treated.merge(&other.treated);
+        self.treat_mode.merge(&other.treat_mode);
+        self.caps.merge(&other.caps);
+        self.bill_cap.merge(&other.bill_cap);
+        self.sponsor.merge(&other.sponsor);
+        self.settle_status.merge(&other.settle_status);
+        self.finalized.merge(&other.finalized);
+    }
+
+    fn to_settings(&self) -> BillSettings {
+        BillSettings {
+            event_name: self.event_name.value.clone(),
+            total_tax: self.total_tax.value,
+            tip: self.tip.value,
+            discount: self.discount.value,
+            receipt_total: self.receipt_total.value,
+            unassigned_policy: self.unassigned_policy.value,
+            treated: self.treated.value.clone(),
+            treat_mode: self.treat_mode.value,
+            caps: self.caps.value.clone(),
+            bill_cap: self.bill_cap.value,
+            sponsor: self.sponsor.value,
+            settle_status: self.settle_status.value,
+            finalized: self.finalized.value.clone(),
+        }
+    }
+}
+
+/// Documents stored before settings were split up kept them all in one
+/// register; every setting starts out with its stamp.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredSettings {
+    Fields(SettingsEntry),
+    Whole(Lww<BillSettings>),
+}
+
+fn settings_entry<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SettingsEntry, D::Error> {
+    Ok(match StoredSettings::deserialize(deserializer)? {
+        StoredSettings::Fields(entry) => entry,
+        StoredSettings::Whole(whole) => SettingsEntry::new(&whole.value, whole.stamp),
+    })
+}
+
+/// A `BillOp` together with when and where it was made.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StampedOp {
+    pub stamp: Stamp,
+    pub op: BillOp,
+}
+
+/// A bill that can be edited on several devices at once, offline or not, and
+/// merged back together. Every field of every participant and item keeps the
+/// stamp of its last edit, so merging two documents gives the same bill no
+/// matter which order they are merged in.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BillDoc {
+    pub id: Uuid,
+    /// The highest clock seen so far.
+    pub clock: u64,
+    participants: BTreeMap<Uuid, ParticipantEntry>,
+    items: BTreeMap<Uuid, ItemEntry>,
+    #[serde(deserialize_with = "settings_entry")]
+    settings: SettingsEntry,
+}
+
+impl BillDoc {
+    /// Starts a document from a saved bill. Every device starting from the
+    /// same bill ends up with the same document.
+    pub fn from_bill(bill: &Bill) -> Self {
+        let stamp = Stamp::default();
+        BillDoc {
+            id: bill.id,
+            clock: 0,
+            participants: bill.participants.iter().map(|bp| (bp.id, ParticipantEntry::new(bp, stamp))).collect(),
+            items: bill.items.iter().map(|bi| (bi.id, ItemEntry::new(bi, stamp))).collect(),
+            settings: SettingsEntry::new(&bill.settings(), stamp),
+        }
+    }
+
+    /// Records edits made on this device, and returns the ones that took
+    /// stamped, so they can be sent to everyone else. A finalized bill only
+    /// takes the op unlocking it, as with `Bill::apply`.
+    pub fn apply_local(&mut self, actor: Uuid, ops: &[BillOp]) -> Vec<StampedOp> {
+        ops.iter()
+            .filter_map(|op| {
+                if self.is_finalized() && !op.unlocks() {
+                    return None;
+                }
+                let stamped = StampedOp {
+                    stamp: Stamp { clock: self.clock + 1, actor },
+                    op: op.clone(),
+                };
+                self.apply(&stamped).then_some(stamped)
+            })
+            .collect()
+    }
+
+    pub fn is_finalized(&self) -> bool {
+        self.settings.finalized.value.is_some()
+    }
+
+    /// Applies an edit from any device, and returns whether it changed
+    /// anything. Applying the same edit twice, or edits in a different order,
+    /// gives the same result, and so does merging documents holding them.
+    /// Edits to a participant or item that hasn't been added yet are kept
+    /// until it is.
+    ///
+    /// The lock is kept where edits are made, by `apply_local` and the
+    /// server, not here: an edit made at the same time as the bill was
+    /// finalized is kept on every device whichever arrives first, and shows
+    /// up as the content hash no longer matching.
+    pub fn apply(&mut self, stamped: &StampedOp) -> bool {
+        let stamp = stamped.stamp;
+        self.clock = self.clock.max(stamp.clock);
+        match &stamped.op {
+            BillOp::AddParticipant(bp) => {
+                let entry = ParticipantEntry::new(bp, stamp);
+                match self.participants.get_mut(&bp.id) {
+                    Some(existing) => existing.merge(&entry),
+                    None => {
+                        self.participants.insert(bp.id, entry);
+                        true
+                    }
+                }
+            }
+            BillOp::UpdateParticipant(patch) => {
+                let entry = self.participants.entry(patch.id).or_insert_with(ParticipantEntry::unseen);
+                let mut changed = false;
+                if let Some(name) = &patch.name {
+                    changed |= entry.name.set(name.clone(), stamp);
+                }
+                if let Some(payer) = patch.payer {
+                    changed |= entry.payer.set(payer, stamp);
+                }
+                if let Some(settled) = patch.settled {
+                    changed |= entry.settled.set(settled, stamp);
+                }
+                if let Some(weight) = patch.weight {
+                    changed |= entry.weight.set(weight, stamp);
+                }
+                changed
+            }
+            BillOp::RemoveParticipant(id) => self
+                .participants
+                .entry(*id)
+                .or_insert_with(ParticipantEntry::unseen)
+                .present
+                .set(false, stamp),
+            BillOp::AddItem(bi) => {
+                let entry = ItemEntry::new(bi, stamp);
+                match self.items.get_mut(&bi.id) {
+                    Some(existing) => existing.merge(&entry),
+                    None => {
+                        self.items.insert(bi.id, entry);
+                        true
+                    }
+                }
+            }
+            BillOp::UpdateItem(patch) => {
+                let entry = self.items.entry(patch.id).or_insert_with(ItemEntry::unseen);
+                let mut changed = false;
+                if let Some(name) = &patch.name {
+                    changed |= entry.name.set(name.clone(), stamp);
+                }
+                if let Some(price) = patch.price {
+                    changed |= entry.price.set(price, stamp);
+                }
+                if let Some(quantity) = patch.quantity {
+                    changed |= entry.quantity.set(quantity, stamp);
+                }
+                if let Some(category) = &patch.category {
+                    changed |= entry.category.set(category.clone(), stamp);
+                }
+                if let Some(assignment) = &patch.assignment {
+                    changed |= entry.assignment.set(assignment.clone(), stamp);
+                }
+                if let Some(participants) = &patch.participants {
+                    changed |= entry.participants.set(participants.clone(), stamp);
+                }
+                changed
+            }
+            BillOp::RemoveItem(id) => self
+                .items
+                .entry(*id)
+                .or_insert_with(ItemEntry::unseen)
+                .present
+                .set(false, stamp),
+            BillOp::UpdateSettings(patch) => self.settings.set(patch, stamp),
+        }
+    }
+
+    /// Merges in everything another device knows about this bill.
+    pub fn merge(&mut self, other: &BillDoc) {
+        self.clock = self.clock.max(other.clock);
+        for (id, entry) in other.participants.iter() {
+            self.participants
+                .entry(*id)
+                .and_modify(|e| {
+                    e.merge(entry);
+                })
+                .or_insert_with(|| entry.clone());
+        }
+        for (id, entry) in other.items.iter() {
+            self.items
+                .entry(*id)
+                .and_modify(|e| {
+                    e.merge(entry);
+                })
+                .or_insert_with(|| entry.clone());
+        }
+        self.settings.merge(&other.settings);
+    }
+
+    /// The bill as it stands after every edit. Participants and items are
+    /// listed in the order they were added, and references to removed
+    /// participants are dropped.
+    pub fn to_bill(&self) -> Bill {
+        let mut participants: Vec<_> = self.participants.iter().filter(|(_, e)| e.present.value).collect();
+        participants.sort_by_key(|(id, e)| (e.added, **id));
+        let mut items: Vec<_> = self.items.iter().filter(|(_, e)| e.present.value).collect();
+        items.sort_by_key(|(id, e)| (e.added, **id));
+
+        let is_present = |id: &Uuid| participants.iter().any(|(p, _)| *p == id);
+        let mut settings = self.settings.to_settings();
+        settings.treated.retain(|id| is_present(id));
+        settings.caps.retain(|id, _| is_present(id));
+        settings.sponsor = settings.sponsor.filter(|id| is_present(id));
+
+        let mut bill = Bill::empty(self.id);
+        bill.participants = participants.iter().map(|(id, e)| e.to_participant(**id)).collect();
+        bill.items = items
+            .iter()
+            .map(|(id, e)| {
+                let mut item = e.to_item(**id);
+                item.participants.retain(|id| is_present(id));
+                if let Assignment::EveryoneExcept(excluded) = &mut item.assignment {
+                    excluded.retain(|id| is_present(id));
+                }
+                item
+            })
+            .collect();
+        bill.set_settings(&settings);
+        bill
+    }
+}
@@ -0,0 +1,78 @@
+use aes_gcm::{aead::{Aead, KeyInit}, Aes256Gcm, Nonce};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use uuid::Uuid;
+use crate::bill::Bill;
+
+/// Prefix of the URL fragment carrying the decryption key, e.g. `#key=...`.
+pub const KEY_FRAGMENT_PREFIX: &str = "key=";
+
+const NONCE_LEN: usize = 12;
+
+/// A fresh random AES-256 key, base64url-encoded for the URL fragment.
+pub fn generate_key() -> Result<String, String> {
+    let mut key = [0u8; 32];
+    getrandom::getrandom(&mut key).map_err(|e| e.to_string())?;
+    Ok(URL_SAFE_NO_PAD.encode(key))
+}
+
+/// Encrypts the serialised bill with AES-256-GCM. The random nonce is
+/// prepended to the ciphertext.
+pub fn encrypt_bill(bill: &Bill, key: &str) -> Result<String, String> {
+    let cipher = cipher(key)?;
+    let mut nonce = [0u8; NONCE_LEN];
+    getrandom::getrandom(&mut nonce).map_err(|e| e.to_string())?;
+    let plaintext = serde_json::to_vec(bill).map_err(|e| e.to_string())?;
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+        .map_err(|_| String::from("couldn't encrypt the bill"))?;
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    Ok(URL_SAFE_NO_PAD.encode(sealed))
+}
+
+pub fn decrypt_bill(sealed: &str, key: &str) -> Result<Bill, String> {
+    let cipher = cipher(key)?;
+    let sealed = URL_SAFE_NO_PAD
+        .decode(sealed)
+        .map_err(|e| format!("the stored bill is damaged: {}", e))?;
+    if sealed.len() < NONCE_LEN {
+        return Err(String::from("the stored bill is damaged"));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| String::from("the key in this link doesn't open the bill"))?;
+    serde_json::from_slice(&plaintext).map_err(|e| e.to_string())
+}
+
+/// What the server asks for before saving over an encrypted bill: an
+/// HMAC-SHA256 of the bill's id under the key. Only holders of the key can
+/// make it, and it doesn't give the key away.
+pub fn write_token(id: Uuid, key: &str) -> Result<String, String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(&decode_key(key)?)
+        .map_err(|_| String::from("the key in this link is invalid"))?;
+    mac.update(id.as_bytes());
+    Ok(URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes()))
+}
+
+pub fn key_from_fragment(hash: &str) -> Option<String> {
+    hash.trim_start_matches('#')
+        .strip_prefix(KEY_FRAGMENT_PREFIX)
+        .map(String::from)
+}
+
+pub fn encrypted_bill_path(id: Uuid, key: &str) -> String {
+    format!("/e/{}#{}{}", id, KEY_FRAGMENT_PREFIX, key)
+}
+
+fn decode_key(key: &str) -> Result<Vec<u8>, String> {
+    URL_SAFE_NO_PAD
+        .decode(key)
+        .map_err(|_| String::from("the key in this link is invalid"))
+}
+
+fn cipher(key: &str) -> Result<Aes256Gcm, String> {
+    Aes256Gcm::new_from_slice(&decode_key(key)?).map_err(|_| String::from("the key in this link is invalid"))
+}
@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use csv::{ReaderBuilder, StringRecord, Trim, Writer};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+use crate::app::{Assignment, SplitItem};
+use crate::bill::{default_quantity, Bill, BillItem, BillParticipant};
+use crate::ops::BillOp;
+use crate::split::paid_amounts;
+
+fn finish(writer: Writer<Vec<u8>>) -> Result<String, String> {
+    let bytes = writer.into_inner().map_err(|e| e.to_string())?;
+    String::from_utf8(bytes).map_err(|e| e.to_string())
+}
+
+fn ensure_split(split: &SplitItem) -> Result<(), String> {
+    if split.blocked() {
+        return Err(String::from("the split is blocked until every item is assigned"));
+    }
+    Ok(())
+}
+
+/// One row per participant: what their share is made of, what they paid at
+/// the till and where that leaves them. Built from the same breakdown as the
+/// summary text, so `total` is the summary's "Total Amount Owed". With
+/// several payers, `paid` is an equal part of the bill each, as the summary
+/// says.
+pub fn participants_csv(split: &SplitItem) -> Result<String, String> {
+    ensure_split(split)?;
+    let bill = split.to_bill();
+    let shares = split.final_shares();
+    let breakdowns = split.breakdowns();
+    let paid = paid_amounts(&bill, &shares);
+
+    let mut writer = Writer::from_writer(Vec::new());
+    writer
+        .write_record(["participant", "subtotal", "tax", "tip", "discount", "treats", "caps", "total", "paid", "net"])
+        .map_err(|e| e.to_string())?;
+    for p in bill.participants.iter() {
+        let b = breakdowns.get(&p.id).cloned().unwrap_or_default();
+        let total = shares.get(&p.id).copied().unwrap_or_default();
+        let paid = paid.get(&p.id).copied().unwrap_or_default().round_dp(2);
+        writer
+            .write_record([
+                p.name.clone(),
+                b.items.to_string(),
+                b.tax.to_string(),
+                b.tip.to_string(),
+                b.discount.to_string(),
+                b.treat.to_string(),
+                b.cap.to_string(),
+                total.to_string(),
+                paid.to_string(),
+                (paid - total).round_dp(2).to_string(),
+            ])
+            .map_err(|e| e.to_string())?;
+    }
+    finish(writer)
+}
+
+/// One row for every participant on every item they share, with their part
+/// of its price. Items nobody shares yet are left out.
+pub fn items_csv(split: &SplitItem) -> Result<String, String> {
+    ensure_split(split)?;
+    let bill = split.to_bill();
+    let names: HashMap<Uuid, &str> = bill.participants.iter().map(|p| (p.id, p.name.as_str())).collect();
+
+    let mut writer = Writer::from_writer(Vec::new());
+    writer
+        .write_record(["item", "price", "participant", "amount"])
+        .map_err(|e| e.to_string())?;
+    for (item_id, participant_id, amount) in split.item_shares() {
+        let Some(item) = bill.items.iter().find(|i| i.id == item_id) else {
+            continue;
+        };
+        writer
+            .write_record([
+                item.name.as_str(),
+                &item.price.to_string(),
+                names.get(&participant_id).copied().unwrap_or_default(),
+                &amount.to_string(),
+            ])
+            .map_err(|e| e.to_string())?;
+    }
+    finish(writer)
+}
+
+/// A line item read from a CSV file, not on the bill yet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImportedItem {
+    pub name: String,
+    pub quantity: u32,
+    pub unit_price: Decimal,
+    pub category: String,
+    /// As written in the file. "all" or "everyone" assigns it to everyone.
+    pub participants: Vec<String>,
+}
+
+impl ImportedItem {
+    pub fn price(&self) -> Decimal {
+        (self.unit_price * Decimal::from(self.quantity)).round_dp(2)
+    }
+
+    fn is_for_everyone(&self) -> bool {
+        self.participants.iter().any(|name| matches!(name.to_lowercase().as_str(), "all" | "everyone"))
+    }
+
+    /// Names on the row that nobody on `bill` goes by.
+    pub fn unknown_names(&self, bill: &Bill) -> Vec<String> {
+        if self.is_for_everyone() {
+            return Vec::new();
+        }
+        self.participants
+            .iter()
+            .filter(|name| find_participant(bill, name).is_none())
+            .cloned()
+            .collect()
+    }
+}
+
+/// A data row of an imported file, with the line it came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImportRow {
+    pub line: u64,
+    pub item: Result<ImportedItem, String>,
+}
+
+impl ImportRow {
+    /// Whether the row can be added to `bill`, and what else that adds.
+    pub fn status(&self, bill: &Bill, create_missing: bool) -> Result<Option<String>, String> {
+        let item = self.item.as_ref().map_err(Clone::clone)?;
+        let unknown = item.unknown_names(bill);
+        match (unknown.is_empty(), create_missing) {
+            (true, _) => Ok(None),
+            (false, true) => Ok(Some(format!("adds {}", unknown.join(", ")))),
+            (false, false) => Err(format!("{} isn't on the bill", unknown.join(", "))),
+        }
+    }
+}
+
+const NAME_COLUMNS: &[&str] = &["name", "item", "item name", "description"];
+const QUANTITY_COLUMNS: &[&str] = &["quantity", "qty", "count"];
+const PRICE_COLUMNS: &[&str] = &["unit price", "unit_price", "price", "rate"];
+const CATEGORY_COLUMNS: &[&str] = &["category", "type"];
+const PARTICIPANT_COLUMNS: &[&str] = &["participants", "assigned to", "assigned", "shared by", "people"];
+
+fn column(headers: &StringRecord, names: &[&str]) -> Option<usize> {
+    headers.iter().position(|h| names.contains(&h.trim().to_lowercase().as_str()))
+}
+
+/// Reads an amount as people write it, e.g. `₹1,250.00` or `Rs. 90`.
+pub fn parse_amount(text: &str) -> Option<Decimal> {
+    let cleaned: String = text
+        .trim()
+        .trim_start_matches("INR")
+        .trim_start_matches("Rs.")
+        .trim_start_matches("Rs")
+        .chars()
+        .filter(|c| !matches!(c, '₹' | ',' | ' '))
+        .collect();
+    cleaned.parse::<Decimal>().ok()
+}
+
+/// The participant a name from a file or a receipt means, ignoring case and
+/// spacing.
+pub fn find_participant<'a>(bill: &'a Bill, name: &str) -> Option<&'a BillParticipant> {
+    let name = name.trim();
+    bill.participants.iter().find(|p| p.name.trim().eq_ignore_ascii_case(name))
+}
+
+struct Columns {
+    name: usize,
+    price: usize,
+    quantity: Option<usize>,
+    category: Option<usize>,
+    participants: Option<usize>,
+}
+
+fn parse_row(record: &StringRecord, columns: &Columns) -> Result<ImportedItem, String> {
+    let field = |i: Option<usize>| i.and_then(|i| record.get(i)).unwrap_or_default();
+    let name = field(Some(columns.name));
+    if name.is_empty() {
+        return Err(String::from("the name is empty"));
+    }
+    let price = field(Some(columns.price));
+    let unit_price = parse_amount(price).ok_or_else(|| format!("'{}' isn't a price", price))?;
+    if unit_price < Decimal::ZERO {
+        return Err(String::from("the price can't be negative"));
+    }
+    let quantity = match field(columns.quantity) {
+        "" => default_quantity(),
+        quantity => quantity
+            .parse::<u32>()
+            .ok()
+            .filter(|q| *q > 0)
+            .ok_or_else(|| format!("'{}' isn't a quantity", quantity))?,
+    };
+    Ok(ImportedItem {
+        name: name.to_string(),
+        quantity,
+        unit_price,
+        category: field(columns.category).to_string(),
+        participants: field(columns.participants)
+            .split([';', '|', ','])
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(String::from)
+            .collect(),
+    })
+}
+
+/// Reads line items from a CSV file with a header row. Name and unit price
+/// columns are needed; quantity, category and participants are optional.
+/// Participants go in one cell, separated by `;`, `|` or `,`.
+pub fn parse_items_csv(text: &str) -> Result<Vec<ImportRow>, String> {
+    let mut reader = ReaderBuilder::new().flexible(true).trim(Trim::All).from_reader(text.as_bytes());
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+    let columns = Columns {
+        name: column(&headers, NAME_COLUMNS).ok_or("there's no name column")?,
+        price: column(&headers, PRICE_COLUMNS).ok_or("there's no unit price column")?,
+        quantity: column(&headers, QUANTITY_COLUMNS),
+        category: column(&headers, CATEGORY_COLUMNS),
+        participants: column(&headers, PARTICIPANT_COLUMNS),
+    };
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let row = match record {
+            Ok(record) if record.iter().all(str::is_empty) => continue,
+            Ok(record) => ImportRow {
+                line: record.position().map_or(0, |p| p.line()),
+                item: parse_row(&record, &columns),
+            },
+            Err(e) => ImportRow {
+                line: e.position().map_or(0, |p| p.line()),
+                item: Err(e.to_string()),
+            },
+        };
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// The edits that add every row without errors to `bill`. With
+/// `create_missing`, people the rows name who aren't on the bill are added
+/// first; without it, rows naming them are left out.
+pub fn import_ops(bill: &Bill, rows: &[ImportRow], create_missing: bool) -> Vec<BillOp> {
+    let mut bill = bill.clone();
+    let mut ops = Vec::new();
+    for row in rows.iter() {
+        // Checked against the bill so far, so a name added for one row counts
+        // for the rows after it
+        if row.status(&bill, create_missing).is_err() {
+            continue;
+        }
+        let Ok(item) = &row.item else {
+            continue;
+        };
+        for name in item.unknown_names(&bill) {
+            // The same new name can come up twice on a row
+            if find_participant(&bill, &name).is_some() {
+                continue;
+            }
+            let op = BillOp::AddParticipant(BillParticipant {
+                id: Uuid::new_v4(),
+                name,
+                payer: false,
+                settled: false,
+                weight: Decimal::ONE,
+            });
+            bill.apply(&op);
+            ops.push(op);
+        }
+        let (assignment, participants) = if item.is_for_everyone() {
+            (Assignment::Everyone, Vec::new())
+        } else {
+            let ids = item
+                .participants
+                .iter()
+                .filter_map(|name| find_participant(&bill, name).map(|p| p.id))
+                .collect();
+            (Assignment::Selected, ids)
+        };
+        let op = BillOp::AddItem(BillItem {
+            id: Uuid::new_v4(),
+            name: item.name.clone(),
+            price: item.price(),
+            quantity: item.quantity,
+            category: item.category.clone(),
+            assignment,
+            participants,
+        });
+        bill.apply(&op);
+        ops.push(op);
+    }
+    ops
+}
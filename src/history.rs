@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::app::{Assignment, SplitItem};
+use crate::bill::Bill;
+use crate::ops::BillOp;
+
+/// One stored change to a bill: who made it, when, and the ops that turn the
+/// previous revision into this one.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BillEvent {
+    pub revision: i64,
+    pub author: String,
+    pub created_at: i64,
+    /// Set when this change put the bill back the way it was at that revision.
+    pub restored_from: Option<i64>,
+    pub ops: Vec<BillOp>,
+}
+
+/// The bill as it was at `revision`, replayed from the start of its history.
+pub fn bill_at(id: Uuid, events: &[BillEvent], revision: i64) -> Bill {
+    let mut bill = Bill::empty(id);
+    for event in events.iter().filter(|e| e.revision <= revision) {
+        for op in event.ops.iter() {
+            bill.apply(op);
+        }
+    }
+    bill
+}
+
+fn money(amount: Decimal) -> String {
+    format!("₹{}", amount.round_dp(2))
+}
+
+fn participant_name(bill: &Bill, id: Uuid) -> String {
+    bill.participants
+        .iter()
+        .find(|p| p.id == id)
+        .map(|p| p.name.clone())
+        .unwrap_or_else(|| String::from("Someone"))
+}
+
+fn item_name(bill: &Bill, id: Uuid) -> String {
+    bill.items
+        .iter()
+        .find(|i| i.id == id)
+        .map(|i| i.name.clone())
+        .unwrap_or_else(|| String::from("An item"))
+}
+
+/// Describes an op in words, using `before` to name what it changed.
+pub fn describe_op(before: &Bill, op: &BillOp) -> Vec<String> {
+    match op {
+        BillOp::AddParticipant(bp) => vec![format!("{} joined", bp.name)],
+        BillOp::UpdateParticipant(patch) => {
+            let name = participant_name(before, patch.id);
+            let old = before.participants.iter().find(|p| p.id == patch.id);
+            let mut changes = Vec::new();
+            if let Some(new_name) = &patch.name {
+                changes.push(format!("{} was renamed to {}", name, new_name));
+            }
+            if let Some(payer) = patch.payer {
+                changes.push(if payer { format!("{} is now paying", name) } else { format!("{} is no longer paying", name) });
+            }
+            if let Some(settled) = patch.settled {
+                changes.push(if settled { format!("{} settled up", name) } else { format!("{} is no longer settled", name) });
+            }
+            if let Some(weight) = patch.weight {
+                let old_weight = old.map(|p| p.weight).unwrap_or(Decimal::ONE);
+                changes.push(format!("{}'s weight went from {} to {}", name, old_weight, weight));
+            }
+            changes
+        }
+        BillOp::RemoveParticipant(id) => vec![format!("{} was removed", participant_name(before, *id))],
+        BillOp::AddItem(bi) => vec![format!("'{}' was added at {}", bi.name, money(bi.price))],
+        BillOp::UpdateItem(patch) => {
+            let name = item_name(before, patch.id);
+            let old = before.items.iter().find(|i| i.id == patch.id);
+            let mut changes = Vec::new();
+            if let Some(new_name) = &patch.name {
+                changes.push(format!("'{}' was renamed to '{}'", name, new_name));
+            }
+            if let Some(price) = patch.price {
+                let old_price = old.map(|i| i.price).unwrap_or_default();
+                changes.push(format!("'{}' went from {} to {}", name, money(old_price), money(price)));
+            }
+            if let Some(quantity) = patch.quantity {
+                let old_quantity = old.map(|i| i.quantity).unwrap_or(1);
+                changes.push(format!("'{}' went from {} to {} ordered", name, old_quantity, quantity));
+            }
+            if let Some(category) = &patch.category {
+                changes.push(format!("'{}' was moved to '{}'", name, category));
+            }
+            if patch.assignment.is_some() || patch.participants.is_some() {
+                changes.push(format!("'{}' was reassigned", name));
+            }
+            changes
+        }
+        BillOp::RemoveItem(id) => vec![format!("'{}' was removed", item_name(before, *id))],
+        BillOp::UpdateSettings(patch) => {
+            let mut settings = before.settings();
+            patch.apply_to(&mut settings);
+            let mut changes = Vec::new();
+            if settings.event_name != before.event_name {
+                changes.push(format!("The bill was renamed to '{}'", settings.event_name));
+            }
+            if settings.total_tax != before.total_tax {
+                changes.push(format!("Tax went from {} to {}", money(before.total_tax), money(settings.total_tax)));
+            }
+            if settings.tip != before.tip {
+                changes.push(format!("Tip went from {} to {}", money(before.tip), money(settings.tip)));
+            }
+            if settings.discount != before.discount {
+                changes.push(format!("Discount went from {} to {}", money(before.discount), money(settings.discount)));
+            }
+            if settings.receipt_total != before.receipt_total {
+                changes.push(String::from("The receipt total changed"));
+            }
+            if settings.unassigned_policy != before.unassigned_policy {
+                changes.push(format!("Unassigned items now: {}", settings.unassigned_policy.description()));
+            }
+            if settings.treated != before.treated || settings.treat_mode != before.treat_mode {
+                changes.push(String::from("Who is being treated changed"));
+            }
+            if settings.caps != before.caps || settings.bill_cap != before.bill_cap {
+                changes.push(String::from("The caps changed"));
+            }
+            if settings.sponsor != before.sponsor {
+                changes.push(match settings.sponsor {
+                    Some(id) => format!("{} now covers capped shares", participant_name(before, id)),
+                    None => String::from("Nobody covers capped shares any more"),
+                });
+            }
+            if settings.settle_status != before.settle_status {
+                changes.push(String::from("The bill's settled status changed"));
+            }
+            if settings.finalized != before.finalized {
+                changes.push(match &settings.finalized {
+                    Some(hash) => format!("The bill was finalized with hash {}", hash),
+                    None => String::from("The bill was unlocked"),
+                });
+            }
+            changes
+        }
+    }
+}
+
+/// Everything that changed between two revisions, in words.
+pub fn describe_changes(before: &Bill, after: &Bill) -> Vec<String> {
+    before.diff(after).iter().flat_map(|op| describe_op(before, op)).collect()
+}
+
+fn shares(bill: &Bill) -> HashMap<Uuid, Decimal> {
+    SplitItem::from_bill(bill).final_shares()
+}
+
+/// Whether `participant` pays towards `item_id` in `bill`.
+fn shares_item(bill: &Bill, item_id: Uuid, participant: Uuid) -> bool {
+    bill.items.iter().find(|i| i.id == item_id).is_some_and(|item| match &item.assignment {
+        Assignment::Selected => item.participants.contains(&participant),
+        Assignment::Everyone => true,
+        Assignment::EveryoneExcept(excluded) => !excluded.contains(&participant),
+    })
+}
+
+/// Whether `op` can explain a change in `participant`'s share.
+fn affects(before: &Bill, after: &Bill, op: &BillOp, participant: Uuid) -> bool {
+    match op {
+        BillOp::AddParticipant(_) | BillOp::RemoveParticipant(_) | BillOp::UpdateSettings(_) => true,
+        BillOp::UpdateParticipant(patch) => patch.id == participant || patch.weight.is_some(),
+        BillOp::AddItem(bi) => shares_item(after, bi.id, participant),
+        BillOp::RemoveItem(id) => shares_item(before, *id, participant),
+        BillOp::UpdateItem(patch) => {
+            shares_item(before, patch.id, participant) || shares_item(after, patch.id, participant)
+        }
+    }
+}
+
+/// How each participant's share changed between two revisions, and the
+/// changes that explain it, e.g. "Bob's share went from ₹540 to ₹610
+/// because 'Mojito' was reassigned".
+pub fn describe_share_changes(before: &Bill, after: &Bill) -> Vec<String> {
+    let old_shares = shares(before);
+    let new_shares = shares(after);
+    let ops = before.diff(after);
+    let mut people: Vec<(Uuid, String)> = after.participants.iter().map(|p| (p.id, p.name.clone())).collect();
+    for p in before.participants.iter().filter(|p| !after.participants.iter().any(|a| a.id == p.id)) {
+        people.push((p.id, p.name.clone()));
+    }
+
+    people
+        .into_iter()
+        .filter_map(|(id, name)| {
+            let old = old_shares.get(&id).copied().unwrap_or_default();
+            let new = new_shares.get(&id).copied().unwrap_or_default();
+            if old == new {
+                return None;
+            }
+            let reasons: Vec<String> = ops
+                .iter()
+                .filter(|op| affects(before, after, op, id))
+                .flat_map(|op| describe_op(before, op))
+                .collect();
+            let mut line = format!("{}'s share went from {} to {}", name, money(old), money(new));
+            if !reasons.is_empty() {
+                line.push_str(&format!(" because {}", reasons.join(", ")));
+            }
+            Some(line)
+        })
+        .collect()
+}
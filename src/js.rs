@@ -0,0 +1,118 @@
+//! The split engine for other JavaScript apps, without the Leptos UI. Built
+//! with the `js` feature; wasm-bindgen writes the TypeScript definitions
+//! below into the package's `.d.ts`.
+
+use js_sys::JSON;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use crate::bill::Bill;
+use crate::split::compute;
+
+#[wasm_bindgen(typescript_custom_section)]
+const TYPES: &'static str = r#"
+/** Amounts are decimal strings, e.g. "123.45", so nothing is lost to floats. */
+export type Money = string;
+
+export type Assignment = "Selected" | "Everyone" | { EveryoneExcept: string[] };
+
+export type UnassignedPolicy = "Block" | "SplitAmongEveryone" | "ChargePayer";
+
+export type TreatMode = "Equal" | "Proportional";
+
+export interface BillParticipant {
+    /** A fresh one is made up when missing. */
+    id?: string;
+    name: string;
+    payer?: boolean;
+    settled?: boolean;
+    /** Defaults to "1". */
+    weight?: Money;
+}
+
+export interface BillItem {
+    /** A fresh one is made up when missing. */
+    id?: string;
+    name: string;
+    /** For all of them together. */
+    price: Money;
+    /** Defaults to 1. */
+    quantity?: number;
+    category?: string;
+    /** Defaults to "Selected". */
+    assignment?: Assignment;
+    /** Ids of the participants who had the item, when `assignment` is "Selected". */
+    participants?: string[];
+}
+
+/** A bill, the same shape the web app and the JSON API use. */
+export interface Bill {
+    id?: string;
+    event_name?: string;
+    total_tax?: Money;
+    tip?: Money;
+    discount?: Money;
+    receipt_total?: Money | null;
+    unassigned_policy?: UnassignedPolicy;
+    treated?: string[];
+    treat_mode?: TreatMode;
+    caps?: Record<string, Money>;
+    bill_cap?: Money | null;
+    sponsor?: string | null;
+    settle_status?: boolean;
+    finalized?: string | null;
+    participants?: BillParticipant[];
+    items?: BillItem[];
+}
+
+export interface ShareBreakdown {
+    items: Money;
+    tax: Money;
+    tip: Money;
+    discount: Money;
+    treat: Money;
+    cap: Money;
+}
+
+export interface ParticipantShare {
+    id: string;
+    name: string;
+    share: Money;
+    breakdown: ShareBreakdown;
+}
+
+export interface Transfer {
+    from: string;
+    to: string;
+    amount: Money;
+}
+
+export interface SplitResult {
+    total: Money;
+    /** Set when unassigned items stop the split, see `UnassignedPolicy`. */
+    blocked: boolean;
+    shares: ParticipantShare[];
+    settlement: Transfer[];
+    summary: string;
+}
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(typescript_type = "SplitResult")]
+    pub type JsSplitResult;
+}
+
+/// Splits a bill given as JSON, by the same rules as the web app. Throws if
+/// the JSON isn't a valid bill.
+#[wasm_bindgen(js_name = computeSplit)]
+pub fn compute_split(bill_json: &str) -> Result<JsSplitResult, JsError> {
+    let mut bill: Bill = serde_json::from_str(bill_json).map_err(|e| JsError::new(&format!("not a bill: {}", e)))?;
+    bill.fill_missing_ids();
+    bill.validate().map_err(|e| JsError::new(&e.to_string()))?;
+
+    let result = compute(&bill);
+    let json = serde_json::to_string(&result).map_err(|e| JsError::new(&e.to_string()))?;
+    JSON::parse(&json)
+        .map(|value| value.unchecked_into())
+        .map_err(|_| JsError::new("couldn't read the split back"))
+}
@@ -1,4 +1,21 @@
 pub mod app;
+pub mod bill;
+pub mod crdt;
+pub mod crypto;
+pub mod csv_file;
+pub mod history;
+pub mod ops;
+pub mod quick_entry;
+pub mod receipt;
+pub mod share;
+pub mod split;
+pub mod storage;
+#[cfg(feature = "ssr")]
+pub mod api;
+#[cfg(feature = "js")]
+pub mod js;
+#[cfg(feature = "ssr")]
+pub mod realtime;
 use log::LevelFilter;
 
 #[cfg(feature = "hydrate")]
@@ -8,16 +8,20 @@ async fn main() -> std::io::Result<()> {
     use whati8::app::*;
 
     let conf = get_configuration(None).await.unwrap();
+    whati8::storage::db::init().expect("Couldn't open the bill database");
     let addr = conf.leptos_options.site_addr;
     // Generate the list of routes in your Leptos App
     let routes = generate_route_list(App);
     println!("listening on http://{}", &addr);
+    let rooms = web::Data::new(whati8::realtime::BillRooms::default());
 
     HttpServer::new(move || {
         let leptos_options = &conf.leptos_options;
         let site_root = &leptos_options.site_root;
 
         App::new()
+            .route("/ws/bill/{id}", web::get().to(whati8::realtime::bill_socket))
+            .configure(whati8::api::configure)
             .route("/api/{tail:.*}", leptos_actix::handle_server_fns())
             // serve JS/WASM/CSS from `pkg`
             .service(Files::new("/pkg", format!("{site_root}/pkg")))
@@ -27,6 +31,7 @@ async fn main() -> std::io::Result<()> {
             .service(favicon)
             .leptos_routes(leptos_options.to_owned(), routes.to_owned(), App)
             .app_data(web::Data::new(leptos_options.to_owned()))
+            .app_data(rooms.clone())
         //.wrap(middleware::Compress::default())
     })
     .bind(&addr)?
@@ -0,0 +1,390 @@
+use std::collections::HashMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer, Serialize};
+use uuid::Uuid;
+use crate::app::{Assignment, TreatMode, UnassignedPolicy};
+use crate::bill::{Bill, BillItem, BillParticipant};
+
+/// Everything on a bill that isn't a participant or an item.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BillSettings {
+    pub event_name: String,
+    pub total_tax: Decimal,
+    #[serde(default)]
+    pub tip: Decimal,
+    #[serde(default)]
+    pub discount: Decimal,
+    pub receipt_total: Option<Decimal>,
+    pub unassigned_policy: UnassignedPolicy,
+    pub treated: Vec<Uuid>,
+    pub treat_mode: TreatMode,
+    pub caps: HashMap<Uuid, Decimal>,
+    pub bill_cap: Option<Decimal>,
+    pub sponsor: Option<Uuid>,
+    pub settle_status: bool,
+    #[serde(default)]
+    pub finalized: Option<String>,
+}
+
+/// The fields of a participant that changed. Only the changed fields are
+/// sent, so concurrent edits to different fields don't overwrite each other.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParticipantPatch {
+    pub id: Uuid,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payer: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settled: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<Decimal>,
+}
+
+impl ParticipantPatch {
+    pub fn between(old: &BillParticipant, new: &BillParticipant) -> Option<Self> {
+        let patch = ParticipantPatch {
+            id: new.id,
+            name: (old.name != new.name).then(|| new.name.clone()),
+            payer: (old.payer != new.payer).then_some(new.payer),
+            settled: (old.settled != new.settled).then_some(new.settled),
+            weight: (old.weight != new.weight).then_some(new.weight),
+        };
+        (patch != ParticipantPatch { id: new.id, ..Default::default() }).then_some(patch)
+    }
+
+    pub fn apply_to(&self, participant: &mut BillParticipant) {
+        if let Some(name) = &self.name {
+            participant.name = name.clone();
+        }
+        if let Some(payer) = self.payer {
+            participant.payer = payer;
+        }
+        if let Some(settled) = self.settled {
+            participant.settled = settled;
+        }
+        if let Some(weight) = self.weight {
+            participant.weight = weight;
+        }
+    }
+}
+
+/// The fields of a line item that changed.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemPatch {
+    pub id: Uuid,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quantity: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assignment: Option<Assignment>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub participants: Option<Vec<Uuid>>,
+}
+
+impl ItemPatch {
+    pub fn between(old: &BillItem, new: &BillItem) -> Option<Self> {
+        let patch = ItemPatch {
+            id: new.id,
+            name: (old.name != new.name).then(|| new.name.clone()),
+            price: (old.price != new.price).then_some(new.price),
+            quantity: (old.quantity != new.quantity).then_some(new.quantity),
+            category: (old.category != new.category).then(|| new.category.clone()),
+            assignment: (old.assignment != new.assignment).then(|| new.assignment.clone()),
+            participants: (old.participants != new.participants).then(|| new.participants.clone()),
+        };
+        (patch != ItemPatch { id: new.id, ..Default::default() }).then_some(patch)
+    }
+
+    pub fn apply_to(&self, item: &mut BillItem) {
+        if let Some(name) = &self.name {
+            item.name = name.clone();
+        }
+        if let Some(price) = self.price {
+            item.price = price;
+        }
+        if let Some(quantity) = self.quantity {
+            item.quantity = quantity;
+        }
+        if let Some(category) = &self.category {
+            item.category = category.clone();
+        }
+        if let Some(assignment) = &self.assignment {
+            item.assignment = assignment.clone();
+        }
+        if let Some(participants) = &self.participants {
+            item.participants = participants.clone();
+        }
+    }
+}
+
+/// Tells a field sent as `null` apart from one that wasn't sent.
+fn present<'de, D: Deserializer<'de>, T: Deserialize<'de>>(deserializer: D) -> Result<Option<T>, D::Error> {
+    T::deserialize(deserializer).map(Some)
+}
+
+/// The settings that changed. Ops stored before settings were patched carry
+/// every field, which reads as a patch setting all of them.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SettingsPatch {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_tax: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tip: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discount: Option<Decimal>,
+    #[serde(default, deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub receipt_total: Option<Option<Decimal>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unassigned_policy: Option<UnassignedPolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub treated: Option<Vec<Uuid>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub treat_mode: Option<TreatMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub caps: Option<HashMap<Uuid, Decimal>>,
+    #[serde(default, deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub bill_cap: Option<Option<Decimal>>,
+    #[serde(default, deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub sponsor: Option<Option<Uuid>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settle_status: Option<bool>,
+    #[serde(default, deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub finalized: Option<Option<String>>,
+}
+
+impl SettingsPatch {
+    pub fn between(old: &BillSettings, new: &BillSettings) -> Option<Self> {
+        let patch = SettingsPatch {
+            event_name: (old.event_name != new.event_name).then(|| new.event_name.clone()),
+            total_tax: (old.total_tax != new.total_tax).then_some(new.total_tax),
+            tip: (old.tip != new.tip).then_some(new.tip),
+            discount: (old.discount != new.discount).then_some(new.discount),
+            receipt_total: (old.receipt_total != new.receipt_total).then_some(new.receipt_total),
+            unassigned_policy: (old.unassigned_policy != new.unassigned_policy).then_some(new.unassigned_policy),
+            treated: (old.treated != new.treated).then(|| new.treated.clone()),
+            treat_mode: (old.treat_mode != new.treat_mode).then_some(new.treat_mode),
+            caps: (old.caps != new.caps).then(|| new.caps.clone()),
+            bill_cap: (old.bill_cap != new.bill_cap).then_some(new.bill_cap),
+            sponsor: (old.sponsor != new.sponsor).then_some(new.sponsor),
+            settle_status: (old.settle_status != new.settle_status).then_some(new.settle_status),
+            finalized: (old.finalized != new.finalized).then(|| new.finalized.clone()),
+        };
+        (patch != SettingsPatch::default()).then_some(patch)
+    }
+
+    pub fn apply_to(&self, settings: &mut BillSettings) {
+        if let Some(event_name) = &self.event_name {
+            settings.event_name = event_name.clone();
+        }
+        if let Some(total_tax) = self.total_tax {
+            settings.total_tax = total_tax;
+        }
+        if let Some(tip) = self.tip {
+            settings.tip = tip;
+        }
+        if let Some(discount) = self.discount {
+            settings.discount = discount;
+        }
+        if let Some(receipt_total) = self.receipt_total {
+            settings.receipt_total = receipt_total;
+        }
+        if let Some(unassigned_policy) = self.unassigned_policy {
+            settings.unassigned_policy = unassigned_policy;
+        }
+        if let Some(treated) = &self.treated {
+            settings.treated = treated.clone();
+        }
+        if let Some(treat_mode) = self.treat_mode {
+            settings.treat_mode = treat_mode;
+        }
+        if let Some(caps) = &self.caps {
+            settings.caps = caps.clone();
+        }
+        if let Some(bill_cap) = self.bill_cap {
+            settings.bill_cap = bill_cap;
+        }
+        if let Some(sponsor) = self.sponsor {
+            settings.sponsor = sponsor;
+        }
+        if let Some(settle_status) = self.settle_status {
+            settings.settle_status = settle_status;
+        }
+        if let Some(finalized) = &self.finalized {
+            settings.finalized = finalized.clone();
+        }
+    }
+}
+
+/// Every setting, e.g. to load a whole bill.
+impl From<BillSettings> for SettingsPatch {
+    fn from(settings: BillSettings) -> Self {
+        SettingsPatch {
+            event_name: Some(settings.event_name),
+            total_tax: Some(settings.total_tax),
+            tip: Some(settings.tip),
+            discount: Some(settings.discount),
+            receipt_total: Some(settings.receipt_total),
+            unassigned_policy: Some(settings.unassigned_policy),
+            treated: Some(settings.treated),
+            treat_mode: Some(settings.treat_mode),
+            caps: Some(settings.caps),
+            bill_cap: Some(settings.bill_cap),
+            sponsor: Some(settings.sponsor),
+            settle_status: Some(settings.settle_status),
+            finalized: Some(settings.finalized),
+        }
+    }
+}
+
+/// A single change to a bill, as sent between everyone editing it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BillOp {
+    AddParticipant(BillParticipant),
+    UpdateParticipant(ParticipantPatch),
+    RemoveParticipant(Uuid),
+    AddItem(BillItem),
+    UpdateItem(ItemPatch),
+    RemoveItem(Uuid),
+    UpdateSettings(SettingsPatch),
+}
+
+impl BillOp {
+    /// Whether this op unlocks the bill, the only op a finalized bill takes.
+    pub fn unlocks(&self) -> bool {
+        matches!(self, BillOp::UpdateSettings(patch) if patch.finalized == Some(None))
+    }
+}
+
+impl Bill {
+    pub fn settings(&self) -> BillSettings {
+        BillSettings {
+            event_name: self.event_name.clone(),
+            total_tax: self.total_tax,
+            tip: self.tip,
+            discount: self.discount,
+            receipt_total: self.receipt_total,
+            unassigned_policy: self.unassigned_policy,
+            treated: self.treated.clone(),
+            treat_mode: self.treat_mode,
+            caps: self.caps.clone(),
+            bill_cap: self.bill_cap,
+            sponsor: self.sponsor,
+            settle_status: self.settle_status,
+            finalized: self.finalized.clone(),
+        }
+    }
+
+    /// Applies `op`, unless the bill is finalized and `op` doesn't unlock it.
+    pub fn apply(&mut self, op: &BillOp) {
+        if self.finalized.is_some() && !op.unlocks() {
+            return;
+        }
+        match op {
+            BillOp::AddParticipant(bp) => {
+                if !self.participants.iter().any(|p| p.id == bp.id) {
+                    self.participants.push(bp.clone());
+                }
+            }
+            BillOp::UpdateParticipant(patch) => {
+                if let Some(p) = self.participants.iter_mut().find(|p| p.id == patch.id) {
+                    patch.apply_to(p);
+                }
+            }
+            BillOp::RemoveParticipant(id) => {
+                self.participants.retain(|p| p.id != *id);
+                for item in self.items.iter_mut() {
+                    item.participants.retain(|p| p != id);
+                    if let Assignment::EveryoneExcept(excluded) = &mut item.assignment {
+                        excluded.retain(|p| p != id);
+                    }
+                }
+                self.treated.retain(|p| p != id);
+                self.caps.remove(id);
+                if self.sponsor == Some(*id) {
+                    self.sponsor = None;
+                }
+            }
+            BillOp::AddItem(bi) => {
+                if !self.items.iter().any(|i| i.id == bi.id) {
+                    self.items.push(bi.clone());
+                }
+            }
+            BillOp::UpdateItem(patch) => {
+                if let Some(i) = self.items.iter_mut().find(|i| i.id == patch.id) {
+                    patch.apply_to(i);
+                }
+            }
+            BillOp::RemoveItem(id) => self.items.retain(|i| i.id != *id),
+            BillOp::UpdateSettings(patch) => {
+                let mut settings = self.settings();
+                patch.apply_to(&mut settings);
+                self.set_settings(&settings);
+            }
+        }
+    }
+
+    pub fn set_settings(&mut self, settings: &BillSettings) {
+        self.event_name = settings.event_name.clone();
+        self.total_tax = settings.total_tax;
+        self.tip = settings.tip;
+        self.discount = settings.discount;
+        self.receipt_total = settings.receipt_total;
+        self.unassigned_policy = settings.unassigned_policy;
+        self.treated = settings.treated.clone();
+        self.treat_mode = settings.treat_mode;
+        self.caps = settings.caps.clone();
+        self.bill_cap = settings.bill_cap;
+        self.sponsor = settings.sponsor;
+        self.settle_status = settings.settle_status;
+        self.finalized = settings.finalized.clone();
+    }
+
+    /// Whether a finalized bill can become `new`: only by staying the same or
+    /// by being unlocked, with nothing else changed.
+    pub fn allows_change_to(&self, new: &Bill) -> bool {
+        self.finalized.is_none() || new == self || *new == Bill { finalized: None, ..self.clone() }
+    }
+
+    /// The ops that turn `self` into `new`. Order of participants and items
+    /// is ignored. Unlocking comes first and locking last, so the ops apply
+    /// to a finalized bill.
+    pub fn diff(&self, new: &Bill) -> Vec<BillOp> {
+        let mut ops = Vec::new();
+        let settings = SettingsPatch::between(&self.settings(), &new.settings()).map(BillOp::UpdateSettings);
+        if let Some(unlock) = settings.as_ref().filter(|op| op.unlocks()) {
+            ops.push(unlock.clone());
+        }
+        for old in self.participants.iter() {
+            if !new.participants.iter().any(|p| p.id == old.id) {
+                ops.push(BillOp::RemoveParticipant(old.id));
+            }
+        }
+        for bp in new.participants.iter() {
+            match self.participants.iter().find(|p| p.id == bp.id) {
+                None => ops.push(BillOp::AddParticipant(bp.clone())),
+                Some(old) => ops.extend(ParticipantPatch::between(old, bp).map(BillOp::UpdateParticipant)),
+            }
+        }
+        for old in self.items.iter() {
+            if !new.items.iter().any(|i| i.id == old.id) {
+                ops.push(BillOp::RemoveItem(old.id));
+            }
+        }
+        for bi in new.items.iter() {
+            match self.items.iter().find(|i| i.id == bi.id) {
+                None => ops.push(BillOp::AddItem(bi.clone())),
+                Some(old) => ops.extend(ItemPatch::between(old, bi).map(BillOp::UpdateItem)),
+            }
+        }
+        ops.extend(settings.filter(|op| !op.unlocks()));
+        ops
+    }
+}
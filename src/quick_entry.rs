@@ -0,0 +1,242 @@
+//! One-line entry for items and tax, e.g. `Pizza 450 @alice @bob`,
+//! `2x Beer 300 @all -@carol` or `Tax 18%`.
+
+use rust_decimal::Decimal;
+use uuid::Uuid;
+use crate::app::Assignment;
+use crate::bill::{Bill, BillItem, BillParticipant};
+use crate::csv_file::{find_participant, parse_amount};
+use crate::ops::{BillOp, SettingsPatch};
+
+/// Tax as written: a share of the items' total or an amount.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuickTax {
+    Percent(Decimal),
+    Amount(Decimal),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum QuickEntry {
+    Item(BillItem),
+    Tax(QuickTax),
+}
+
+/// A line of quick entry and what it was read as.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QuickLine {
+    pub text: String,
+    pub entry: Result<QuickEntry, String>,
+}
+
+const TAX_WORDS: &[&str] = &["tax", "taxes", "gst", "cgst", "sgst", "igst", "vat", "cess"];
+const EVERYONE: &[&str] = &["all", "everyone"];
+
+/// Lower case letters and digits only, so `@mary_jane` finds "Mary Jane".
+fn squash(name: &str) -> String {
+    name.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The participant `@name` means: an exact name, then the only name it's the
+/// start of, then the only name within a typo or two of it.
+pub fn match_participant<'a>(bill: &'a Bill, name: &str) -> Result<&'a BillParticipant, String> {
+    if let Some(found) = find_participant(bill, name) {
+        return Ok(found);
+    }
+    let wanted = squash(name);
+    if wanted.is_empty() {
+        return Err(String::from("@ needs a name after it"));
+    }
+    let pick = |matches: Vec<&'a BillParticipant>| -> Option<Result<&'a BillParticipant, String>> {
+        match matches.as_slice() {
+            [] => None,
+            [only] => Some(Ok(*only)),
+            several => {
+                let names: Vec<&str> = several.iter().map(|p| p.name.as_str()).collect();
+                Some(Err(format!("@{} could be {}", name, names.join(" or "))))
+            }
+        }
+    };
+    let exact = bill.participants.iter().filter(|p| squash(&p.name) == wanted).collect();
+    let prefix = bill.participants.iter().filter(|p| squash(&p.name).starts_with(&wanted)).collect();
+    let allowed = if wanted.chars().count() <= 4 { 1 } else { 2 };
+    let close = bill
+        .participants
+        .iter()
+        .filter(|p| edit_distance(&squash(&p.name), &wanted) <= allowed)
+        .collect();
+    pick(exact)
+        .or_else(|| pick(prefix))
+        .or_else(|| pick(close))
+        .unwrap_or_else(|| Err(format!("nobody called '{}' is on the bill", name)))
+}
+
+/// A quantity written as `2x`, `x2` or `2 x`.
+fn quantity(token: &str) -> Option<u32> {
+    let lower = token.to_lowercase();
+    lower
+        .strip_suffix('x')
+        .or_else(|| lower.strip_prefix('x'))
+        .and_then(|n| n.parse::<u32>().ok())
+        .filter(|q| *q > 0)
+}
+
+fn parse_tax(rest: &[&str]) -> Result<QuickTax, String> {
+    let [value] = rest else {
+        return Err(String::from("write tax as 'Tax 18%' or 'Tax 120'"));
+    };
+    let tax = match value.strip_suffix('%') {
+        Some(percent) => QuickTax::Percent(
+            percent.trim().parse::<Decimal>().map_err(|_| format!("'{}' isn't a percentage", value))?,
+        ),
+        None => QuickTax::Amount(parse_amount(value).ok_or_else(|| format!("'{}' isn't an amount", value))?),
+    };
+    match tax {
+        QuickTax::Percent(v) | QuickTax::Amount(v) if v < Decimal::ZERO => Err(String::from("tax can't be negative")),
+        tax => Ok(tax),
+    }
+}
+
+fn parse_item(tokens: &[&str], bill: &Bill) -> Result<BillItem, String> {
+    let mut words = Vec::new();
+    let mut price = None;
+    let mut count = None;
+    let mut everyone = false;
+    let mut included = Vec::new();
+    let mut excluded = Vec::new();
+    let mut tokens = tokens.iter().copied().peekable();
+    while let Some(token) = tokens.next() {
+        if let Some(name) = token.strip_prefix("-@") {
+            excluded.push(match_participant(bill, name)?.id);
+        } else if let Some(name) = token.strip_prefix('@') {
+            if EVERYONE.contains(&name.to_lowercase().as_str()) {
+                everyone = true;
+            } else {
+                included.push(match_participant(bill, name)?.id);
+            }
+        } else if words.is_empty() && count.is_none() && quantity(token).is_some() {
+            count = quantity(token);
+        } else if words.is_empty()
+            && count.is_none()
+            && token.parse::<u32>().is_ok_and(|q| q > 0)
+            && tokens.peek().is_some_and(|next| next.eq_ignore_ascii_case("x"))
+        {
+            count = token.parse().ok();
+            tokens.next();
+        } else if let Some(amount) = parse_amount(token).filter(|_| !words.is_empty()) {
+            // Only the last number is the price; earlier ones are part of the name
+            if let Some(previous) = price.replace(amount) {
+                words.push(previous.to_string());
+            }
+        } else if !words.is_empty() && quantity(token).is_some() {
+            count = quantity(token);
+        } else {
+            if let Some(previous) = price.take() {
+                words.push(previous.to_string());
+            }
+            words.push(token.to_string());
+        }
+    }
+    if words.is_empty() {
+        return Err(String::from("the item needs a name"));
+    }
+    let price = price.ok_or_else(|| String::from("the item needs a price"))?;
+    if price < Decimal::ZERO {
+        return Err(String::from("the price can't be negative"));
+    }
+    let (assignment, participants) = if everyone || !excluded.is_empty() {
+        if !included.is_empty() {
+            return Err(String::from("use either @names or @all with -@names, not both"));
+        }
+        if excluded.is_empty() {
+            (Assignment::Everyone, Vec::new())
+        } else {
+            (Assignment::EveryoneExcept(excluded), Vec::new())
+        }
+    } else {
+        included.sort();
+        included.dedup();
+        (Assignment::Selected, included)
+    };
+    Ok(BillItem {
+        id: Uuid::new_v4(),
+        name: words.join(" "),
+        price,
+        quantity: count.unwrap_or(1),
+        category: String::new(),
+        assignment,
+        participants,
+    })
+}
+
+/// Reads one line. The price is for all of the quantity together, the same
+/// as on the bill.
+pub fn parse_line(line: &str, bill: &Bill) -> Result<QuickEntry, String> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    match tokens.split_first() {
+        None => Err(String::from("the line is empty")),
+        Some((first, rest)) if TAX_WORDS.contains(&first.to_lowercase().trim_end_matches(':')) => {
+            parse_tax(rest).map(QuickEntry::Tax)
+        }
+        Some(_) => parse_item(&tokens, bill).map(QuickEntry::Item),
+    }
+}
+
+/// Reads every non-empty line of what was typed or pasted.
+pub fn parse_quick_entry(text: &str, bill: &Bill) -> Vec<QuickLine> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| QuickLine { text: line.to_string(), entry: parse_line(line, bill) })
+        .collect()
+}
+
+/// The edits for the lines that were read. A percentage tax is of the
+/// items' total including the ones added here. Tax lines, such as CGST and
+/// SGST, add up, and are added to the tax already on the bill rather than
+/// replacing it.
+pub fn quick_entry_ops(bill: &Bill, lines: &[QuickLine]) -> Vec<BillOp> {
+    let entries = lines.iter().filter_map(|line| line.entry.as_ref().ok());
+    let mut ops = Vec::new();
+    let mut taxes = Vec::new();
+    for entry in entries {
+        match entry {
+            QuickEntry::Item(item) => ops.push(BillOp::AddItem(item.clone())),
+            QuickEntry::Tax(tax) => taxes.push(*tax),
+        }
+    }
+    if !taxes.is_empty() {
+        let items_total: Decimal = bill
+            .items
+            .iter()
+            .map(|i| i.price)
+            .chain(ops.iter().filter_map(|op| match op {
+                BillOp::AddItem(item) => Some(item.price),
+                _ => None,
+            }))
+            .sum();
+        let mut settings = bill.settings();
+        settings.total_tax += taxes
+            .iter()
+            .map(|tax| match tax {
+                QuickTax::Percent(percent) => (items_total * percent / Decimal::ONE_HUNDRED).round_dp(2),
+                QuickTax::Amount(amount) => *amount,
+            })
+            .sum::<Decimal>();
+        ops.extend(SettingsPatch::between(&bill.settings(), &settings).map(BillOp::UpdateSettings));
+    }
+    ops
+}
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_ws::{Message, Session};
+use serde::Deserialize;
+use uuid::Uuid;
+use crate::crdt::StampedOp;
+use crate::storage::{db, Access, BillLinks};
+
+/// Someone viewing a bill, and the token they connected with.
+struct Peer {
+    id: Uuid,
+    token: String,
+    session: Session,
+}
+
+/// Everyone currently viewing a bill, by bill id. Each connection gets its
+/// own peer id so it doesn't receive its own ops back.
+#[derive(Default)]
+pub struct BillRooms {
+    rooms: Mutex<HashMap<Uuid, Vec<Peer>>>,
+}
+
+impl BillRooms {
+    fn join(&self, bill_id: Uuid, peer: Peer) {
+        let mut rooms = self.rooms.lock().unwrap();
+        rooms.entry(bill_id).or_default().push(peer);
+    }
+
+    fn leave(&self, bill_id: Uuid, peer: Uuid) {
+        let mut rooms = self.rooms.lock().unwrap();
+        if let Some(room) = rooms.get_mut(&bill_id) {
+            room.retain(|p| p.id != peer);
+            if room.is_empty() {
+                rooms.remove(&bill_id);
+            }
+        }
+    }
+
+    /// Splits everyone but `from` into those whose token still works and
+    /// those whose token was rotated or revoked, who leave the room.
+    fn others(&self, bill_id: Uuid, from: Option<Uuid>, links: Option<&BillLinks>) -> (Vec<Session>, Vec<Session>) {
+        let valid = |token: &str| {
+            links.is_some_and(|links| links.edit_token == token || links.view_token.as_deref() == Some(token))
+        };
+        let mut rooms = self.rooms.lock().unwrap();
+        let Some(room) = rooms.get_mut(&bill_id) else {
+            return (Vec::new(), Vec::new());
+        };
+        let (keep, revoked): (Vec<Peer>, Vec<Peer>) = room.drain(..).partition(|p| valid(&p.token));
+        *room = keep;
+        let others = room.iter().filter(|p| Some(p.id) != from).map(|p| p.session.clone()).collect();
+        if room.is_empty() {
+            rooms.remove(&bill_id);
+        }
+        (others, revoked.into_iter().map(|p| p.session).collect())
+    }
+
+    /// Sends `ops` to everyone on the bill but `from`, and disconnects anyone
+    /// whose link stopped working since they connected.
+    pub async fn broadcast(&self, bill_id: Uuid, from: Option<Uuid>, ops: &[StampedOp]) {
+        if ops.is_empty() {
+            return;
+        }
+        let links = web::block(move || db::find_links(bill_id)).await.map_err(db::server_error).and_then(|r| r);
+        let links = match links {
+            Ok(links) => links,
+            Err(e) => {
+                log::error!("Couldn't check the links of bill {}: {}", bill_id, e);
+                return;
+            }
+        };
+        let Ok(text) = serde_json::to_string(ops) else {
+            return;
+        };
+        let (others, revoked) = self.others(bill_id, from, links.as_ref());
+        for session in revoked {
+            let _ = session.close(None).await;
+        }
+        for mut other in others {
+            let _ = other.text(text.clone()).await;
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SocketQuery {
+    token: String,
+    /// Who to credit in the bill's history.
+    #[serde(default)]
+    author: String,
+}
+
+/// `GET /ws/bill/{id}?token=...&author=...`. Anyone with a valid token
+/// receives the ops for the bill; only editors' ops are stored and passed on.
+/// The token is checked again for every message, so rotating or revoking it
+/// cuts off sockets that are already open.
+pub async fn bill_socket(
+    req: HttpRequest,
+    body: web::Payload,
+    path: web::Path<Uuid>,
+    query: web::Query<SocketQuery>,
+    rooms: web::Data<BillRooms>,
+) -> actix_web::Result<HttpResponse> {
+    let bill_id = path.into_inner();
+    db::require_access(bill_id, &query.token, Access::ReadOnly)
+        .map_err(|e| actix_web::error::ErrorForbidden(e.to_string()))?;
+    let (response, session, mut stream) = actix_ws::handle(&req, body)?;
+    let SocketQuery { token, author } = query.into_inner();
+    let peer = Uuid::new_v4();
+    rooms.join(bill_id, Peer { id: peer, token: token.clone(), session: session.clone() });
+
+    actix_web::rt::spawn(async move {
+        let mut session = session;
+        while let Some(Ok(msg)) = stream.recv().await {
+            match msg {
+                Message::Text(text) => {
+                    let Ok(ops) = serde_json::from_str::<Vec<StampedOp>>(&text) else {
+                        continue;
+                    };
+                    let (token, author) = (token.clone(), author.clone());
+                    let stored = web::block(move || match db::access(bill_id, &token)? {
+                        Some(Access::Editor) => db::apply_ops(bill_id, &ops, &author).map(Some),
+                        Some(Access::ReadOnly) => Ok(Some(Vec::new())),
+                        None => Ok(None),
+                    })
+                    .await
+                    .map_err(db::server_error)
+                    .and_then(|r| r);
+                    match stored {
+                        Ok(Some(accepted)) => rooms.broadcast(bill_id, Some(peer), &accepted).await,
+                        // The token was rotated or revoked
+                        Ok(None) => break,
+                        Err(e) => log::error!("Couldn't store live edits to bill {}: {}", bill_id, e),
+                    }
+                }
+                Message::Ping(bytes) => {
+                    if session.pong(&bytes).await.is_err() {
+                        break;
+                    }
+                }
+                Message::Close(_) => break,
+                _ => {}
+            }
+        }
+        rooms.leave(bill_id, peer);
+        let _ = session.close(None).await;
+    });
+
+    Ok(response)
+}
@@ -0,0 +1,356 @@
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::app::Assignment;
+use crate::bill::{Bill, BillItem};
+use crate::csv_file::parse_amount;
+use crate::ops::{BillOp, SettingsPatch};
+
+/// An item line, e.g. `Butter Naan  4  60.00  240.00`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiptItem {
+    pub name: String,
+    pub quantity: u32,
+    /// For all of them together.
+    pub price: Decimal,
+}
+
+/// A labelled amount that isn't an item, e.g. `CGST @ 2.5%  25.50`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiptCharge {
+    pub label: String,
+    pub amount: Decimal,
+}
+
+/// What could be read off a pasted receipt.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Receipt {
+    pub items: Vec<ReceiptItem>,
+    /// Taxes, service charge, delivery and packaging fees and rounding:
+    /// everything that is shared out like tax.
+    pub charges: Vec<ReceiptCharge>,
+    /// Positive amounts, taken off the bill.
+    pub discounts: Vec<ReceiptCharge>,
+    pub tip: Decimal,
+    pub subtotal: Option<Decimal>,
+    pub grand_total: Option<Decimal>,
+    /// Lines with an amount that couldn't be placed.
+    pub unread: Vec<String>,
+}
+
+impl Receipt {
+    pub fn item_total(&self) -> Decimal {
+        self.items.iter().map(|i| i.price).sum()
+    }
+
+    pub fn tax(&self) -> Decimal {
+        self.charges.iter().map(|c| c.amount).sum()
+    }
+
+    pub fn discount(&self) -> Decimal {
+        self.discounts.iter().map(|d| d.amount).sum()
+    }
+
+    /// The total the lines that were read add up to.
+    pub fn computed_total(&self) -> Decimal {
+        self.item_total() + self.tax() + self.tip - self.discount()
+    }
+
+    /// How far the lines that were read are off the printed grand total.
+    pub fn difference(&self) -> Option<Decimal> {
+        self.grand_total.map(|total| total - self.computed_total())
+    }
+
+    /// The edits that put the receipt on `bill`: every item, unassigned, and
+    /// its grand total as the receipt total. Its charges, tip and discounts
+    /// are added to what the bill already has, like tax lines in quick entry.
+    pub fn to_ops(&self, bill: &Bill) -> Vec<BillOp> {
+        let mut ops: Vec<BillOp> = self
+            .items
+            .iter()
+            .map(|item| {
+                BillOp::AddItem(BillItem {
+                    id: Uuid::new_v4(),
+                    name: item.name.clone(),
+                    price: item.price,
+                    quantity: item.quantity,
+                    category: String::new(),
+                    assignment: Assignment::Selected,
+                    participants: Vec::new(),
+                })
+            })
+            .collect();
+        let mut settings = bill.settings();
+        if !self.charges.is_empty() {
+            settings.total_tax = (settings.total_tax + self.tax()).max(Decimal::ZERO);
+        }
+        if !self.tip.is_zero() {
+            settings.tip += self.tip;
+        }
+        if !self.discounts.is_empty() {
+            settings.discount += self.discount();
+        }
+        if self.grand_total.is_some() {
+            settings.receipt_total = self.grand_total;
+        }
+        ops.extend(SettingsPatch::between(&bill.settings(), &settings).map(BillOp::UpdateSettings));
+        ops
+    }
+}
+
+/// Labels of the amount that was actually charged.
+const GRAND_TOTAL: &[&str] = &[
+    "grand total", "net amount", "net payable", "amount payable", "total payable", "bill total", "bill amount",
+    "order total", "to pay", "total amount", "amount due", "total due", "net total",
+];
+const SUBTOTAL: &[&str] = &[
+    "sub total", "subtotal", "item total", "items total", "food total", "basic amount", "gross amount", "gross total",
+    "taxable amount", "taxable value",
+];
+/// Lines with numbers that aren't amounts, or are about paying the bill.
+/// Checked after the charges, so `Order Packing Charges` is still a charge.
+const NOISE: &[&str] = &[
+    "gstin", "gst no", "gst in", "invoice", "bill no", "order", "table", "date", "time", "phone", "tel", "mobile",
+    "fssai", "cashier", "steward", "waiter", "kot", "token", "hsn", "sac", "pin", "thank", "total qty",
+    "total quantity", "total items", "no of items", "item count", "paid", "upi", "card", "cash", "change", "balance",
+];
+const ROUNDING: &[&str] = &["round off", "rounding", "round"];
+const DISCOUNT: &[&str] = &["discount", "coupon", "offer", "promo", "savings", "saved"];
+const TIP: &[&str] = &["tip", "gratuity"];
+const CHARGE: &[&str] = &[
+    "cgst", "sgst", "igst", "utgst", "gst", "vat", "cess", "tax", "taxes", "service charge", "service chg",
+    "delivery", "packaging", "packing", "platform", "convenience", "handling", "surcharge", "charge", "charges", "fee",
+    "fees",
+];
+
+/// Whether `label` has any of `phrases` as whole words.
+fn mentions(label: &str, phrases: &[&str]) -> bool {
+    phrases.iter().any(|phrase| label.contains(&format!(" {} ", phrase)))
+}
+
+/// The label in lower case with only its words, padded with spaces for
+/// `mentions`.
+fn normalize(label: &str) -> String {
+    let words: Vec<String> = label
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    format!(" {} ", words.join(" "))
+}
+
+fn is_currency(token: &str) -> bool {
+    matches!(token.to_lowercase().as_str(), "₹" | "rs" | "rs." | "inr" | "/-")
+}
+
+/// A number on a receipt, and whether it was written with a decimal point.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Amount {
+    value: Decimal,
+    whole: bool,
+}
+
+fn amount(token: &str) -> Option<Amount> {
+    if token.contains('%') {
+        return None;
+    }
+    let mut text = token.trim_end_matches("/-");
+    let mut negative = false;
+    if let Some(inner) = text.strip_prefix('(').and_then(|t| t.strip_suffix(')')) {
+        text = inner;
+        negative = true;
+    }
+    if let Some(rest) = text.strip_prefix('-') {
+        text = rest;
+        negative = true;
+    }
+    for prefix in ["₹", "Rs.", "rs.", "Rs", "rs", "INR"] {
+        text = text.strip_prefix(prefix).unwrap_or(text);
+    }
+    if text.is_empty() || !text.chars().all(|c| c.is_ascii_digit() || c == '.' || c == ',') {
+        return None;
+    }
+    let value = parse_amount(text)?;
+    // Pin codes and phone numbers
+    if !text.contains('.') && value >= Decimal::from(100_000) {
+        return None;
+    }
+    Some(Amount {
+        value: if negative { -value } else { value },
+        whole: !text.contains('.'),
+    })
+}
+
+/// Splits a line into the words of its label and the amounts at its end.
+/// Currency signs are skipped, and a number written before one belongs to
+/// the label, as in `Chicken 65  ₹250`.
+fn split_line(line: &str) -> (Vec<&str>, Vec<Amount>) {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let mut amounts: Vec<Amount> = Vec::new();
+    let mut end = tokens.len();
+    let mut seen_currency = false;
+    while end > 0 {
+        let token = tokens[end - 1];
+        if is_currency(token) {
+            seen_currency = true;
+        } else if token == "-" || token == "-₹" {
+            if let Some(last) = amounts.last_mut() {
+                last.value = -last.value.abs();
+            }
+            seen_currency |= token == "-₹";
+        } else if seen_currency && !amounts.is_empty() {
+            break;
+        } else if let Some(found) = amount(token) {
+            amounts.push(found);
+            seen_currency = token.contains('₹') || token.to_lowercase().trim_start_matches('-').starts_with("rs");
+        } else {
+            break;
+        }
+        end -= 1;
+    }
+    amounts.reverse();
+    (tokens[..end].to_vec(), amounts)
+}
+
+/// Takes a quantity written into the name, as in `2 x Beer`, `2x Beer`,
+/// `Beer x 2` or `Beer (x2)`, and a serial number such as `1.` off the front.
+fn take_quantity(words: &mut Vec<&str>) -> Option<u32> {
+    let small = |text: &str| text.parse::<u32>().ok().filter(|q| (1..=99).contains(q));
+    if words.first().is_some_and(|w| w.ends_with(['.', ')']) && small(&w[..w.len() - 1]).is_some()) {
+        words.remove(0);
+    }
+    if words.len() > 2 && small(words[0]).is_some() && words[1].eq_ignore_ascii_case("x") {
+        let quantity = small(words[0]);
+        words.drain(..2);
+        return quantity;
+    }
+    if let Some(quantity) = words.first().and_then(|w| w.strip_suffix(['x', 'X'])).and_then(small) {
+        words.remove(0);
+        return Some(quantity);
+    }
+    let n = words.len();
+    if n > 2 && words[n - 2].eq_ignore_ascii_case("x") {
+        if let Some(quantity) = small(words[n - 1]) {
+            words.truncate(n - 2);
+            return Some(quantity);
+        }
+    }
+    if let Some(last) = words.last() {
+        let bare = last.trim_start_matches('(').trim_end_matches(')');
+        if let Some(quantity) = bare.strip_prefix(['x', 'X', '*']).and_then(small) {
+            words.pop();
+            return Some(quantity);
+        }
+    }
+    None
+}
+
+/// Works out quantity and line price from the numbers after an item's
+/// name: `qty rate amount`, `qty amount`, `rate amount` or just `amount`.
+/// Numbers that don't fit go back into the name.
+fn read_item(mut words: Vec<&str>, amounts: &[Amount]) -> Option<ReceiptItem> {
+    let close = |a: Decimal, b: Decimal| (a - b).abs() <= Decimal::new(1, 2);
+    let (quantity, price, used) = match amounts {
+        [.., q, rate, total] if q.whole && !q.value.is_zero() && close(q.value * rate.value, total.value) => {
+            (q.value.to_u32(), total.value, 3)
+        }
+        [.., q, total] if q.whole && q.value >= Decimal::ONE && q.value <= Decimal::from(50) => {
+            (q.value.to_u32(), total.value, 2)
+        }
+        [.., rate, total]
+            if !rate.value.is_zero()
+                && total.value > rate.value
+                && (total.value / rate.value).fract().is_zero() =>
+        {
+            ((total.value / rate.value).to_u32(), total.value, 2)
+        }
+        [.., total] => (None, total.value, 1),
+        [] => return None,
+    };
+    let leftover: Vec<String> = amounts[..amounts.len() - used].iter().map(|a| a.value.to_string()).collect();
+    // A leading number on a line with quantity and rate columns is a serial
+    if used == 3 && words.first().is_some_and(|w| w.parse::<u32>().is_ok()) {
+        words.remove(0);
+    }
+    let named_quantity = take_quantity(&mut words);
+    let mut name = words.join(" ");
+    for number in leftover {
+        name.push(' ');
+        name.push_str(&number);
+    }
+    let name = name.trim().trim_end_matches([':', '-']).trim().to_string();
+    if name.is_empty() || price < Decimal::ZERO {
+        return None;
+    }
+    Some(ReceiptItem {
+        name,
+        quantity: quantity.filter(|q| *q > 1).or(named_quantity).unwrap_or(1),
+        price,
+    })
+}
+
+fn is_separator(line: &str) -> bool {
+    line.chars().all(|c| matches!(c, '-' | '=' | '*' | '_' | '~' | '.' | ' ' | '|'))
+}
+
+/// Reads a receipt pasted as text, from a restaurant's printed bill or a
+/// delivery app's order summary. Anything that isn't recognised is left out
+/// rather than guessed at, so check `unread` and `difference` before
+/// trusting it.
+pub fn parse_receipt(text: &str) -> Receipt {
+    let mut receipt = Receipt::default();
+    let mut explicit_total = false;
+    // A name on a line of its own, waiting for its price on the next one
+    let mut pending: Option<String> = None;
+
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || is_separator(line) {
+            pending = None;
+            continue;
+        }
+        let (words, amounts) = split_line(line);
+        let label = words.join(" ");
+        let normalized = normalize(&label);
+        let Some(last) = amounts.last().map(|a| a.value) else {
+            pending = (!mentions(&normalized, NOISE)).then(|| label.clone());
+            continue;
+        };
+        let charge = || ReceiptCharge { label: label.trim_end_matches(':').trim().to_string(), amount: last };
+
+        if mentions(&normalized, GRAND_TOTAL) {
+            receipt.grand_total = Some(last);
+            explicit_total = true;
+        } else if mentions(&normalized, SUBTOTAL) {
+            receipt.subtotal = Some(last);
+        } else if mentions(&normalized, ROUNDING) {
+            receipt.charges.push(charge());
+        } else if mentions(&normalized, DISCOUNT) {
+            receipt.discounts.push(ReceiptCharge { amount: last.abs(), ..charge() });
+        } else if mentions(&normalized, TIP) {
+            receipt.tip += last;
+        } else if mentions(&normalized, CHARGE) {
+            receipt.charges.push(charge());
+        } else if mentions(&normalized, NOISE) {
+            // Most likely not part of what was charged, as in `Table: 7` or
+            // `Paid by UPI 1122.00`, but `Table Water 40` is, so it's left
+            // for the user to check
+            receipt.unread.push(line.to_string());
+        } else if normalized.starts_with(" total ") || normalized == " amount " {
+            if !explicit_total {
+                receipt.grand_total = Some(last);
+            }
+        } else if words.is_empty() {
+            match pending.take().and_then(|name| read_item(name.split_whitespace().collect(), &amounts)) {
+                Some(item) => receipt.items.push(item),
+                None => receipt.unread.push(line.to_string()),
+            }
+        } else {
+            match read_item(words.clone(), &amounts) {
+                Some(item) => receipt.items.push(item),
+                None => receipt.unread.push(line.to_string()),
+            }
+        }
+        pending = None;
+    }
+    receipt
+}
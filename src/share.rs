@@ -0,0 +1,39 @@
+use std::io::{Read, Write};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use crate::bill::Bill;
+
+/// Prefix of the URL fragment carrying a whole bill, e.g. `#bill=...`.
+pub const FRAGMENT_PREFIX: &str = "bill=";
+
+/// Serialises, compresses and base64url-encodes a bill so it fits in a URL
+/// fragment. Fragments are never sent to the server.
+pub fn encode_bill(bill: &Bill) -> Result<String, String> {
+    let json = serde_json::to_vec(bill).map_err(|e| e.to_string())?;
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(&json).map_err(|e| e.to_string())?;
+    let compressed = encoder.finish().map_err(|e| e.to_string())?;
+    Ok(URL_SAFE_NO_PAD.encode(compressed))
+}
+
+pub fn decode_bill(encoded: &str) -> Result<Bill, String> {
+    let compressed = URL_SAFE_NO_PAD
+        .decode(encoded.trim())
+        .map_err(|e| format!("the link is damaged: {}", e))?;
+    let mut json = Vec::new();
+    DeflateDecoder::new(compressed.as_slice())
+        .read_to_end(&mut json)
+        .map_err(|e| format!("the link is damaged: {}", e))?;
+    serde_json::from_slice(&json).map_err(|e| format!("the link doesn't contain a bill: {}", e))
+}
+
+pub fn bill_fragment(bill: &Bill) -> Result<String, String> {
+    encode_bill(bill).map(|encoded| format!("#{}{}", FRAGMENT_PREFIX, encoded))
+}
+
+/// The bill in a `location.hash` value, if it carries one.
+pub fn bill_from_fragment(hash: &str) -> Option<Result<Bill, String>> {
+    hash.trim_start_matches('#')
+        .strip_prefix(FRAGMENT_PREFIX)
+        .map(decode_bill)
+}
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use leptos::create_runtime;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::app::{ShareBreakdown, SplitItem};
+use crate::bill::Bill;
+
+/// One payment in the settlement plan.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transfer {
+    pub from: Uuid,
+    pub to: Uuid,
+    pub amount: Decimal,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParticipantShare {
+    pub id: Uuid,
+    pub name: String,
+    pub share: Decimal,
+    pub breakdown: ShareBreakdown,
+}
+
+/// Everything the split engine works out for a bill.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SplitResult {
+    pub total: Decimal,
+    /// Set when unassigned items stop the split, see `UnassignedPolicy::Block`.
+    pub blocked: bool,
+    pub shares: Vec<ParticipantShare>,
+    pub settlement: Vec<Transfer>,
+    pub summary: String,
+}
+
+/// Splits a bill outside the page, e.g. for the API, the JavaScript library
+/// or the CLI. The engine is built on signals, so every split gets a
+/// reactive runtime of its own.
+pub fn compute(bill: &Bill) -> SplitResult {
+    let runtime = create_runtime();
+    let result = split_bill(bill);
+    runtime.dispose();
+    result
+}
+
+/// Runs the same split as the page. Has to be called inside a reactive
+/// runtime, see `compute`.
+pub fn split_bill(bill: &Bill) -> SplitResult {
+    let split = SplitItem::from_bill(bill);
+    let final_shares = split.final_shares();
+    let breakdowns = split.breakdowns();
+    let shares: Vec<ParticipantShare> = bill
+        .participants
+        .iter()
+        .map(|p| ParticipantShare {
+            id: p.id,
+            name: p.name.clone(),
+            share: final_shares.get(&p.id).copied().unwrap_or_default(),
+            breakdown: breakdowns.get(&p.id).cloned().unwrap_or_default(),
+        })
+        .collect();
+    SplitResult {
+        total: shares.iter().map(|s| s.share).sum(),
+        blocked: split.blocked(),
+        settlement: settlement_plan(bill, &final_shares),
+        shares,
+        summary: split.summary(),
+    }
+}
+
+/// What each participant put down at the till. Payers are taken to have
+/// split the bill evenly between them.
+pub fn paid_amounts(bill: &Bill, shares: &HashMap<Uuid, Decimal>) -> HashMap<Uuid, Decimal> {
+    let payers = bill.participants.iter().filter(|p| p.payer).count();
+    let total: Decimal = shares.values().sum();
+    bill.participants
+        .iter()
+        .map(|p| {
+            let paid = if p.payer { total / Decimal::from(payers) } else { Decimal::ZERO };
+            (p.id, paid)
+        })
+        .collect()
+}
+
+/// The fewest payments that pay back whoever covered the bill, see
+/// `paid_amounts`. Participants who already settled up are left out.
+pub fn settlement_plan(bill: &Bill, shares: &HashMap<Uuid, Decimal>) -> Vec<Transfer> {
+    if !bill.participants.iter().any(|p| p.payer) {
+        return Vec::new();
+    }
+    let paid = paid_amounts(bill, shares);
+
+    let mut balances: Vec<(Uuid, Decimal)> = bill
+        .participants
+        .iter()
+        .filter(|p| p.payer || !p.settled)
+        .map(|p| {
+            let paid = paid.get(&p.id).copied().unwrap_or_default();
+            (p.id, (paid - shares.get(&p.id).copied().unwrap_or_default()).round_dp(2))
+        })
+        .collect();
+    // Biggest amounts first, ties by id so the plan is the same every time
+    balances.sort_by(|a, b| b.1.abs().cmp(&a.1.abs()).then(a.0.cmp(&b.0)));
+    let mut owed: Vec<(Uuid, Decimal)> = balances.iter().filter(|(_, b)| *b > Decimal::ZERO).copied().collect();
+    let mut owing: Vec<(Uuid, Decimal)> = balances.iter().filter(|(_, b)| *b < Decimal::ZERO).map(|(id, b)| (*id, -*b)).collect();
+
+    let mut transfers = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < owing.len() && j < owed.len() {
+        let amount = owing[i].1.min(owed[j].1);
+        if amount >= Decimal::new(1, 2) {
+            transfers.push(Transfer { from: owing[i].0, to: owed[j].0, amount });
+        }
+        owing[i].1 -= amount;
+        owed[j].1 -= amount;
+        if owing[i].1.is_zero() {
+            i += 1;
+        }
+        if owed[j].1.is_zero() {
+            j += 1;
+        }
+    }
+    transfers
+}
@@ -0,0 +1,730 @@
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::bill::Bill;
+use crate::crdt::BillDoc;
+use crate::history::BillEvent;
+
+/// One row of the saved bills list.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BillSummary {
+    pub id: Uuid,
+    pub event_name: String,
+    pub updated_at: i64,
+}
+
+/// What a share token lets its holder do with a bill.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Access {
+    ReadOnly,
+    Editor,
+}
+
+impl Access {
+    pub fn allows(&self, needed: Access) -> bool {
+        *self == Access::Editor || needed == Access::ReadOnly
+    }
+}
+
+/// The two share tokens of a bill. Only editors ever get to see these.
+#[cfg_attr(feature = "ssr", derive(utoipa::ToSchema))]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BillLinks {
+    pub id: Uuid,
+    /// `None` once the read-only link has been revoked.
+    pub view_token: Option<String>,
+    pub edit_token: String,
+}
+
+impl BillLinks {
+    pub fn view_path(&self) -> Option<String> {
+        self.view_token.as_ref().map(|token| bill_path(self.id, token))
+    }
+
+    pub fn edit_path(&self) -> String {
+        bill_path(self.id, &self.edit_token)
+    }
+}
+
+pub fn bill_path(id: Uuid, token: &str) -> String {
+    format!("/bill/{}?token={}", id, token)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SharedBill {
+    pub bill: Bill,
+    /// What the server knows about the bill's edits, so this device's edits
+    /// are stamped after them.
+    pub doc: BillDoc,
+    pub access: Access,
+}
+
+#[server(CreateBill, "/api", "Cbor")]
+pub async fn create_bill(bill: Bill, author: String) -> Result<BillLinks, ServerFnError> {
+    let mut bill = bill;
+    bill.id = Uuid::new_v4();
+    bill.validate().map_err(|e| ServerFnError::ServerError(e.to_string()))?;
+    let links = BillLinks {
+        id: bill.id,
+        view_token: Some(db::new_token()),
+        edit_token: db::new_token(),
+    };
+    db::insert_bill(&bill, &links, &author)?;
+    Ok(links)
+}
+
+#[server(LoadBill, "/api")]
+pub async fn load_bill(id: Uuid, token: String) -> Result<SharedBill, ServerFnError> {
+    let access = db::require_access(id, &token, Access::ReadOnly)?;
+    let doc = db::get_doc(id)?.ok_or_else(|| ServerFnError::ServerError(format!("bill {} not found", id)))?;
+    Ok(SharedBill { bill: doc.to_bill(), doc, access })
+}
+
+#[server(SaveBill, "/api", "Cbor")]
+pub async fn save_bill(bill: Bill, token: String, author: String) -> Result<(), ServerFnError> {
+    db::require_access(bill.id, &token, Access::Editor)?;
+    bill.validate().map_err(|e| ServerFnError::ServerError(e.to_string()))?;
+    db::save_bill(&bill, &author)?;
+    Ok(())
+}
+
+/// Merges the edits a device made, possibly while offline, into the stored
+/// bill and returns everything the server knows, so the device can catch up.
+#[server(SyncBill, "/api", "Cbor")]
+pub async fn sync_bill(id: Uuid, token: String, doc: BillDoc, author: String) -> Result<BillDoc, ServerFnError> {
+    db::require_access(id, &token, Access::Editor)?;
+    db::merge_doc(id, &doc, &author)
+}
+
+/// Every stored change to a bill, oldest first.
+#[server(BillHistory, "/api", "Cbor")]
+pub async fn bill_history(id: Uuid, token: String) -> Result<Vec<BillEvent>, ServerFnError> {
+    db::require_access(id, &token, Access::Editor)?;
+    db::list_events(id)
+}
+
+/// Puts the bill back the way it was at `revision`. This is recorded as a new
+/// revision, so the history itself is never rewritten.
+#[server(RestoreRevision, "/api")]
+pub async fn restore_revision(id: Uuid, token: String, revision: i64, author: String) -> Result<(), ServerFnError> {
+    db::require_access(id, &token, Access::Editor)?;
+    db::restore_revision(id, revision, &author)
+}
+
+/// Lists the bills the caller holds an editor token for.
+#[server(ListBills, "/api", "Cbor")]
+pub async fn list_bills(edit_tokens: Vec<String>) -> Result<Vec<BillSummary>, ServerFnError> {
+    db::list_bills(&edit_tokens)
+}
+
+#[server(DeleteBill, "/api")]
+pub async fn delete_bill(id: Uuid, token: String) -> Result<(), ServerFnError> {
+    db::require_access(id, &token, Access::Editor)?;
+    db::delete_bill(id)
+}
+
+#[server(GetBillLinks, "/api")]
+pub async fn bill_links(id: Uuid, token: String) -> Result<BillLinks, ServerFnError> {
+    db::require_access(id, &token, Access::Editor)?;
+    db::get_links(id)
+}
+
+/// Replaces one of the tokens, so the old link stops working.
+#[server(RotateToken, "/api")]
+pub async fn rotate_token(id: Uuid, token: String, kind: Access) -> Result<BillLinks, ServerFnError> {
+    db::require_access(id, &token, Access::Editor)?;
+    db::set_token(id, kind, Some(db::new_token()))?;
+    db::get_links(id)
+}
+
+/// Disables the read-only link until it is rotated again.
+#[server(RevokeViewToken, "/api")]
+pub async fn revoke_view_token(id: Uuid, token: String) -> Result<BillLinks, ServerFnError> {
+    db::require_access(id, &token, Access::Editor)?;
+    db::set_token(id, Access::ReadOnly, None)?;
+    db::get_links(id)
+}
+
+/// An end-to-end encrypted bill. The server never sees the key, only the
+/// ciphertext and how many times it has been saved.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptedBill {
+    pub ciphertext: String,
+    pub version: i64,
+}
+
+/// Stores a new encrypted bill under the id the browser picked, since its
+/// `write_token` is derived from the id. See `crypto::write_token`.
+#[server(CreateEncryptedBill, "/api")]
+pub async fn create_encrypted_bill(id: Uuid, ciphertext: String, write_token: String) -> Result<(), ServerFnError> {
+    db::insert_encrypted_bill(id, &ciphertext, &write_token)
+}
+
+#[server(LoadEncryptedBill, "/api")]
+pub async fn load_encrypted_bill(id: Uuid) -> Result<EncryptedBill, ServerFnError> {
+    db::get_encrypted_bill(id)?.ok_or_else(|| ServerFnError::ServerError(format!("bill {} not found", id)))
+}
+
+/// Stores a new ciphertext if nobody saved since `version`, and returns the
+/// new version. Only holders of the key can produce `write_token`, so
+/// knowing the bill's id isn't enough to overwrite it.
+#[server(SaveEncryptedBill, "/api")]
+pub async fn save_encrypted_bill(
+    id: Uuid,
+    ciphertext: String,
+    version: i64,
+    write_token: String,
+) -> Result<i64, ServerFnError> {
+    if db::update_encrypted_bill(id, &ciphertext, version, &write_token)? == 0 {
+        return Err(ServerFnError::ServerError(String::from(
+            "someone else saved this bill in the meantime, reload to see their changes",
+        )));
+    }
+    Ok(version + 1)
+}
+
+#[cfg(feature = "ssr")]
+pub mod db {
+    use std::sync::{Mutex, OnceLock};
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use leptos::ServerFnError;
+    use rusqlite::{params, Connection, OptionalExtension};
+    use sha2::{Digest, Sha256};
+    use uuid::Uuid;
+    use crate::bill::Bill;
+    use crate::crdt::{BillDoc, StampedOp};
+    use crate::history::BillEvent;
+    use crate::ops::BillOp;
+    use super::{Access, BillLinks, BillSummary, EncryptedBill};
+
+    /// The manifest the server was built from, for `db-file` in
+    /// `[package.metadata.leptos]` next to the rest of its settings.
+    const MANIFEST: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"));
+
+    /// Where bills are stored when the Leptos settings don't say.
+    const DEFAULT_DB_FILE: &str = "whati8.db";
+
+    const SCHEMA: &str = "
+        CREATE TABLE IF NOT EXISTS bills (
+            id TEXT PRIMARY KEY NOT NULL,
+            event_name TEXT NOT NULL,
+            data TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL,
+            view_token TEXT,
+            edit_token TEXT NOT NULL DEFAULT '',
+            doc TEXT
+        );
+        CREATE TABLE IF NOT EXISTS bill_events (
+            bill_id TEXT NOT NULL,
+            revision INTEGER NOT NULL,
+            author TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            restored_from INTEGER,
+            ops TEXT NOT NULL,
+            PRIMARY KEY (bill_id, revision)
+        );
+        CREATE TABLE IF NOT EXISTS encrypted_bills (
+            id TEXT PRIMARY KEY NOT NULL,
+            ciphertext TEXT NOT NULL,
+            version INTEGER NOT NULL,
+            write_hash TEXT
+        );
+    ";
+
+    /// Columns added after the first release, for databases created before them.
+    const MIGRATIONS: &[(&str, &str, &str)] = &[
+        ("bills", "view_token", "ALTER TABLE bills ADD COLUMN view_token TEXT"),
+        ("bills", "edit_token", "ALTER TABLE bills ADD COLUMN edit_token TEXT NOT NULL DEFAULT ''"),
+        ("bills", "doc", "ALTER TABLE bills ADD COLUMN doc TEXT"),
+        // Encrypted bills stored before write tokens have none and can't be saved over
+        ("encrypted_bills", "write_hash", "ALTER TABLE encrypted_bills ADD COLUMN write_hash TEXT"),
+    ];
+
+    /// Bills saved before share tokens existed get a fresh pair.
+    const BACKFILL_TOKENS: &str = "UPDATE bills SET view_token = lower(hex(randomblob(16))), edit_token = lower(hex(randomblob(16))) WHERE edit_token = ''";
+
+    /// Edits made through `save_bill` are stamped by the server itself.
+    const SERVER_ACTOR: Uuid = Uuid::nil();
+
+    /// Opened once, on first use. A database that fails to open stays failed,
+    /// which `init` reports at startup.
+    static CONNECTION: OnceLock<Result<Mutex<Connection>, String>> = OnceLock::new();
+
+    /// `WHATI8_DB_FILE` if it's set, like the `LEPTOS_*` variables override
+    /// the rest of the Leptos settings, or else `db-file` from them.
+    pub fn db_file() -> String {
+        std::env::var("WHATI8_DB_FILE")
+            .ok()
+            .or_else(configured_db_file)
+            .unwrap_or_else(|| String::from(DEFAULT_DB_FILE))
+    }
+
+    fn configured_db_file() -> Option<String> {
+        let manifest: toml::Table = toml::from_str(MANIFEST).ok()?;
+        let leptos = manifest.get("package")?.get("metadata")?.get("leptos")?;
+        leptos.get("db-file")?.as_str().map(String::from)
+    }
+
+    /// Opens the database and creates the tables, so a bad path fails at startup.
+    pub fn init() -> Result<(), ServerFnError> {
+        with_connection(|_| Ok(()))
+    }
+
+    pub(crate) fn with_connection<T>(
+        f: impl FnOnce(&Connection) -> rusqlite::Result<T>,
+    ) -> Result<T, ServerFnError> {
+        let conn = CONNECTION
+            .get_or_init(|| open().map(Mutex::new).map_err(|e| e.to_string()))
+            .as_ref()
+            .map_err(server_error)?;
+        let conn = conn.lock().map_err(server_error)?;
+        f(&conn).map_err(server_error)
+    }
+
+    fn open() -> rusqlite::Result<Connection> {
+        let conn = Connection::open(db_file())?;
+        conn.execute_batch(SCHEMA)?;
+        migrate(&conn)?;
+        Ok(conn)
+    }
+
+    fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+        for (table, column, migration) in MIGRATIONS {
+            let exists = conn
+                .prepare("SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2")?
+                .exists(params![table, column])?;
+            if !exists {
+                conn.execute(migration, [])?;
+            }
+        }
+        conn.execute(BACKFILL_TOKENS, [])?;
+        Ok(())
+    }
+
+    fn to_sql_error(e: serde_json::Error) -> rusqlite::Error {
+        rusqlite::Error::ToSqlConversionFailure(Box::new(e))
+    }
+
+    pub(crate) fn server_error(e: impl std::fmt::Display) -> ServerFnError {
+        ServerFnError::ServerError(e.to_string())
+    }
+
+    pub(crate) fn now() -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default()
+    }
+
+    pub fn new_token() -> String {
+        Uuid::new_v4().simple().to_string()
+    }
+
+    /// What `token` allows on the bill, `None` if the bill doesn't exist or
+    /// the token isn't one of its own.
+    pub fn access(id: Uuid, token: &str) -> Result<Option<Access>, ServerFnError> {
+        Ok(find_links(id)?.and_then(|links| {
+            if links.edit_token == token {
+                Some(Access::Editor)
+            } else if links.view_token.as_deref() == Some(token) {
+                Some(Access::ReadOnly)
+            } else {
+                None
+            }
+        }))
+    }
+
+    pub fn require_access(id: Uuid, token: &str, needed: Access) -> Result<Access, ServerFnError> {
+        match access(id, token)? {
+            Some(access) if access.allows(needed) => Ok(access),
+            _ => Err(ServerFnError::ServerError(String::from("this link is invalid or has been revoked"))),
+        }
+    }
+
+    pub fn get_links(id: Uuid) -> Result<BillLinks, ServerFnError> {
+        find_links(id)?.ok_or_else(|| ServerFnError::ServerError(format!("bill {} not found", id)))
+    }
+
+    pub fn find_links(id: Uuid) -> Result<Option<BillLinks>, ServerFnError> {
+        with_connection(|conn| {
+            conn.query_row(
+                "SELECT view_token, edit_token FROM bills WHERE id = ?1",
+                params![id.to_string()],
+                |row| {
+                    Ok(BillLinks {
+                        id,
+                        view_token: row.get(0)?,
+                        edit_token: row.get(1)?,
+                    })
+                },
+            )
+            .optional()
+        })
+    }
+
+    pub fn set_token(id: Uuid, kind: Access, token: Option<String>) -> Result<(), ServerFnError> {
+        let sql = match kind {
+            Access::ReadOnly => "UPDATE bills SET view_token = ?2 WHERE id = ?1",
+            Access::Editor => "UPDATE bills SET edit_token = ?2 WHERE id = ?1",
+        };
+        with_connection(|conn| conn.execute(sql, params![id.to_string(), token]))?;
+        Ok(())
+    }
+
+    pub fn insert_bill(bill: &Bill, links: &BillLinks, author: &str) -> Result<(), ServerFnError> {
+        let data = serde_json::to_string(bill).map_err(server_error)?;
+        with_connection(|conn| {
+            let tx = conn.unchecked_transaction()?;
+            tx.execute(
+                "INSERT INTO bills (id, event_name, data, created_at, updated_at, view_token, edit_token)
+                 VALUES (?1, ?2, ?3, ?4, ?4, ?5, ?6)",
+                params![bill.id.to_string(), bill.event_name, data, now(), links.view_token, links.edit_token],
+            )?;
+            record_change(&tx, bill.id, author, &Bill::empty(bill.id), bill, None)?;
+            tx.commit()
+        })
+    }
+
+    fn insert_event(
+        conn: &Connection,
+        id: Uuid,
+        revision: i64,
+        author: &str,
+        restored_from: Option<i64>,
+        ops: &[BillOp],
+    ) -> rusqlite::Result<()> {
+        let ops = serde_json::to_string(ops).map_err(to_sql_error)?;
+        conn.execute(
+            "INSERT INTO bill_events (bill_id, revision, author, created_at, restored_from, ops)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![id.to_string(), revision, author, now(), restored_from, ops],
+        )?;
+        Ok(())
+    }
+
+    /// Stores the change from `before` to `after` as the next revision. Bills
+    /// saved before history existed first get their state at that point as a
+    /// baseline revision.
+    fn record_change(
+        conn: &Connection,
+        id: Uuid,
+        author: &str,
+        before: &Bill,
+        after: &Bill,
+        restored_from: Option<i64>,
+    ) -> rusqlite::Result<()> {
+        let ops = before.diff(after);
+        if ops.is_empty() {
+            return Ok(());
+        }
+        let mut revision: i64 = conn.query_row(
+            "SELECT COALESCE(MAX(revision), 0) FROM bill_events WHERE bill_id = ?1",
+            params![id.to_string()],
+            |row| row.get(0),
+        )?;
+        if revision == 0 {
+            let baseline = Bill::empty(id).diff(before);
+            if !baseline.is_empty() {
+                revision += 1;
+                insert_event(conn, id, revision, "", None, &baseline)?;
+            }
+        }
+        insert_event(conn, id, revision + 1, author, restored_from, &ops)
+    }
+
+    pub fn list_events(id: Uuid) -> Result<Vec<BillEvent>, ServerFnError> {
+        with_connection(|conn| read_events(conn, id))
+    }
+
+    fn read_events(conn: &Connection, id: Uuid) -> rusqlite::Result<Vec<BillEvent>> {
+        let mut stmt = conn.prepare(
+            "SELECT revision, author, created_at, restored_from, ops FROM bill_events
+             WHERE bill_id = ?1 ORDER BY revision",
+        )?;
+        let rows = stmt.query_map(params![id.to_string()], |row| {
+            let ops: String = row.get(4)?;
+            Ok(BillEvent {
+                revision: row.get(0)?,
+                author: row.get(1)?,
+                created_at: row.get(2)?,
+                restored_from: row.get(3)?,
+                ops: serde_json::from_str(&ops).map_err(to_sql_error)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// The stored document of a bill, or a fresh one for bills that were never
+    /// synced. Must be called with the connection lock held.
+    fn load_doc(conn: &Connection, id: Uuid) -> rusqlite::Result<Option<BillDoc>> {
+        let row: Option<(String, Option<String>)> = conn
+            .query_row("SELECT data, doc FROM bills WHERE id = ?1", params![id.to_string()], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .optional()?;
+        let Some((data, doc)) = row else {
+            return Ok(None);
+        };
+        match doc {
+            Some(doc) => serde_json::from_str(&doc).map(Some).map_err(to_sql_error),
+            None => serde_json::from_str::<Bill>(&data)
+                .map(|bill| Some(BillDoc::from_bill(&bill)))
+                .map_err(to_sql_error),
+        }
+    }
+
+    /// Stores `doc` if it changed and records what changed in the history,
+    /// both or neither.
+    fn commit_doc(
+        conn: &Connection,
+        before: &BillDoc,
+        doc: &BillDoc,
+        author: &str,
+        restored_from: Option<i64>,
+    ) -> rusqlite::Result<()> {
+        if doc == before {
+            return Ok(());
+        }
+        let tx = conn.unchecked_transaction()?;
+        store_doc(&tx, doc)?;
+        record_change(&tx, doc.id, author, &before.to_bill(), &doc.to_bill(), restored_from)?;
+        tx.commit()
+    }
+
+    fn store_doc(conn: &Connection, doc: &BillDoc) -> rusqlite::Result<usize> {
+        let bill = doc.to_bill();
+        let data = serde_json::to_string(&bill).map_err(to_sql_error)?;
+        let doc_data = serde_json::to_string(doc).map_err(to_sql_error)?;
+        conn.execute(
+            "UPDATE bills SET event_name = ?2, data = ?3, doc = ?4, updated_at = ?5 WHERE id = ?1",
+            params![doc.id.to_string(), bill.event_name, data, doc_data, now()],
+        )
+    }
+
+    pub fn get_doc(id: Uuid) -> Result<Option<BillDoc>, ServerFnError> {
+        with_connection(|conn| load_doc(conn, id))
+    }
+
+    fn finalized_error() -> ServerFnError {
+        ServerFnError::ServerError(String::from("the bill is finalized, unlock it before changing it"))
+    }
+
+    /// Merges `doc` into the stored document in one go, so two devices
+    /// syncing at the same time can't lose each other's edits.
+    pub fn merge_doc(id: Uuid, doc: &BillDoc, author: &str) -> Result<BillDoc, ServerFnError> {
+        with_connection(|conn| {
+            let stored = load_doc(conn, id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+            let mut merged = stored.clone();
+            merged.merge(doc);
+            if !stored.to_bill().allows_change_to(&merged.to_bill()) {
+                return Ok(Err(finalized_error()));
+            }
+            commit_doc(conn, &stored, &merged, author, None)?;
+            Ok(Ok(merged))
+        })?
+    }
+
+    /// Applies edits sent over the live socket, and returns the ones that
+    /// changed the bill, so only those are passed on.
+    pub fn apply_ops(id: Uuid, ops: &[StampedOp], author: &str) -> Result<Vec<StampedOp>, ServerFnError> {
+        with_connection(|conn| {
+            let stored = load_doc(conn, id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+            let mut doc = stored.clone();
+            let mut accepted = Vec::new();
+            for op in ops {
+                if doc.is_finalized() && !op.op.unlocks() {
+                    return Ok(Err(finalized_error()));
+                }
+                if doc.apply(op) {
+                    accepted.push(op.clone());
+                }
+            }
+            commit_doc(conn, &stored, &doc, author, None)?;
+            Ok(Ok(accepted))
+        })?
+    }
+
+    /// Saves a whole bill as an edit on top of the stored document, so it
+    /// merges with edits from devices that are still offline.
+    pub fn save_bill(bill: &Bill, author: &str) -> Result<(), ServerFnError> {
+        with_connection(|conn| write_bill(conn, bill, author, None))?
+    }
+
+    /// Puts the bill back the way it was at `revision`, reading the history
+    /// under the same lock so no edit can slip in between.
+    pub fn restore_revision(id: Uuid, revision: i64, author: &str) -> Result<(), ServerFnError> {
+        with_connection(|conn| {
+            let events = read_events(conn, id)?;
+            if !events.iter().any(|e| e.revision == revision) {
+                return Ok(Err(ServerFnError::ServerError(format!("revision {} not found", revision))));
+            }
+            let bill = crate::history::bill_at(id, &events, revision);
+            write_bill(conn, &bill, author, Some(revision))
+        })?
+    }
+
+    /// Changes a bill in place, all under the lock so nobody can save in
+    /// between. Nothing is stored if `f` fails. Also returns the change as
+    /// stamped ops, for anyone watching the bill live.
+    pub fn edit_bill<T, E: From<ServerFnError>>(
+        id: Uuid,
+        author: &str,
+        f: impl FnOnce(&mut Bill) -> Result<T, E>,
+    ) -> Result<(Bill, T, Vec<StampedOp>), E> {
+        with_connection(|conn| {
+            let stored = load_doc(conn, id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+            let current = stored.to_bill();
+            let mut bill = current.clone();
+            let value = match f(&mut bill) {
+                Ok(value) => value,
+                Err(e) => return Ok(Err(e)),
+            };
+            if !current.allows_change_to(&bill) {
+                return Ok(Err(finalized_error().into()));
+            }
+            let mut doc = stored.clone();
+            let stamped = doc.apply_local(SERVER_ACTOR, &current.diff(&bill));
+            commit_doc(conn, &stored, &doc, author, None)?;
+            Ok(Ok((doc.to_bill(), value, stamped)))
+        })?
+    }
+
+    fn write_bill(
+        conn: &Connection,
+        bill: &Bill,
+        author: &str,
+        restored_from: Option<i64>,
+    ) -> rusqlite::Result<Result<(), ServerFnError>> {
+        let stored = load_doc(conn, bill.id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+        let current = stored.to_bill();
+        if !current.allows_change_to(bill) {
+            return Ok(Err(finalized_error()));
+        }
+        let mut doc = stored.clone();
+        doc.apply_local(SERVER_ACTOR, &current.diff(bill));
+        commit_doc(conn, &stored, &doc, author, restored_from).map(Ok)
+    }
+
+    pub fn get_bill(id: Uuid) -> Result<Option<Bill>, ServerFnError> {
+        let data: Option<String> = with_connection(|conn| {
+            conn.query_row("SELECT data FROM bills WHERE id = ?1", params![id.to_string()], |row| row.get(0))
+                .optional()
+        })?;
+        data.map(|data| serde_json::from_str(&data).map_err(server_error))
+            .transpose()
+    }
+
+    pub fn list_bills(edit_tokens: &[String]) -> Result<Vec<BillSummary>, ServerFnError> {
+        let tokens = serde_json::to_string(edit_tokens).map_err(server_error)?;
+        with_connection(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, event_name, updated_at FROM bills
+                 WHERE edit_token IN (SELECT value FROM json_each(?1))
+                 ORDER BY updated_at DESC",
+            )?;
+            let rows = stmt.query_map(params![tokens], |row| {
+                let id: String = row.get(0)?;
+                Ok(BillSummary {
+                    id: Uuid::parse_str(&id).unwrap_or_default(),
+                    event_name: row.get(1)?,
+                    updated_at: row.get(2)?,
+                })
+            })?;
+            rows.collect()
+        })
+    }
+
+    pub fn delete_bill(id: Uuid) -> Result<(), ServerFnError> {
+        with_connection(|conn| remove_bill(conn, id))
+    }
+
+    /// Deletes a bill if `check` passes on it, all under the lock like
+    /// `edit_bill`, so nobody can save in between.
+    pub fn delete_bill_if<E: From<ServerFnError>>(
+        id: Uuid,
+        check: impl FnOnce(&Bill) -> Result<(), E>,
+    ) -> Result<(), E> {
+        with_connection(|conn| {
+            let stored = load_doc(conn, id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+            if let Err(e) = check(&stored.to_bill()) {
+                return Ok(Err(e));
+            }
+            remove_bill(conn, id).map(Ok)
+        })?
+    }
+
+    fn remove_bill(conn: &Connection, id: Uuid) -> rusqlite::Result<()> {
+        let tx = conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM bill_events WHERE bill_id = ?1", params![id.to_string()])?;
+        tx.execute("DELETE FROM bills WHERE id = ?1", params![id.to_string()])?;
+        tx.commit()
+    }
+
+    /// Only a hash of the write token is kept, so the database alone doesn't
+    /// let anyone save.
+    fn write_hash(write_token: &str) -> String {
+        format!("{:x}", Sha256::digest(write_token.as_bytes()))
+    }
+
+    pub fn insert_encrypted_bill(id: Uuid, ciphertext: &str, write_token: &str) -> Result<(), ServerFnError> {
+        if write_token.is_empty() {
+            return Err(ServerFnError::ServerError(String::from("a write token is required")));
+        }
+        with_connection(|conn| {
+            conn.execute(
+                "INSERT INTO encrypted_bills (id, ciphertext, version, write_hash) VALUES (?1, ?2, 1, ?3)",
+                params![id.to_string(), ciphertext, write_hash(write_token)],
+            )
+        })?;
+        Ok(())
+    }
+
+    pub fn get_encrypted_bill(id: Uuid) -> Result<Option<EncryptedBill>, ServerFnError> {
+        with_connection(|conn| {
+            conn.query_row(
+                "SELECT ciphertext, version FROM encrypted_bills WHERE id = ?1",
+                params![id.to_string()],
+                |row| Ok(EncryptedBill { ciphertext: row.get(0)?, version: row.get(1)? }),
+            )
+            .optional()
+        })
+    }
+
+    /// Saves over the bill if `write_token` is its own, and returns how many
+    /// rows changed: 0 when someone else saved since `version`.
+    pub fn update_encrypted_bill(
+        id: Uuid,
+        ciphertext: &str,
+        version: i64,
+        write_token: &str,
+    ) -> Result<usize, ServerFnError> {
+        let stored: Option<Option<String>> = with_connection(|conn| {
+            conn.query_row(
+                "SELECT write_hash FROM encrypted_bills WHERE id = ?1",
+                params![id.to_string()],
+                |row| row.get(0),
+            )
+            .optional()
+        })?;
+        match stored {
+            None => return Err(ServerFnError::ServerError(format!("bill {} not found", id))),
+            Some(None) => {
+                return Err(ServerFnError::ServerError(String::from(
+                    "this bill was saved before it could be protected, export it and import it into a new one",
+                )))
+            }
+            Some(Some(hash)) if write_token.is_empty() || hash != write_hash(write_token) => {
+                return Err(ServerFnError::ServerError(String::from("this link can't save changes to the bill")))
+            }
+            Some(Some(_)) => {}
+        }
+        with_connection(|conn| {
+            conn.execute(
+                "UPDATE encrypted_bills SET ciphertext = ?2, version = version + 1
+                 WHERE id = ?1 AND version = ?3 AND write_hash = ?4",
+                params![id.to_string(), ciphertext, version, write_hash(write_token)],
+            )
+        })
+    }
+}
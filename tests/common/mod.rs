@@ -0,0 +1,40 @@
+//! Bills, people and items for the integration tests.
+#![allow(dead_code)]
+
+use rust_decimal::Decimal;
+use uuid::Uuid;
+use whati8::app::Assignment;
+use whati8::bill::{Bill, BillItem, BillParticipant};
+
+pub fn money(text: &str) -> Decimal {
+    text.parse().unwrap()
+}
+
+pub fn participant(name: &str) -> BillParticipant {
+    BillParticipant { id: Uuid::new_v4(), name: name.to_string(), payer: false, settled: false, weight: Decimal::ONE }
+}
+
+/// An item everyone shares.
+pub fn item(name: &str, price: &str) -> BillItem {
+    BillItem {
+        id: Uuid::new_v4(),
+        name: name.to_string(),
+        price: money(price),
+        quantity: 1,
+        category: String::new(),
+        assignment: Assignment::Everyone,
+        participants: Vec::new(),
+    }
+}
+
+/// An empty bill with these people on it.
+pub fn bill_with(names: &[&str]) -> Bill {
+    let mut bill = Bill::empty(Uuid::new_v4());
+    bill.participants = names.iter().map(|name| participant(name)).collect();
+    bill
+}
+
+/// The id of whoever on `bill` is called `name`.
+pub fn id(bill: &Bill, name: &str) -> Uuid {
+    bill.participants.iter().find(|p| p.name == name).unwrap().id
+}
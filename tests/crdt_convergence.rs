@@ -0,0 +1,255 @@
+mod common;
+
+use common::{bill_with, item, money, participant};
+use uuid::Uuid;
+use whati8::app::Assignment;
+use whati8::bill::Bill;
+use whati8::crdt::{BillDoc, Stamp, StampedOp};
+use whati8::ops::{BillOp, ItemPatch, ParticipantPatch, SettingsPatch};
+
+fn base() -> Bill {
+    let mut bill = bill_with(&["Alice", "Bob"]);
+    bill.event_name = String::from("Dinner");
+    bill.items = vec![item("Pizza", "450"), item("Beer", "300")];
+    bill
+}
+
+fn stamped(clock: u64, actor: Uuid, op: BillOp) -> StampedOp {
+    StampedOp { stamp: Stamp { clock, actor }, op }
+}
+
+/// Edits three devices made at the same time, without seeing each other's.
+fn concurrent_ops(bill: &Bill) -> Vec<StampedOp> {
+    let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    let (alice, bob) = (bill.participants[0].id, bill.participants[1].id);
+    let (pizza, beer) = (bill.items[0].id, bill.items[1].id);
+    vec![
+        stamped(1, a, BillOp::UpdateSettings(SettingsPatch { tip: Some(money("50")), ..Default::default() })),
+        stamped(1, b, BillOp::UpdateSettings(SettingsPatch { total_tax: Some(money("36")), ..Default::default() })),
+        stamped(1, c, BillOp::UpdateParticipant(ParticipantPatch { id: alice, name: Some(String::from("Ally")), ..Default::default() })),
+        stamped(2, a, BillOp::UpdateParticipant(ParticipantPatch { id: alice, name: Some(String::from("Alicia")), ..Default::default() })),
+        stamped(2, b, BillOp::RemoveItem(beer)),
+        stamped(2, c, BillOp::UpdateItem(ItemPatch { id: beer, price: Some(money("320")), ..Default::default() })),
+        stamped(3, c, BillOp::UpdateItem(ItemPatch { id: pizza, participants: Some(vec![bob]), assignment: Some(Assignment::Selected), ..Default::default() })),
+        stamped(3, b, BillOp::AddParticipant(participant("Carol"))),
+    ]
+}
+
+/// Every order of `items`, by Heap's algorithm.
+fn permutations<T: Clone>(items: &[T]) -> Vec<Vec<T>> {
+    fn permute<T: Clone>(k: usize, items: &mut [T], out: &mut Vec<Vec<T>>) {
+        if k <= 1 {
+            out.push(items.to_vec());
+            return;
+        }
+        for i in 0..k {
+            permute(k - 1, items, out);
+            let j = if k % 2 == 0 { i } else { 0 };
+            items.swap(j, k - 1);
+        }
+    }
+    let mut out = Vec::new();
+    permute(items.len(), &mut items.to_vec(), &mut out);
+    out
+}
+
+fn applied(bill: &Bill, ops: &[StampedOp]) -> BillDoc {
+    let mut doc = BillDoc::from_bill(bill);
+    for op in ops {
+        doc.apply(op);
+    }
+    doc
+}
+
+#[test]
+fn the_same_ops_in_any_order_give_the_same_doc() {
+    let bill = base();
+    let ops = concurrent_ops(&bill);
+    let expected = applied(&bill, &ops);
+    for order in permutations(&ops) {
+        assert_eq!(applied(&bill, &order), expected);
+    }
+
+    let result = expected.to_bill();
+    assert_eq!(result.tip, money("50"));
+    assert_eq!(result.total_tax, money("36"));
+    let alice = result.participants.iter().find(|p| p.id == bill.participants[0].id).unwrap();
+    assert_eq!(alice.name, "Alicia");
+    assert_eq!(result.participants.len(), 3);
+    assert_eq!(result.items.len(), 1);
+    assert_eq!(result.items[0].participants, vec![bill.participants[1].id]);
+}
+
+#[test]
+fn applying_an_op_twice_changes_nothing_the_second_time() {
+    let bill = base();
+    let mut doc = BillDoc::from_bill(&bill);
+    for op in concurrent_ops(&bill) {
+        doc.apply(&op);
+        let once = doc.clone();
+        assert!(!doc.apply(&op));
+        assert_eq!(doc, once);
+    }
+}
+
+#[test]
+fn concurrent_edits_to_different_settings_both_survive() {
+    let bill = base();
+    let mut phone = BillDoc::from_bill(&bill);
+    let mut laptop = BillDoc::from_bill(&bill);
+    phone.apply_local(Uuid::new_v4(), &[BillOp::UpdateSettings(SettingsPatch { tip: Some(money("40")), ..Default::default() })]);
+    laptop.apply_local(Uuid::new_v4(), &[BillOp::UpdateSettings(SettingsPatch { total_tax: Some(money("20")), ..Default::default() })]);
+
+    phone.merge(&laptop);
+    let result = phone.to_bill();
+    assert_eq!(result.tip, money("40"));
+    assert_eq!(result.total_tax, money("20"));
+}
+
+/// Three devices that each made some of the concurrent edits.
+fn devices(bill: &Bill, ops: &[StampedOp]) -> (BillDoc, BillDoc, BillDoc) {
+    (applied(bill, &ops[..3]), applied(bill, &ops[3..6]), applied(bill, &ops[6..]))
+}
+
+#[test]
+fn merge_is_commutative() {
+    let bill = base();
+    let (a, b, _) = devices(&bill, &concurrent_ops(&bill));
+    let mut ab = a.clone();
+    ab.merge(&b);
+    let mut ba = b.clone();
+    ba.merge(&a);
+    assert_eq!(ab, ba);
+}
+
+#[test]
+fn merge_is_associative() {
+    let bill = base();
+    let (a, b, c) = devices(&bill, &concurrent_ops(&bill));
+    let mut ab_c = a.clone();
+    ab_c.merge(&b);
+    ab_c.merge(&c);
+    let mut bc = b.clone();
+    bc.merge(&c);
+    let mut a_bc = a.clone();
+    a_bc.merge(&bc);
+    assert_eq!(ab_c, a_bc);
+}
+
+#[test]
+fn merge_is_idempotent() {
+    let bill = base();
+    let (a, b, _) = devices(&bill, &concurrent_ops(&bill));
+    let mut same = a.clone();
+    same.merge(&a);
+    assert_eq!(same, a);
+
+    let mut once = a.clone();
+    once.merge(&b);
+    let mut twice = once.clone();
+    twice.merge(&b);
+    assert_eq!(twice, once);
+}
+
+#[test]
+fn merging_gives_the_same_doc_as_applying_every_op() {
+    let bill = base();
+    let ops = concurrent_ops(&bill);
+    let (mut a, b, c) = devices(&bill, &ops);
+    a.merge(&b);
+    a.merge(&c);
+    assert_eq!(a, applied(&bill, &ops));
+}
+
+#[test]
+fn edits_that_arrive_before_their_add_are_kept() {
+    let bill = base();
+    let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+    let carol = participant("Carol");
+    let fries = item("Fries", "120");
+    let ops = vec![
+        stamped(1, a, BillOp::AddParticipant(carol.clone())),
+        stamped(2, b, BillOp::UpdateParticipant(ParticipantPatch { id: carol.id, name: Some(String::from("Caz")), ..Default::default() })),
+        stamped(1, a, BillOp::AddItem(fries.clone())),
+        stamped(2, b, BillOp::UpdateItem(ItemPatch { id: fries.id, price: Some(money("140")), ..Default::default() })),
+        stamped(3, a, BillOp::RemoveParticipant(bill.participants[1].id)),
+    ];
+    let expected = applied(&bill, &ops);
+    for order in permutations(&ops) {
+        assert_eq!(applied(&bill, &order), expected);
+    }
+
+    let result = expected.to_bill();
+    let carol = result.participants.iter().find(|p| p.id == carol.id).unwrap();
+    assert_eq!(carol.name, "Caz");
+    let fries = result.items.iter().find(|i| i.id == fries.id).unwrap();
+    assert_eq!((fries.name.as_str(), fries.price), ("Fries", money("140")));
+    assert_eq!(result.participants.len(), 2);
+
+    // An edit with nothing to apply to yet isn't shown, but merges in
+    let mut early = applied(&bill, &ops[1..2]);
+    assert_eq!(early.to_bill(), BillDoc::from_bill(&bill).to_bill());
+    early.merge(&applied(&bill, &ops[..1]));
+    assert_eq!(early.to_bill().participants[2].name, "Caz");
+}
+
+#[test]
+fn an_edit_racing_the_lock_is_kept_in_any_order() {
+    let bill = base();
+    let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+    let hash = bill.content_hash();
+    let pizza = bill.items[0].id;
+    let ops = vec![
+        stamped(2, a, BillOp::UpdateSettings(SettingsPatch { finalized: Some(Some(hash.clone())), ..Default::default() })),
+        stamped(2, b, BillOp::UpdateItem(ItemPatch { id: pizza, price: Some(money("500")), ..Default::default() })),
+        stamped(1, b, BillOp::UpdateSettings(SettingsPatch { tip: Some(money("20")), ..Default::default() })),
+    ];
+    let expected = applied(&bill, &ops);
+    for order in permutations(&ops) {
+        assert_eq!(applied(&bill, &order), expected);
+    }
+    let mut merged = applied(&bill, &ops[..1]);
+    merged.merge(&applied(&bill, &ops[1..]));
+    assert_eq!(merged, expected);
+
+    // Both edits stay, and the hash taken at the lock no longer matches
+    let result = expected.to_bill();
+    assert_eq!(result.finalized, Some(hash.clone()));
+    assert_eq!(result.items.iter().find(|i| i.id == pizza).unwrap().price, money("500"));
+    assert_ne!(result.content_hash(), hash);
+}
+
+#[test]
+fn a_locked_doc_only_takes_local_edits_that_unlock_it() {
+    let bill = base();
+    let mut doc = BillDoc::from_bill(&bill);
+    let actor = Uuid::new_v4();
+    let lock = BillOp::UpdateSettings(SettingsPatch { finalized: Some(Some(bill.content_hash())), ..Default::default() });
+    assert_eq!(doc.apply_local(actor, &[lock]).len(), 1);
+
+    let tip = BillOp::UpdateSettings(SettingsPatch { tip: Some(money("20")), ..Default::default() });
+    assert!(doc.apply_local(actor, &[tip.clone()]).is_empty());
+    let unlock = BillOp::UpdateSettings(SettingsPatch { finalized: Some(None), ..Default::default() });
+    assert_eq!(doc.apply_local(actor, &[unlock, tip]).len(), 2);
+    assert_eq!(doc.to_bill().tip, money("20"));
+}
+
+#[test]
+fn docs_stored_with_all_settings_in_one_register_still_load() {
+    let mut bill = base();
+    bill.tip = money("25");
+    let doc = BillDoc::from_bill(&bill);
+    let mut stored = serde_json::to_value(&doc).unwrap();
+    stored["settings"] = serde_json::json!({
+        "value": bill.settings(),
+        "stamp": Stamp { clock: 4, actor: Uuid::new_v4() },
+    });
+
+    let mut loaded: BillDoc = serde_json::from_value(stored).unwrap();
+    assert_eq!(loaded.to_bill().settings(), bill.settings());
+
+    // Every setting kept the old register's stamp, so older edits lose
+    let late = stamped(3, Uuid::new_v4(), BillOp::UpdateSettings(SettingsPatch { tip: Some(money("99")), ..Default::default() }));
+    assert!(!loaded.apply(&late));
+    assert_eq!(loaded.to_bill().tip, money("25"));
+}
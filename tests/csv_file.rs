@@ -0,0 +1,307 @@
+mod common;
+
+use std::collections::HashMap;
+use common::{bill_with, id, item, money};
+use leptos::create_runtime;
+use rust_decimal::Decimal;
+use whati8::app::{Assignment, SplitItem};
+use whati8::bill::{Bill, BillItem};
+use whati8::csv_file::{import_ops, items_csv, parse_items_csv, participants_csv, ImportRow, ImportedItem};
+use whati8::ops::BillOp;
+
+fn imported(name: &str, quantity: u32, unit_price: &str, category: &str, participants: &[&str]) -> ImportedItem {
+    ImportedItem {
+        name: name.to_string(),
+        quantity,
+        unit_price: money(unit_price),
+        category: category.to_string(),
+        participants: participants.iter().map(|p| p.to_string()).collect(),
+    }
+}
+
+fn items(rows: &[ImportRow]) -> Vec<ImportedItem> {
+    rows.iter().map(|row| row.item.clone().unwrap()).collect()
+}
+
+fn added_items(ops: &[BillOp]) -> Vec<BillItem> {
+    ops.iter()
+        .filter_map(|op| match op {
+            BillOp::AddItem(item) => Some(item.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn added_names(ops: &[BillOp]) -> Vec<String> {
+    ops.iter()
+        .filter_map(|op| match op {
+            BillOp::AddParticipant(p) => Some(p.name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn headers_go_by_any_of_their_names() {
+    let rows = parse_items_csv(
+        "Item Name, Qty ,Rate,Type,Shared By\n\
+         Paneer Tikka,2,280,Starters,Alice;Bob\n",
+    )
+    .unwrap();
+    assert_eq!(items(&rows), vec![imported("Paneer Tikka", 2, "280", "Starters", &["Alice", "Bob"])]);
+
+    let rows = parse_items_csv(
+        "description,count,unit_price,category,people\n\
+         Lime Soda,3,90,Drinks,Carol|Dan\n",
+    )
+    .unwrap();
+    assert_eq!(items(&rows), vec![imported("Lime Soda", 3, "90", "Drinks", &["Carol", "Dan"])]);
+}
+
+#[test]
+fn only_name_and_price_are_needed() {
+    let rows = parse_items_csv("price,item\n60,Butter Naan\n").unwrap();
+    assert_eq!(items(&rows), vec![imported("Butter Naan", 1, "60", "", &[])]);
+
+    assert_eq!(parse_items_csv("item,qty\nNaan,2\n").unwrap_err(), "there's no unit price column");
+    assert_eq!(parse_items_csv("qty,price\n2,60\n").unwrap_err(), "there's no name column");
+}
+
+#[test]
+fn bad_rows_are_reported_with_their_line() {
+    let rows = parse_items_csv(
+        "name,quantity,unit price\n\
+         Dal Makhani,1,320\n\
+         ,1,100\n\
+         Roti,2,cheap\n\
+         ,,\n\
+         Raita,0,50\n\
+         Papad,two,20\n\
+         Refund,1,-40\n",
+    )
+    .unwrap();
+    let lines: Vec<(u64, Result<ImportedItem, String>)> = rows.into_iter().map(|row| (row.line, row.item)).collect();
+    assert_eq!(
+        lines,
+        vec![
+            (2, Ok(imported("Dal Makhani", 1, "320", "", &[]))),
+            (3, Err(String::from("the name is empty"))),
+            (4, Err(String::from("'cheap' isn't a price"))),
+            (6, Err(String::from("'0' isn't a quantity"))),
+            (7, Err(String::from("'two' isn't a quantity"))),
+            (8, Err(String::from("the price can't be negative"))),
+        ]
+    );
+}
+
+#[test]
+fn bad_rows_are_left_out_of_the_import() {
+    let rows = parse_items_csv("name,unit price\nDal,320\nRoti,cheap\n").unwrap();
+    let bill = bill_with(&[]);
+    let names: Vec<String> = added_items(&import_ops(&bill, &rows, true)).into_iter().map(|i| i.name).collect();
+    assert_eq!(names, vec!["Dal"]);
+}
+
+#[test]
+fn price_is_quantity_times_unit_price_rounded_to_paise() {
+    let rows = parse_items_csv(
+        "name,qty,unit price\n\
+         Filter Coffee,3,33.333\n\
+         Biryani,2,\"₹1,250.50\"\n\
+         Lassi,7,Rs. 0.125\n",
+    )
+    .unwrap();
+    let items = items(&rows);
+    assert_eq!(items[0].unit_price, money("33.333"));
+    assert_eq!(items[0].price(), money("100.00"));
+    assert_eq!(items[1].price(), money("2501.00"));
+    assert_eq!(items[2].price(), money("0.88"));
+
+    let bill = bill_with(&[]);
+    let added = added_items(&import_ops(&bill, &rows, false));
+    let prices: Vec<(u32, Decimal)> = added.iter().map(|i| (i.quantity, i.price)).collect();
+    assert_eq!(prices, vec![(3, money("100.00")), (2, money("2501.00")), (7, money("0.88"))]);
+}
+
+#[test]
+fn all_or_everyone_assigns_the_item_to_everyone() {
+    let bill = bill_with(&["Alice", "Bob"]);
+    let rows = parse_items_csv(
+        "name,price,participants\n\
+         Pizza,450,All\n\
+         Beer,300,everyone\n\
+         Fries,120,EVERYONE\n",
+    )
+    .unwrap();
+    for row in rows.iter() {
+        assert_eq!(row.status(&bill, false), Ok(None));
+    }
+
+    let ops = import_ops(&bill, &rows, false);
+    assert!(added_names(&ops).is_empty());
+    for item in added_items(&ops) {
+        assert_eq!(item.assignment, Assignment::Everyone, "{}", item.name);
+        assert!(item.participants.is_empty());
+    }
+}
+
+#[test]
+fn names_match_the_bill_ignoring_case_and_spacing() {
+    let bill = bill_with(&["Alice", "Bob"]);
+    let rows = parse_items_csv("name,price,participants\nPizza,450,\" alice ; BOB\"\n").unwrap();
+
+    let added = added_items(&import_ops(&bill, &rows, false));
+    assert_eq!(added[0].assignment, Assignment::Selected);
+    assert_eq!(added[0].participants, vec![bill.participants[0].id, bill.participants[1].id]);
+}
+
+#[test]
+fn missing_participants_are_created_when_asked() {
+    let bill = bill_with(&["Alice"]);
+    let rows = parse_items_csv(
+        "name,price,participants\n\
+         Pizza,450,Alice;Bob;Carol\n\
+         Beer,300,bob\n\
+         Fries,120,Alice\n",
+    )
+    .unwrap();
+
+    assert_eq!(rows[0].status(&bill, true), Ok(Some(String::from("adds Bob, Carol"))));
+    assert_eq!(rows[0].status(&bill, false), Err(String::from("Bob, Carol isn't on the bill")));
+
+    // Bob is added once, for the first row, and the second row finds him
+    let ops = import_ops(&bill, &rows, true);
+    assert_eq!(added_names(&ops), vec!["Bob", "Carol"]);
+    let mut imported = bill.clone();
+    for op in ops.iter() {
+        imported.apply(op);
+    }
+    let id = |name: &str| imported.participants.iter().find(|p| p.name == name).unwrap().id;
+    let added = added_items(&ops);
+    assert_eq!(added[0].participants, vec![id("Alice"), id("Bob"), id("Carol")]);
+    assert_eq!(added[1].participants, vec![id("Bob")]);
+    assert_eq!(added[2].participants, vec![id("Alice")]);
+}
+
+#[test]
+fn rows_naming_missing_participants_are_skipped_otherwise() {
+    let bill = bill_with(&["Alice"]);
+    let rows = parse_items_csv(
+        "name,price,participants\n\
+         Pizza,450,Alice;Bob\n\
+         Fries,120,Alice\n",
+    )
+    .unwrap();
+
+    let ops = import_ops(&bill, &rows, false);
+    assert!(added_names(&ops).is_empty());
+    let names: Vec<String> = added_items(&ops).into_iter().map(|i| i.name).collect();
+    assert_eq!(names, vec!["Fries"]);
+}
+
+fn dinner() -> Bill {
+    let mut bill = bill_with(&["Alice", "Bob", "Carol"]);
+    bill.event_name = String::from("Dinner");
+    bill.participants[0].payer = true;
+    bill.participants[2].weight = money("2");
+    let mut beer = item("Beer", "333.33");
+    beer.quantity = 3;
+    beer.category = String::from("Drinks");
+    beer.assignment = Assignment::Selected;
+    beer.participants = vec![id(&bill, "Bob"), id(&bill, "Carol")];
+    bill.items = vec![item("Pizza", "450"), beer];
+    bill.total_tax = money("36.55");
+    bill.tip = money("50");
+    bill.discount = money("20");
+    bill
+}
+
+/// The rows of a CSV file as maps from column to value.
+fn records(csv: &str) -> Vec<HashMap<String, String>> {
+    let mut reader = csv::Reader::from_reader(csv.as_bytes());
+    let headers = reader.headers().unwrap().clone();
+    reader
+        .records()
+        .map(|record| headers.iter().map(String::from).zip(record.unwrap().iter().map(String::from)).collect())
+        .collect()
+}
+
+/// Each participant's "Total Amount Owed" in the summary text, by name.
+fn summary_totals(summary: &str) -> Vec<(String, String)> {
+    let mut totals = Vec::new();
+    let mut name = None;
+    for line in summary.lines() {
+        if let Some(rest) = line.strip_prefix("- Name: ") {
+            name = rest.trim().trim_end_matches("[PAYER]").split(" (x").next().map(|n| n.trim().to_string());
+        } else if let Some(total) = line.trim().strip_prefix("Total Amount Owed: ₹") {
+            totals.push((name.take().unwrap(), total.to_string()));
+        }
+    }
+    totals
+}
+
+#[test]
+fn participant_totals_match_the_summary() {
+    let runtime = create_runtime();
+    let bill = dinner();
+    let split = SplitItem::from_bill(&bill);
+    let rows = records(&participants_csv(&split).unwrap());
+
+    let exported: Vec<(String, String)> = rows.iter().map(|row| (row["participant"].clone(), row["total"].clone())).collect();
+    assert_eq!(exported.len(), 3);
+    assert_eq!(exported, summary_totals(&split.summary()));
+
+    let shares = split.final_shares();
+    for (p, row) in bill.participants.iter().zip(rows.iter()) {
+        assert_eq!(row["total"].parse::<Decimal>().unwrap(), shares[&p.id], "{}", p.name);
+    }
+    runtime.dispose();
+}
+
+#[test]
+fn participant_rows_add_up() {
+    let runtime = create_runtime();
+    let rows = records(&participants_csv(&SplitItem::from_bill(&dinner())).unwrap());
+    runtime.dispose();
+
+    let column = |row: &HashMap<String, String>, name: &str| row[name].parse::<Decimal>().unwrap();
+    let owed: Decimal = rows.iter().map(|row| column(row, "total")).sum();
+    let paid: Decimal = rows.iter().map(|row| column(row, "paid")).sum();
+    assert_eq!(paid, owed.round_dp(2));
+    for row in rows.iter() {
+        assert_eq!(column(row, "net"), (column(row, "paid") - column(row, "total")).round_dp(2), "{}", row["participant"]);
+    }
+    // Only Alice paid, so she's owed everyone else's share
+    assert_eq!(column(&rows[0], "paid"), owed.round_dp(2));
+    assert_eq!(column(&rows[1], "paid"), Decimal::ZERO);
+}
+
+#[test]
+fn item_rows_share_out_each_price() {
+    let runtime = create_runtime();
+    let bill = dinner();
+    let split = SplitItem::from_bill(&bill);
+    let rows = records(&items_csv(&split).unwrap());
+    runtime.dispose();
+
+    let beer: Vec<(String, String)> = rows
+        .iter()
+        .filter(|row| row["item"] == "Beer")
+        .map(|row| (row["participant"].clone(), row["amount"].clone()))
+        .collect();
+    // Carol counts twice
+    assert_eq!(beer, vec![(String::from("Bob"), String::from("111.11")), (String::from("Carol"), String::from("222.22"))]);
+    assert_eq!(rows.iter().filter(|row| row["item"] == "Pizza").count(), 3);
+}
+
+#[test]
+fn a_blocked_split_isnt_exported() {
+    let runtime = create_runtime();
+    let mut bill = dinner();
+    bill.items[1].participants.clear();
+    let split = SplitItem::from_bill(&bill);
+    assert!(split.blocked());
+    assert!(participants_csv(&split).is_err());
+    assert!(items_csv(&split).is_err());
+    runtime.dispose();
+}
@@ -0,0 +1,164 @@
+#![cfg(feature = "ssr")]
+
+use actix_web::http::{Method, StatusCode};
+use actix_web::{test, App};
+use serde_json::Value;
+use utoipa::OpenApi;
+use whati8::api::{configure, ApiDoc};
+
+/// Every route `configure` registers: method, path, request body and the
+/// body of the successful response. `[T]` is a list of `T`.
+const ROUTES: &[(&str, &str, Option<&str>, &str, Option<&str>)] = &[
+    ("post", "/api/v1/split", Some("SplitRequest"), "200", Some("SplitResponse")),
+    ("post", "/api/v1/bills", Some("Bill"), "201", Some("CreatedBill")),
+    ("get", "/api/v1/bills/{id}", None, "200", Some("Bill")),
+    ("put", "/api/v1/bills/{id}", Some("Bill"), "200", Some("Bill")),
+    ("delete", "/api/v1/bills/{id}", None, "204", None),
+    ("get", "/api/v1/bills/{id}/participants", None, "200", Some("[BillParticipant]")),
+    ("post", "/api/v1/bills/{id}/participants", Some("BillParticipant"), "201", Some("BillParticipant")),
+    ("get", "/api/v1/bills/{id}/participants/{participant_id}", None, "200", Some("BillParticipant")),
+    ("put", "/api/v1/bills/{id}/participants/{participant_id}", Some("BillParticipant"), "200", Some("BillParticipant")),
+    ("delete", "/api/v1/bills/{id}/participants/{participant_id}", None, "204", None),
+    ("get", "/api/v1/bills/{id}/items", None, "200", Some("[BillItem]")),
+    ("post", "/api/v1/bills/{id}/items", Some("BillItem"), "201", Some("BillItem")),
+    ("get", "/api/v1/bills/{id}/items/{item_id}", None, "200", Some("BillItem")),
+    ("put", "/api/v1/bills/{id}/items/{item_id}", Some("BillItem"), "200", Some("BillItem")),
+    ("delete", "/api/v1/bills/{id}/items/{item_id}", None, "204", None),
+];
+
+fn doc() -> Value {
+    serde_json::to_value(ApiDoc::openapi()).unwrap()
+}
+
+/// The schema a body refers to, e.g. `Bill` or `[BillItem]`.
+fn schema_name(schema: &Value) -> String {
+    if let Some(reference) = schema["$ref"].as_str() {
+        return reference.trim_start_matches("#/components/schemas/").to_string();
+    }
+    if schema["type"] == "array" {
+        return format!("[{}]", schema_name(&schema["items"]));
+    }
+    panic!("expected a reference to a schema, got {}", schema)
+}
+
+fn json_schema(body: &Value) -> Option<String> {
+    body.get("content").map(|content| schema_name(&content["application/json"]["schema"]))
+}
+
+fn operation<'a>(doc: &'a Value, method: &str, path: &str) -> &'a Value {
+    doc["paths"]
+        .get(path)
+        .and_then(|item| item.get(method))
+        .unwrap_or_else(|| panic!("{} {} isn't documented", method, path))
+}
+
+fn references(value: &Value, found: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            if let Some(Value::String(reference)) = map.get("$ref") {
+                found.push(reference.clone());
+            }
+            map.values().for_each(|v| references(v, found));
+        }
+        Value::Array(values) => values.iter().for_each(|v| references(v, found)),
+        _ => {}
+    }
+}
+
+#[test]
+fn every_route_is_documented_and_nothing_else() {
+    let doc = doc();
+    let mut documented: Vec<(String, String)> = doc["paths"]
+        .as_object()
+        .unwrap()
+        .iter()
+        .flat_map(|(path, item)| item.as_object().unwrap().keys().map(move |method| (method.clone(), path.clone())))
+        .filter(|(method, _)| ["get", "post", "put", "delete", "patch"].contains(&method.as_str()))
+        .collect();
+    documented.sort();
+    let mut expected: Vec<(String, String)> =
+        ROUTES.iter().map(|(method, path, ..)| (method.to_string(), path.to_string())).collect();
+    expected.sort();
+    assert_eq!(documented, expected);
+}
+
+#[test]
+fn request_and_response_bodies_match_the_handlers() {
+    let doc = doc();
+    for (method, path, request, status, response) in ROUTES {
+        let operation = operation(&doc, method, path);
+        assert_eq!(
+            operation.get("requestBody").and_then(json_schema).as_deref(),
+            *request,
+            "request body of {} {}",
+            method,
+            path
+        );
+        let success = operation["responses"]
+            .get(*status)
+            .unwrap_or_else(|| panic!("{} {} doesn't document a {} response", method, path, status));
+        assert_eq!(json_schema(success).as_deref(), *response, "{} response of {} {}", status, method, path);
+    }
+}
+
+#[test]
+fn errors_are_documented_as_error_responses() {
+    let doc = doc();
+    for (method, path, ..) in ROUTES {
+        let responses = operation(&doc, method, path)["responses"].as_object().unwrap();
+        let errors: Vec<_> = responses.iter().filter(|(status, _)| !status.starts_with('2')).collect();
+        assert!(!errors.is_empty(), "{} {} documents no errors", method, path);
+        for (status, error) in errors {
+            assert_eq!(json_schema(error).as_deref(), Some("ErrorResponse"), "{} response of {} {}", status, method, path);
+        }
+    }
+}
+
+#[test]
+fn routes_with_a_bill_need_the_share_token() {
+    let doc = doc();
+    for (method, path, ..) in ROUTES.iter().filter(|(_, path, ..)| path.contains("{id}")) {
+        let operation = operation(&doc, method, path);
+        assert_eq!(operation["security"][0]["share_token"], serde_json::json!([]), "{} {}", method, path);
+        assert!(operation["responses"].get("401").is_some(), "{} {} doesn't document 401", method, path);
+    }
+    assert!(doc["components"]["securitySchemes"].get("share_token").is_some());
+}
+
+#[test]
+fn every_schema_referenced_is_defined() {
+    let doc = doc();
+    let mut found = Vec::new();
+    references(&doc, &mut found);
+    assert!(!found.is_empty());
+    for reference in found {
+        let name = reference.trim_start_matches("#/components/schemas/");
+        assert!(doc["components"]["schemas"].get(name).is_some(), "{} isn't in the components", reference);
+    }
+}
+
+#[actix_web::test]
+async fn every_documented_route_is_served() {
+    let app = test::init_service(App::new().configure(configure)).await;
+    let id = uuid::Uuid::new_v4().to_string();
+    for (method, path, ..) in ROUTES {
+        let uri = path.replace("{id}", &id).replace("{participant_id}", &id).replace("{item_id}", &id);
+        let req = test::TestRequest::default()
+            .method(Method::from_bytes(method.to_uppercase().as_bytes()).unwrap())
+            .uri(&uri)
+            .to_request();
+        let status = test::call_service(&app, req).await.status();
+        // Without a token or a body every handler turns the request down,
+        // but a route that isn't registered answers 404 or 405
+        assert_ne!(status, StatusCode::NOT_FOUND, "{} {}", method, uri);
+        assert_ne!(status, StatusCode::METHOD_NOT_ALLOWED, "{} {}", method, uri);
+    }
+}
+
+#[actix_web::test]
+async fn the_served_document_is_the_api_doc() {
+    let app = test::init_service(App::new().configure(configure)).await;
+    let req = test::TestRequest::get().uri("/api/openapi.json").to_request();
+    let served: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(served, doc());
+}
@@ -0,0 +1,169 @@
+mod common;
+
+use common::{bill_with, id, money};
+use whati8::app::Assignment;
+use whati8::bill::{Bill, BillItem};
+use whati8::ops::BillOp;
+use whati8::quick_entry::{match_participant, parse_line, parse_quick_entry, quick_entry_ops, QuickEntry, QuickTax};
+
+fn item(line: &str, bill: &Bill) -> BillItem {
+    match parse_line(line, bill) {
+        Ok(QuickEntry::Item(item)) => item,
+        entry => panic!("expected an item from '{}', got {:?}", line, entry),
+    }
+}
+
+fn matched<'a>(bill: &'a Bill, name: &str) -> Result<&'a str, String> {
+    match_participant(bill, name).map(|p| p.name.as_str())
+}
+
+#[test]
+fn item_with_a_price_and_people() {
+    let bill = bill_with(&["Alice", "Bob", "Carol"]);
+    let pizza = item("Pizza Margherita 450 @alice @bob", &bill);
+    assert_eq!(pizza.name, "Pizza Margherita");
+    assert_eq!(pizza.price, money("450"));
+    assert_eq!(pizza.quantity, 1);
+    assert_eq!(pizza.assignment, Assignment::Selected);
+    let mut expected = vec![id(&bill, "Alice"), id(&bill, "Bob")];
+    expected.sort();
+    assert_eq!(pizza.participants, expected);
+}
+
+#[test]
+fn everyone_and_everyone_but() {
+    let bill = bill_with(&["Alice", "Bob", "Carol"]);
+    assert_eq!(item("Fries 120 @all", &bill).assignment, Assignment::Everyone);
+    assert_eq!(item("Fries 120 @Everyone", &bill).assignment, Assignment::Everyone);
+    assert_eq!(
+        item("Beer 300 @all -@carol", &bill).assignment,
+        Assignment::EveryoneExcept(vec![id(&bill, "Carol")])
+    );
+    assert_eq!(
+        item("Beer 300 -@carol", &bill).assignment,
+        Assignment::EveryoneExcept(vec![id(&bill, "Carol")])
+    );
+    assert_eq!(
+        parse_line("Beer 300 @alice -@carol", &bill),
+        Err(String::from("use either @names or @all with -@names, not both"))
+    );
+}
+
+#[test]
+fn quantity_in_every_form() {
+    let bill = bill_with(&["Alice"]);
+    for line in ["2x Beer 300", "2X Beer 300", "2 x Beer 300", "x2 Beer 300", "Beer x2 300", "Beer 300 2x"] {
+        let beer = item(line, &bill);
+        assert_eq!((beer.name.as_str(), beer.quantity, beer.price), ("Beer", 2, money("300")), "{}", line);
+    }
+    // The price is for all of them, not each
+    assert_eq!(item("3 x Naan 180", &bill).price, money("180"));
+    assert_eq!(item("0x Beer 300", &bill).name, "0x Beer");
+}
+
+#[test]
+fn only_the_last_number_is_the_price() {
+    let bill = bill_with(&["Alice"]);
+    let pizza = item("Pizza 12 inch 450", &bill);
+    assert_eq!((pizza.name.as_str(), pizza.price), ("Pizza 12 inch", money("450")));
+    let soda = item("7 Up 40", &bill);
+    assert_eq!((soda.name.as_str(), soda.price), ("7 Up", money("40")));
+    assert_eq!(item("Biryani ₹1,250.50", &bill).price, money("1250.50"));
+}
+
+#[test]
+fn items_that_cant_be_read() {
+    let bill = bill_with(&["Alice"]);
+    assert_eq!(parse_line("   ", &bill), Err(String::from("the line is empty")));
+    assert_eq!(parse_line("Pizza @alice", &bill), Err(String::from("the item needs a price")));
+    assert_eq!(parse_line("2x @alice", &bill), Err(String::from("the item needs a name")));
+    assert_eq!(parse_line("Pizza 450 @zed", &bill), Err(String::from("nobody called 'zed' is on the bill")));
+    assert_eq!(parse_line("Pizza 450 @", &bill), Err(String::from("@ needs a name after it")));
+}
+
+#[test]
+fn tax_lines() {
+    let bill = bill_with(&[]);
+    assert_eq!(parse_line("Tax 18%", &bill), Ok(QuickEntry::Tax(QuickTax::Percent(money("18")))));
+    assert_eq!(parse_line("CGST: 2.5%", &bill), Ok(QuickEntry::Tax(QuickTax::Percent(money("2.5")))));
+    assert_eq!(parse_line("gst ₹120", &bill), Ok(QuickEntry::Tax(QuickTax::Amount(money("120")))));
+    assert_eq!(parse_line("Tax", &bill), Err(String::from("write tax as 'Tax 18%' or 'Tax 120'")));
+    assert_eq!(parse_line("Tax 5 %", &bill), Err(String::from("write tax as 'Tax 18%' or 'Tax 120'")));
+    assert_eq!(parse_line("VAT lots%", &bill), Err(String::from("'lots%' isn't a percentage")));
+    assert_eq!(parse_line("Tax -5", &bill), Err(String::from("tax can't be negative")));
+}
+
+#[test]
+fn an_exact_name_comes_first() {
+    let bill = bill_with(&["Alice", "Al", "Mary Jane"]);
+    assert_eq!(matched(&bill, "al"), Ok("Al"));
+    assert_eq!(matched(&bill, "ALICE"), Ok("Alice"));
+    assert_eq!(matched(&bill, "mary_jane"), Ok("Mary Jane"));
+    assert_eq!(matched(&bill, "maryjane"), Ok("Mary Jane"));
+}
+
+#[test]
+fn then_the_only_name_it_starts() {
+    let bill = bill_with(&["Bob", "Bobby", "Carol"]);
+    // "bob" is within a typo of "bobb", but "Bobby" starts with it
+    assert_eq!(matched(&bill, "bobb"), Ok("Bobby"));
+    assert_eq!(matched(&bill, "car"), Ok("Carol"));
+}
+
+#[test]
+fn then_the_only_name_within_a_typo_or_two() {
+    let bill = bill_with(&["Alice", "Ravi", "Rani"]);
+    assert_eq!(matched(&bill, "alcie"), Ok("Alice"));
+    assert_eq!(matched(&bill, "ravy"), Ok("Ravi"));
+    // Short names only get one typo
+    assert_eq!(matched(&bill, "rxfi"), Err(String::from("nobody called 'rxfi' is on the bill")));
+}
+
+#[test]
+fn names_that_could_be_several_people_are_refused() {
+    let bill = bill_with(&["Sam", "Samantha", "Samir", "Ravi", "Rani"]);
+    assert_eq!(matched(&bill, "sam"), Ok("Sam"));
+    assert_eq!(matched(&bill, "sa"), Err(String::from("@sa could be Sam or Samantha or Samir")));
+    assert_eq!(matched(&bill, "raxi"), Err(String::from("@raxi could be Ravi or Rani")));
+    assert_eq!(
+        parse_line("Pizza 450 @sa", &bill),
+        Err(String::from("@sa could be Sam or Samantha or Samir"))
+    );
+}
+
+#[test]
+fn every_line_is_read_and_blank_ones_skipped() {
+    let bill = bill_with(&["Alice"]);
+    let lines = parse_quick_entry("Pizza 450 @alice\n\n  Soup\nTax 5%\n", &bill);
+    let texts: Vec<&str> = lines.iter().map(|line| line.text.as_str()).collect();
+    assert_eq!(texts, vec!["Pizza 450 @alice", "Soup", "Tax 5%"]);
+    assert!(lines[0].entry.is_ok());
+    assert_eq!(lines[1].entry, Err(String::from("the item needs a price")));
+    assert!(lines[2].entry.is_ok());
+}
+
+#[test]
+fn tax_lines_add_to_the_tax_on_the_bill() {
+    let mut bill = bill_with(&["Alice"]);
+    bill.total_tax = money("10");
+    let pizza = item("Pizza 450", &bill);
+    bill.items.push(pizza);
+    let lines = parse_quick_entry("Beer 50\nCGST 2.5%\nSGST 2.5%\nCess 1.25\nNot a line", &bill);
+
+    let ops = quick_entry_ops(&bill, &lines);
+    assert_eq!(ops.len(), 2);
+    assert!(matches!(&ops[0], BillOp::AddItem(beer) if beer.name == "Beer"));
+    match &ops[1] {
+        // 10 already on the bill, 2.5% twice of the 500 of items and 1.25
+        BillOp::UpdateSettings(settings) => assert_eq!(settings.total_tax, Some(money("36.25"))),
+        op => panic!("expected the settings, got {:?}", op),
+    }
+}
+
+#[test]
+fn no_tax_lines_leave_the_tax_alone() {
+    let mut bill = bill_with(&["Alice"]);
+    bill.total_tax = money("10");
+    let ops = quick_entry_ops(&bill, &parse_quick_entry("Beer 50", &bill));
+    assert!(ops.iter().all(|op| matches!(op, BillOp::AddItem(_))));
+}
@@ -0,0 +1,272 @@
+mod common;
+
+use common::money;
+use rust_decimal::Decimal;
+use uuid::Uuid;
+use whati8::bill::Bill;
+use whati8::ops::BillOp;
+use whati8::receipt::{parse_receipt, ReceiptCharge, ReceiptItem};
+
+fn item(name: &str, quantity: u32, price: &str) -> ReceiptItem {
+    ReceiptItem { name: name.to_string(), quantity, price: money(price) }
+}
+
+fn labels(charges: &[ReceiptCharge]) -> Vec<&str> {
+    charges.iter().map(|c| c.label.as_str()).collect()
+}
+
+#[test]
+fn restaurant_bill_with_qty_rate_and_amount_columns() {
+    let receipt = parse_receipt(
+        "
+        SPICE GARDEN RESTAURANT
+        MG Road, Bengaluru 560001
+        GSTIN: 29ABCDE1234F1Z5
+        Bill No: 1234   Table: 7
+        Date: 12/03/2024 21:15
+        ----------------------------------
+        Item              Qty   Rate   Amount
+        ----------------------------------
+        1 Paneer Tikka       1   280.00   280.00
+        2 Butter Naan        4    60.00   240.00
+        3 Dal Makhani        1   320.00   320.00
+        4 Sweet Lime Soda    2    90.00   180.00
+        ----------------------------------
+        Sub Total                      1,020.00
+        Service Charge @ 5%              51.00
+        CGST @ 2.5%                      25.50
+        SGST @ 2.5%                      25.50
+        ----------------------------------
+        Grand Total                    1122.00
+        Paid by UPI                    1122.00
+        Thank you! Visit again
+        ",
+    );
+
+    assert_eq!(
+        receipt.items,
+        vec![
+            item("Paneer Tikka", 1, "280"),
+            item("Butter Naan", 4, "240"),
+            item("Dal Makhani", 1, "320"),
+            item("Sweet Lime Soda", 2, "180"),
+        ]
+    );
+    assert_eq!(labels(&receipt.charges), vec!["Service Charge @ 5%", "CGST @ 2.5%", "SGST @ 2.5%"]);
+    assert_eq!(receipt.tax(), money("102"));
+    assert_eq!(receipt.subtotal, Some(money("1020")));
+    assert_eq!(receipt.grand_total, Some(money("1122")));
+    assert_eq!(receipt.difference(), Some(Decimal::ZERO));
+    // Not charged, but shown in case they were
+    assert_eq!(receipt.unread, vec!["Bill No: 1234   Table: 7", "Paid by UPI                    1122.00"]);
+}
+
+#[test]
+fn restaurant_bill_with_rounding_and_a_discount() {
+    let receipt = parse_receipt(
+        "
+        Chicken 65            250.00
+        Veg Fried Rice   2    440.00
+        Fresh Lime Soda  90.00  180.00
+        Total Qty: 5
+        Discount 10%          (87.00)
+        CGST 2.5%              19.58
+        SGST 2.5%              19.58
+        Round Off              -0.16
+        Net Amount               822
+        ",
+    );
+
+    assert_eq!(
+        receipt.items,
+        vec![
+            item("Chicken 65", 1, "250"),
+            item("Veg Fried Rice", 2, "440"),
+            item("Fresh Lime Soda", 2, "180"),
+        ]
+    );
+    assert_eq!(receipt.discount(), money("87"));
+    assert_eq!(labels(&receipt.charges), vec!["CGST 2.5%", "SGST 2.5%", "Round Off"]);
+    assert_eq!(receipt.grand_total, Some(money("822")));
+    assert_eq!(receipt.difference(), Some(Decimal::ZERO));
+}
+
+#[test]
+fn swiggy_style_order_summary() {
+    let receipt = parse_receipt(
+        "
+        Order #123456789
+        Behrouz Biryani
+        2 x Chicken Dum Biryani   ₹ 798
+        1 x Gulab Jamun (2 pcs)   ₹ 99
+        Item Total   ₹ 897
+        Delivery partner fee   ₹ 45
+        Packaging charges   ₹ 20
+        Platform fee   ₹ 5
+        GST and restaurant charges   ₹ 48.35
+        Delivery Tip   ₹ 30
+        Discount   -₹ 150
+        Grand Total   ₹ 895.35
+        ",
+    );
+
+    assert_eq!(
+        receipt.items,
+        vec![item("Chicken Dum Biryani", 2, "798"), item("Gulab Jamun (2 pcs)", 1, "99")]
+    );
+    assert_eq!(
+        labels(&receipt.charges),
+        vec!["Delivery partner fee", "Packaging charges", "Platform fee", "GST and restaurant charges"]
+    );
+    assert_eq!(receipt.tip, money("30"));
+    assert_eq!(receipt.discount(), money("150"));
+    assert_eq!(receipt.subtotal, Some(money("897")));
+    assert_eq!(receipt.difference(), Some(Decimal::ZERO));
+}
+
+#[test]
+fn delivery_order_with_packing_charges_and_table_water() {
+    let receipt = parse_receipt(
+        "
+        Order ID: 5551234
+        Ordered on 14 Mar, 8:42 PM
+        Paneer Butter Masala x 1   ₹280.00
+        Garlic Naan x 3            ₹150.00
+        Table Water                ₹40.00
+        Item Total                 ₹470.00
+        Order Packing Charges      ₹25.00
+        Delivery Fee               ₹35.00
+        Taxes                      ₹23.50
+        Coupon SAVE50              -₹50.00
+        Order Total                ₹503.50
+        ",
+    );
+
+    assert_eq!(receipt.items, vec![item("Paneer Butter Masala", 1, "280"), item("Garlic Naan", 3, "150")]);
+    assert_eq!(labels(&receipt.charges), vec!["Order Packing Charges", "Delivery Fee", "Taxes"]);
+    assert_eq!(receipt.discount(), money("50"));
+    assert_eq!(receipt.grand_total, Some(money("503.50")));
+    // "Table" reads like the table number, so the water is left for the user
+    // to check, and the total is off by its price
+    assert_eq!(receipt.unread, vec!["Table Water                ₹40.00"]);
+    assert_eq!(receipt.difference(), Some(money("40")));
+}
+
+#[test]
+fn zomato_style_order_with_prices_on_their_own_lines() {
+    let receipt = parse_receipt(
+        "
+        Veg Manchurian x 1   ₹220.00
+        Hakka Noodles x 2    ₹360.00
+        Chilli Paneer (x1)
+        ₹240.00
+        Taxes                ₹41.00
+        Delivery charge      ₹30.00
+        Total                ₹891.00
+        ",
+    );
+
+    assert_eq!(
+        receipt.items,
+        vec![
+            item("Veg Manchurian", 1, "220"),
+            item("Hakka Noodles", 2, "360"),
+            item("Chilli Paneer", 1, "240"),
+        ]
+    );
+    assert_eq!(receipt.tax(), money("71"));
+    assert_eq!(receipt.grand_total, Some(money("891")));
+    assert_eq!(receipt.difference(), Some(Decimal::ZERO));
+}
+
+#[test]
+fn cafe_bill_with_rupee_prefixes_and_vat() {
+    let receipt = parse_receipt(
+        "
+        Cappuccino 2 300.00
+        Blueberry Muffin Rs.150.00
+        VAT 5%  Rs. 22.50
+        Net Amount Rs. 472.50/-
+        ",
+    );
+
+    assert_eq!(receipt.items, vec![item("Cappuccino", 2, "300"), item("Blueberry Muffin", 1, "150")]);
+    assert_eq!(receipt.tax(), money("22.50"));
+    assert_eq!(receipt.grand_total, Some(money("472.50")));
+}
+
+#[test]
+fn totals_that_dont_add_up_are_reported() {
+    let receipt = parse_receipt(
+        "
+        Masala Dosa 120.00
+        Filter Coffee 2 80.00
+        Grand Total 260.00
+        ",
+    );
+
+    assert_eq!(receipt.difference(), Some(money("60")));
+}
+
+#[test]
+fn applying_fills_items_and_tax_fields() {
+    let bill = Bill::empty(Uuid::new_v4());
+    let receipt = parse_receipt(
+        "
+        2 x Chicken Dum Biryani   ₹ 798
+        GST   ₹ 39.90
+        Delivery fee   ₹ 25
+        Coupon   -₹ 100
+        Grand Total   ₹ 762.90
+        ",
+    );
+
+    let ops = receipt.to_ops(&bill);
+    assert_eq!(ops.len(), 2);
+    match &ops[0] {
+        BillOp::AddItem(added) => {
+            assert_eq!(added.name, "Chicken Dum Biryani");
+            assert_eq!(added.quantity, 2);
+            assert_eq!(added.price, money("798"));
+            assert!(added.participants.is_empty());
+        }
+        op => panic!("expected an item, got {:?}", op),
+    }
+    match &ops[1] {
+        BillOp::UpdateSettings(settings) => {
+            assert_eq!(settings.total_tax, Some(money("64.90")));
+            assert_eq!(settings.discount, Some(money("100")));
+            assert_eq!(settings.receipt_total, Some(Some(money("762.90"))));
+        }
+        op => panic!("expected the settings, got {:?}", op),
+    }
+}
+
+#[test]
+fn applying_adds_to_the_tax_tip_and_discount_already_on_the_bill() {
+    let mut bill = Bill::empty(Uuid::new_v4());
+    bill.total_tax = money("10");
+    bill.tip = money("20");
+    bill.discount = money("5");
+    bill.receipt_total = Some(money("100"));
+    let receipt = parse_receipt(
+        "
+        Masala Dosa 120.00
+        GST 6.00
+        Tip 15.00
+        Discount -20.00
+        Grand Total 121.00
+        ",
+    );
+
+    match receipt.to_ops(&bill).last() {
+        Some(BillOp::UpdateSettings(settings)) => {
+            assert_eq!(settings.total_tax, Some(money("16")));
+            assert_eq!(settings.tip, Some(money("35")));
+            assert_eq!(settings.discount, Some(money("25")));
+            // Only the grand total is replaced
+            assert_eq!(settings.receipt_total, Some(Some(money("121"))));
+        }
+        op => panic!("expected the settings, got {:?}", op),
+    }
+}
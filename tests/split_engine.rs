@@ -0,0 +1,299 @@
+mod common;
+
+use std::collections::HashMap;
+use common::{bill_with, id, item, money};
+use rust_decimal::Decimal;
+use whati8::app::{Assignment, TreatMode, UnassignedPolicy};
+use whati8::bill::{Bill, BillItem};
+use whati8::split::{compute, SplitResult};
+
+/// What everyone owes, by name.
+fn shares(result: &SplitResult) -> HashMap<&str, Decimal> {
+    result.shares.iter().map(|s| (s.name.as_str(), s.share)).collect()
+}
+
+/// An item only the people named share.
+fn item_for(bill: &Bill, name: &str, price: &str, people: &[&str]) -> BillItem {
+    let mut item = item(name, price);
+    item.assignment = Assignment::Selected;
+    item.participants = people.iter().map(|p| id(bill, p)).collect();
+    item
+}
+
+#[test]
+fn overflow_above_a_cap_is_spread_to_the_last_paisa() {
+    let mut bill = bill_with(&["Alice", "Bob", "Carol", "Dan"]);
+    bill.items = vec![item("Thali", "400")];
+    bill.caps.insert(id(&bill, "Alice"), money("99"));
+
+    let result = compute(&bill);
+    let shares = shares(&result);
+    assert_eq!(shares["Alice"], money("99"));
+    // 1.00 over three people: the last one takes the leftover paisa
+    let mut others = vec![shares["Bob"], shares["Carol"], shares["Dan"]];
+    others.sort();
+    assert_eq!(others, vec![money("100.33"), money("100.33"), money("100.34")]);
+    assert_eq!(result.total, money("400"));
+    assert!(!result.summary.contains("CAPS NOT MET"), "{}", result.summary);
+}
+
+#[test]
+fn overflow_stops_at_everyone_elses_cap() {
+    let mut bill = bill_with(&["Alice", "Bob", "Carol"]);
+    bill.items = vec![
+        item_for(&bill, "Steak", "200", &["Alice"]),
+        item_for(&bill, "Salad", "50", &["Bob"]),
+        item_for(&bill, "Soup", "80", &["Carol"]),
+    ];
+    bill.bill_cap = Some(money("110"));
+
+    let result = compute(&bill);
+    let shares = shares(&result);
+    // Alice's 90 over goes to Bob until he reaches the cap as well, and the
+    // rest to Carol
+    assert_eq!(shares["Alice"], money("110"));
+    assert_eq!(shares["Bob"] + shares["Carol"], money("220"));
+    assert!(shares["Bob"] <= money("110") && shares["Carol"] <= money("110"));
+    assert_eq!(result.total, money("330"));
+    assert!(!result.summary.contains("CAPS NOT MET"));
+}
+
+#[test]
+fn caps_that_cant_all_be_met_are_reported() {
+    let mut bill = bill_with(&["Alice", "Bob", "Carol"]);
+    bill.items = vec![
+        item_for(&bill, "Steak", "200", &["Alice"]),
+        item_for(&bill, "Salad", "100", &["Bob"]),
+        item_for(&bill, "Soup", "100", &["Carol"]),
+    ];
+    bill.bill_cap = Some(money("100"));
+
+    let result = compute(&bill);
+    assert_eq!(shares(&result)["Alice"], money("200"));
+    assert_eq!(result.total, money("400"));
+    assert!(result.summary.contains("CAPS NOT MET"), "{}", result.summary);
+}
+
+#[test]
+fn the_sponsor_takes_all_the_overflow() {
+    let mut bill = bill_with(&["Alice", "Bob", "Carol"]);
+    bill.items = vec![item("Thali", "300")];
+    bill.caps.insert(id(&bill, "Alice"), money("50"));
+    bill.bill_cap = Some(money("100"));
+    bill.sponsor = Some(id(&bill, "Carol"));
+
+    let shares = shares(&compute(&bill));
+    assert_eq!(shares["Alice"], money("50"));
+    assert_eq!(shares["Bob"], money("100"));
+    // The bill-wide cap doesn't hold the sponsor back
+    assert_eq!(shares["Carol"], money("150"));
+}
+
+#[test]
+fn treated_participants_take_none_of_the_overflow() {
+    let mut bill = bill_with(&["Alice", "Bob", "Carol"]);
+    bill.items = vec![item("Thali", "300")];
+    bill.treated = vec![id(&bill, "Alice")];
+    bill.treat_mode = TreatMode::Equal;
+    bill.caps.insert(id(&bill, "Bob"), money("120"));
+
+    let result = compute(&bill);
+    let shares = shares(&result);
+    // Alice's 100 is split between Bob and Carol, then Bob's 30 over his cap
+    // goes to Carol
+    assert_eq!(shares["Alice"], Decimal::ZERO);
+    assert_eq!(shares["Bob"], money("120"));
+    assert_eq!(shares["Carol"], money("180"));
+    assert_eq!(result.total, money("300"));
+}
+
+#[test]
+fn weights_divide_shared_items_and_tax() {
+    let mut bill = bill_with(&["Alice", "Bob", "Carol"]);
+    bill.participants[0].weight = money("2");
+    bill.items = vec![item("Platter", "400"), item_for(&bill, "Soda", "60", &["Alice", "Bob"])];
+    bill.total_tax = money("40");
+
+    let result = compute(&bill);
+    let shares = shares(&result);
+    // Alice counts twice: half the platter and tax, and two thirds of the soda
+    assert_eq!(shares["Alice"], money("260"));
+    assert_eq!(shares["Bob"], money("130"));
+    assert_eq!(shares["Carol"], money("110"));
+    assert_eq!(result.total, money("500"));
+    assert!(result.summary.contains("- Name: Alice (x2 share)"), "{}", result.summary);
+}
+
+#[test]
+fn a_weight_of_zero_pays_nothing() {
+    let mut bill = bill_with(&["Alice", "Bob"]);
+    bill.participants[0].weight = Decimal::ZERO;
+    bill.items = vec![item("Pizza", "300")];
+    bill.total_tax = money("30");
+
+    let shares = shares(&compute(&bill));
+    assert_eq!(shares["Alice"], Decimal::ZERO);
+    assert_eq!(shares["Bob"], money("330"));
+}
+
+#[test]
+fn all_zero_weights_fall_back_to_a_head_count() {
+    let mut bill = bill_with(&["Alice", "Bob", "Carol"]);
+    for p in bill.participants.iter_mut() {
+        p.weight = Decimal::ZERO;
+    }
+    bill.items = vec![item("Thali", "300")];
+
+    let shares = shares(&compute(&bill));
+    assert_eq!(shares["Alice"], money("100"));
+    assert_eq!(shares["Bob"], money("100"));
+    assert_eq!(shares["Carol"], money("100"));
+}
+
+/// Alice's steak, Bob's salad and Carol's soup, with Alice treated.
+fn treat_for_alice(mode: TreatMode) -> Bill {
+    let mut bill = bill_with(&["Alice", "Bob", "Carol"]);
+    bill.items = vec![
+        item_for(&bill, "Steak", "300", &["Alice"]),
+        item_for(&bill, "Salad", "100", &["Bob"]),
+        item_for(&bill, "Soup", "200", &["Carol"]),
+    ];
+    bill.treated = vec![id(&bill, "Alice")];
+    bill.treat_mode = mode;
+    bill
+}
+
+#[test]
+fn an_equal_treat_is_split_evenly() {
+    let result = compute(&treat_for_alice(TreatMode::Equal));
+    let shares = shares(&result);
+    assert_eq!(shares["Alice"], Decimal::ZERO);
+    assert_eq!(shares["Bob"], money("250"));
+    assert_eq!(shares["Carol"], money("350"));
+    assert_eq!(result.total, money("600"));
+    assert_eq!(result.shares[0].breakdown.treat, money("-300"));
+    assert!(result.summary.contains("Treated: Alice (their share is split equally"), "{}", result.summary);
+}
+
+#[test]
+fn an_equal_treat_follows_weights() {
+    let mut bill = treat_for_alice(TreatMode::Equal);
+    bill.participants[1].weight = money("2");
+
+    let shares = shares(&compute(&bill));
+    assert_eq!(shares["Bob"], money("300"));
+    assert_eq!(shares["Carol"], money("300"));
+}
+
+#[test]
+fn a_proportional_treat_follows_what_each_person_ordered() {
+    let result = compute(&treat_for_alice(TreatMode::Proportional));
+    let shares = shares(&result);
+    // Carol ordered twice what Bob did, so she covers twice as much
+    assert_eq!(shares["Alice"], Decimal::ZERO);
+    assert_eq!(shares["Bob"], money("200"));
+    assert_eq!(shares["Carol"], money("400"));
+    assert_eq!(result.total, money("600"));
+    assert!(result.summary.contains("split in proportion to what each person ordered"), "{}", result.summary);
+}
+
+#[test]
+fn a_proportional_treat_nobody_else_ordered_for_is_split_evenly() {
+    let mut bill = bill_with(&["Alice", "Bob", "Carol"]);
+    bill.items = vec![item_for(&bill, "Cake", "300", &["Alice"])];
+    bill.treated = vec![id(&bill, "Alice")];
+    bill.treat_mode = TreatMode::Proportional;
+
+    let shares = shares(&compute(&bill));
+    assert_eq!(shares["Alice"], Decimal::ZERO);
+    assert_eq!(shares["Bob"], money("150"));
+    assert_eq!(shares["Carol"], money("150"));
+}
+
+/// Naan for Alice, and a tikka nobody was put down for.
+fn unassigned_tikka(policy: UnassignedPolicy) -> Bill {
+    let mut bill = bill_with(&["Alice", "Bob", "Carol"]);
+    bill.items = vec![item_for(&bill, "Naan", "60", &["Alice"]), item_for(&bill, "Tikka", "300", &[])];
+    bill.unassigned_policy = policy;
+    bill
+}
+
+#[test]
+fn unassigned_items_block_the_split_by_default() {
+    let bill = unassigned_tikka(UnassignedPolicy::default());
+    assert_eq!(bill.unassigned_policy, UnassignedPolicy::Block);
+
+    let result = compute(&bill);
+    assert!(result.blocked);
+    assert_eq!(result.total, Decimal::ZERO);
+    assert!(result.shares.iter().all(|s| s.share.is_zero()));
+    assert!(result.summary.contains("  - Tikka: ₹300"), "{}", result.summary);
+    assert!(result.summary.contains("SPLIT BLOCKED"), "{}", result.summary);
+}
+
+#[test]
+fn unassigned_items_can_be_split_among_everyone() {
+    let result = compute(&unassigned_tikka(UnassignedPolicy::SplitAmongEveryone));
+    let shares = shares(&result);
+    assert!(!result.blocked);
+    assert_eq!(shares["Alice"], money("160"));
+    assert_eq!(shares["Bob"], money("100"));
+    assert_eq!(shares["Carol"], money("100"));
+    assert!(result.summary.contains("Unassigned Items (Split equally among everyone)"), "{}", result.summary);
+}
+
+#[test]
+fn unassigned_items_can_be_charged_to_the_payer() {
+    let mut bill = unassigned_tikka(UnassignedPolicy::ChargePayer);
+    bill.participants[1].payer = true;
+
+    let result = compute(&bill);
+    let shares = shares(&result);
+    assert!(!result.blocked);
+    assert_eq!(shares["Alice"], money("60"));
+    assert_eq!(shares["Bob"], money("300"));
+    assert_eq!(shares["Carol"], Decimal::ZERO);
+    assert!(result.summary.contains("Unassigned Items (Charged to the payer)"), "{}", result.summary);
+}
+
+#[test]
+fn unassigned_items_with_no_payer_to_charge_are_split_among_everyone() {
+    let result = compute(&unassigned_tikka(UnassignedPolicy::ChargePayer));
+    let shares = shares(&result);
+    assert_eq!(shares["Alice"], money("160"));
+    assert_eq!(shares["Bob"], money("100"));
+    assert_eq!(shares["Carol"], money("100"));
+    assert!(result.summary.contains("Nobody is marked as the payer"), "{}", result.summary);
+}
+
+/// ₹550 of food and tax, against what the receipt says.
+fn receipt_of(total: &str) -> Bill {
+    let mut bill = bill_with(&["Alice", "Bob"]);
+    bill.items = vec![item("Biryani", "500")];
+    bill.total_tax = money("50");
+    bill.receipt_total = Some(money(total));
+    bill
+}
+
+#[test]
+fn a_receipt_that_matches_has_no_difference() {
+    let result = compute(&receipt_of("550"));
+    assert_eq!(result.total, money("550"));
+    assert!(result.summary.contains("Receipt Total: ₹550"), "{}", result.summary);
+    assert!(!result.summary.contains("Unreconciled Difference"), "{}", result.summary);
+}
+
+#[test]
+fn a_receipt_higher_than_the_bill_means_something_is_missing() {
+    let result = compute(&receipt_of("580"));
+    // The shares stay with what was entered, the summary flags the gap
+    assert_eq!(result.total, money("550"));
+    assert!(result.summary.contains("Unreconciled Difference: ₹30"), "{}", result.summary);
+}
+
+#[test]
+fn a_receipt_lower_than_the_bill_means_a_discount_is_missing() {
+    let result = compute(&receipt_of("520"));
+    assert_eq!(result.total, money("550"));
+    assert!(result.summary.contains("Unreconciled Difference: ₹-30"), "{}", result.summary);
+}